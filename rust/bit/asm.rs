@@ -0,0 +1,863 @@
+use std::{
+	collections::HashMap,
+	fmt::Write,
+	path::PathBuf,
+	process::{Command, Output, Stdio},
+	time::{Duration, Instant},
+};
+
+use super::*;
+
+/// Registers used for expression temporaries.
+///
+/// These are callee-saved in the System V ABI, so temporaries survive
+/// calls. Temporaries work as a stack: when all registers are in use, the
+/// oldest value is spilled to the machine stack and restored when the
+/// newer value is released.
+const REGS: [&str; 5] = ["%rbx", "%r12", "%r13", "%r14", "%r15"];
+
+/// Registers for the first function arguments.
+const ARGS: [&str; 6] = ["%rdi", "%rsi", "%rdx", "%rcx", "%r8", "%r9"];
+
+/// Size of the saved registers between `%rbp` and the local variables.
+const SAVED: i64 = 8 * REGS.len() as i64;
+
+/// Generates x86-64 GNU assembly for the integer and print subset of
+/// [`Code`], following the System V ABI and using `printf` for output.
+pub struct Builder<'a> {
+	names: NameSet<'a>,
+	/// Stack slots of the variables and labels of the functions in scope.
+	scopes: Scopes<'a, i64, &'a str>,
+	checker: Checker<'a>,
+	labels: usize,
+	strings: HashMap<String, String>,
+	data: String,
+	funcs: Vec<String>,
+	func: FuncState,
+}
+
+/// Code generation state for the current function.
+#[derive(Default)]
+struct FuncState {
+	code: String,
+	locals: i64,
+	depth: usize,
+	ret: Option<Kind>,
+	ret_label: String,
+	loops: Vec<(String, String)>,
+}
+
+impl<'a> Builder<'a> {
+	pub fn new(store: &'a Store) -> Self {
+		let mut out = Self {
+			names: NameSet::new(store),
+			scopes: Scopes::new(),
+			checker: Checker::new(),
+			labels: 0,
+			strings: Default::default(),
+			data: String::new(),
+			funcs: Vec::new(),
+			func: Default::default(),
+		};
+		out.func.ret_label = out.label();
+		out
+	}
+
+	/// Finish the `main` function and return the program.
+	pub fn build(&mut self) -> Runner {
+		while self.func.depth > 0 {
+			self.free();
+		}
+		self.emit("xor %eax, %eax");
+		let main = std::mem::take(&mut self.func);
+		let main = self.finish_func("main", main);
+
+		let mut code = String::new();
+		code.push_str("\t.text\n");
+		for it in self.funcs.iter() {
+			code.push('\n');
+			code.push_str(it);
+		}
+		code.push_str("\n\t.globl main\n");
+		code.push_str(&main);
+
+		if !self.data.is_empty() {
+			code.push_str("\n\t.section .rodata\n");
+			code.push_str(&self.data);
+		}
+		code.push_str("\n\t.section .note.GNU-stack,\"\",@progbits\n");
+
+		let mut program = Runner::new();
+		program.append(code);
+		program
+	}
+
+	fn finish_func(&self, name: &str, func: FuncState) -> String {
+		// after the return address, `%rbp`, and the saved registers the
+		// stack is misaligned by 8, so round the locals to fix that
+		let mut size = func.locals * 8;
+		if size % 16 == 0 {
+			size += 8;
+		}
+
+		let mut code = String::new();
+		let _ = writeln!(code, "{name}:");
+		code.push_str("\tpush %rbp\n\tmov %rsp, %rbp\n");
+		for it in REGS {
+			let _ = writeln!(code, "\tpush {it}");
+		}
+		let _ = writeln!(code, "\tsub ${size}, %rsp");
+		code.push_str(&func.code);
+		let _ = writeln!(code, "{}:", func.ret_label);
+		let _ = writeln!(code, "\tlea -{SAVED}(%rbp), %rsp");
+		for it in REGS.iter().rev() {
+			let _ = writeln!(code, "\tpop {it}");
+		}
+		code.push_str("\tpop %rbp\n\tret\n");
+		code
+	}
+
+	fn emit<T: AsRef<str>>(&mut self, line: T) {
+		let _ = writeln!(self.func.code, "\t{}", line.as_ref());
+	}
+
+	fn label(&mut self) -> String {
+		self.labels += 1;
+		format!(".L{}", self.labels)
+	}
+
+	fn place(&mut self, label: &str) {
+		let _ = writeln!(self.func.code, "{label}:");
+	}
+
+	/// Return the label for a string constant.
+	fn string(&mut self, value: &str) -> Result<String> {
+		if value.contains('\0') {
+			Err("strings with `\\0` are not supported by the assembly backend")?;
+		}
+		if let Some(label) = self.strings.get(value) {
+			return Ok(label.clone());
+		}
+
+		let label = format!(".LS{}", self.strings.len());
+		let mut data = format!("{label}:\n\t.asciz \"");
+		for b in value.bytes() {
+			match b {
+				b'"' | b'\\' => {
+					data.push('\\');
+					data.push(b as char);
+				}
+				0x20..=0x7E => data.push(b as char),
+				_ => {
+					let _ = write!(data, "\\{b:03o}");
+				}
+			}
+		}
+		data.push_str("\"\n");
+		self.data.push_str(&data);
+		self.strings.insert(value.to_string(), label.clone());
+		Ok(label)
+	}
+
+	/// Allocate a temporary register, spilling if necessary.
+	fn alloc(&mut self) -> &'static str {
+		let reg = REGS[self.func.depth % REGS.len()];
+		if self.func.depth >= REGS.len() {
+			self.emit(format!("push {reg}"));
+		}
+		self.func.depth += 1;
+		reg
+	}
+
+	/// Release the top temporary register, restoring any spilled value.
+	fn free(&mut self) {
+		self.func.depth -= 1;
+		let reg = REGS[self.func.depth % REGS.len()];
+		if self.func.depth >= REGS.len() {
+			self.emit(format!("pop {reg}"));
+		}
+	}
+
+	fn top(&self) -> &'static str {
+		REGS[(self.func.depth - 1) % REGS.len()]
+	}
+
+	/// Number of values spilled to the stack, which affects alignment.
+	fn spilled(&self) -> usize {
+		self.func.depth.saturating_sub(REGS.len())
+	}
+
+	/// Allocate a new stack slot and return its offset from `%rbp`.
+	fn slot(&mut self) -> i64 {
+		self.func.locals += 1;
+		-SAVED - 8 * self.func.locals
+	}
+
+	fn call(&mut self, func: &str) {
+		let align = self.spilled() % 2 == 1;
+		if align {
+			self.emit("sub $8, %rsp");
+		}
+		self.emit(format!("call {func}"));
+		if align {
+			self.emit("add $8, %rsp");
+		}
+	}
+
+	pub fn declare(&mut self, name: Sym<'a>, kind: Kind) -> i64 {
+		let slot = self.slot();
+		self.scopes.declare(name, slot, kind);
+		slot
+	}
+
+	pub fn declare_func(&mut self, def: &'a FuncDef<'a>) -> Result<&'a str> {
+		let names = &self.names;
+		self.scopes.declare_func(def, || names.resolve(names.unique(def.name)))
+	}
+
+	pub fn lookup(&self, name: Sym<'a>) -> Result<(i64, Kind)> {
+		self.scopes.lookup(name)
+	}
+
+	pub fn lookup_func(&self, name: Sym<'a>) -> Result<(&'a str, &'a FuncDef<'a>)> {
+		self.scopes.lookup_func(name)
+	}
+
+	fn generate_scope(&mut self, code: &'a [Code<'a>]) -> Result<()> {
+		self.scopes.push();
+		let result = self.generate_list(code);
+		self.scopes.pop();
+		result
+	}
+
+	fn generate_list(&mut self, code: &'a [Code<'a>]) -> Result<()> {
+		// functions are visible in the entire scope, so declare them first
+		for def in scope::functions(code) {
+			self.declare_func(def)?;
+		}
+		for it in code.iter() {
+			self.generate_stmt(it)?;
+		}
+		Ok(())
+	}
+
+	fn generate_stmt(&mut self, code: &'a Code<'a>) -> Result<()> {
+		if code.generate_asm(self)? != Kind::Void {
+			self.free();
+		}
+		Ok(())
+	}
+
+	fn generate_func(&mut self, def: &'a FuncDef<'a>) -> Result<()> {
+		let name = self.declare_func(def)?;
+		if def.args.len() > ARGS.len() {
+			Err(format!(
+				"`{}` has more than {} arguments",
+				def.name.as_str(),
+				ARGS.len()
+			))?;
+		}
+
+		let ret_label = self.label();
+		let outer = std::mem::replace(
+			&mut self.func,
+			FuncState {
+				ret: Some(def.ret),
+				ret_label,
+				..Default::default()
+			},
+		);
+		self.scopes.push_func();
+
+		let result = (|| -> Result<()> {
+			for (arg, reg) in def.args.iter().zip(ARGS) {
+				if !matches!(arg.kind, Kind::I64 | Kind::Bool | Kind::Str) {
+					Err(format!("unsupported argument type {:?}", arg.kind))?;
+				}
+				let slot = self.declare(arg.name, arg.kind);
+				self.emit(format!("mov {reg}, {slot}(%rbp)"));
+			}
+			self.generate_body(def.body)?;
+			self.emit("xor %eax, %eax");
+			Ok(())
+		})();
+
+		self.scopes.pop_func();
+		let func = std::mem::replace(&mut self.func, outer);
+		result?;
+
+		let code = self.finish_func(name, func);
+		self.funcs.push(code);
+		Ok(())
+	}
+
+	fn generate_body(&mut self, code: &'a Code<'a>) -> Result<()> {
+		match code.inner() {
+			Code::Block(list) => self.generate_scope(list),
+			_ => self.generate_scope(std::slice::from_ref(code)),
+		}
+	}
+
+	/// Generate a condition and jump to `label` if it is false.
+	fn generate_cond(&mut self, code: &'a Code<'a>, label: &str) -> Result<()> {
+		match code.generate_asm(self)? {
+			Kind::Bool | Kind::I64 => {}
+			kind => Err(format!("invalid condition of type {kind:?}"))?,
+		}
+		let reg = self.top();
+		self.emit(format!("test {reg}, {reg}"));
+		self.free();
+		self.emit(format!("jz {label}"));
+		Ok(())
+	}
+
+	fn generate_print(&mut self, args: &'a [Code<'a>]) -> Result<()> {
+		// evaluate all arguments before printing, like the C backend
+		let mut values = Vec::new();
+		for it in args.iter() {
+			if let Code::Str(str) = it.inner() {
+				values.push((Kind::Str, Err(self.string(str)?)));
+				continue;
+			}
+
+			let kind = it.generate_asm(self)?;
+			if kind == Kind::Void {
+				continue;
+			}
+			let slot = self.slot();
+			let reg = self.top();
+			self.emit(format!("mov {reg}, {slot}(%rbp)"));
+			self.free();
+			values.push((kind, Ok(slot)));
+		}
+
+		for (n, (kind, value)) in values.into_iter().enumerate() {
+			let sep = if n > 0 { " " } else { "" };
+			let fmt = match kind {
+				Kind::I64 => format!("{sep}%ld"),
+				_ => format!("{sep}%s"),
+			};
+			let fmt = self.string(&fmt)?;
+			self.emit(format!("lea {fmt}(%rip), %rdi"));
+			match value {
+				Err(label) => self.emit(format!("lea {label}(%rip), %rsi")),
+				Ok(slot) if kind == Kind::Bool => {
+					let yes = self.string("true")?;
+					let no = self.string("false")?;
+					self.emit(format!("lea {yes}(%rip), %rsi"));
+					self.emit(format!("lea {no}(%rip), %rax"));
+					self.emit(format!("cmpq $0, {slot}(%rbp)"));
+					self.emit("cmovz %rax, %rsi");
+				}
+				Ok(slot) => self.emit(format!("mov {slot}(%rbp), %rsi")),
+			}
+			self.emit("xor %eax, %eax");
+			self.call("printf@PLT");
+		}
+
+		let newline = self.string("\n")?;
+		self.emit(format!("lea {newline}(%rip), %rdi"));
+		self.emit("xor %eax, %eax");
+		self.call("printf@PLT");
+		Ok(())
+	}
+
+	fn generate_binary(&mut self, op: BinaryOp, lhs: &'a Code<'a>, rhs: &'a Code<'a>) -> Result<Kind> {
+		let lhs_kind = lhs.generate_asm(self)?;
+		if op.is_logic() {
+			// short-circuit by skipping the right side
+			let end = self.label();
+			let reg = self.top();
+			self.emit(format!("test {reg}, {reg}"));
+			self.emit(format!("{} {end}", if op == BinaryOp::And { "jz" } else { "jnz" }));
+			let rhs_kind = rhs.generate_asm(self)?;
+			let Some(kind) = binary_kind(op, lhs_kind, rhs_kind) else {
+				Err(format!("invalid operands for {op:?}: {lhs_kind:?} and {rhs_kind:?}"))?
+			};
+			let src = self.top();
+			let dst = REGS[(self.func.depth - 2) % REGS.len()];
+			self.emit(format!("mov {src}, {dst}"));
+			self.free();
+			self.place(&end);
+			return Ok(kind);
+		}
+
+		let rhs_kind = rhs.generate_asm(self)?;
+		let Some(kind) = binary_kind(op, lhs_kind, rhs_kind) else {
+			Err(format!("invalid operands for {op:?}: {lhs_kind:?} and {rhs_kind:?}"))?
+		};
+		if !matches!(lhs_kind, Kind::I64 | Kind::Bool) {
+			Err(format!(
+				"{lhs_kind:?} operators are not supported by the assembly backend"
+			))?;
+		}
+
+		// operate on both registers before releasing the right side, as
+		// releasing may restore a spilled value into its register
+		let src = self.top();
+		let dst = REGS[(self.func.depth - 2) % REGS.len()];
+		match op {
+			BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul => {
+				let ins = match op {
+					BinaryOp::Add => "add",
+					BinaryOp::Sub => "sub",
+					_ => "imul",
+				};
+				self.emit(format!("{ins} {src}, {dst}"));
+			}
+			BinaryOp::Div | BinaryOp::Mod => {
+				self.emit(format!("mov {dst}, %rax"));
+				self.emit("cqo");
+				self.emit(format!("idiv {src}"));
+				let out = if op == BinaryOp::Div { "%rax" } else { "%rdx" };
+				self.emit(format!("mov {out}, {dst}"));
+			}
+			_ => {
+				let set = match op {
+					BinaryOp::Eq => "sete",
+					BinaryOp::Ne => "setne",
+					BinaryOp::Lt => "setl",
+					BinaryOp::Le => "setle",
+					BinaryOp::Gt => "setg",
+					_ => "setge",
+				};
+				self.emit(format!("cmp {src}, {dst}"));
+				self.emit(format!("{set} %al"));
+				self.emit(format!("movzbq %al, {dst}"));
+			}
+		}
+		self.free();
+		Ok(kind)
+	}
+}
+
+impl<'a> Code<'a> {
+	/// Generate assembly for the code, leaving any value in a temporary
+	/// register of the builder.
+	pub fn generate_asm(&'a self, builder: &mut Builder<'a>) -> Result<Kind> {
+		builder.checker.enter(self)?;
+		let out = self.generate_asm_node(builder);
+		builder.checker.leave();
+		out
+	}
+
+	fn generate_asm_node(&'a self, builder: &mut Builder<'a>) -> Result<Kind> {
+		let kind = match self {
+			Code::Int(v) => {
+				let reg = builder.alloc();
+				builder.emit(format!("movabsq ${v}, {reg}"));
+				Kind::I64
+			}
+			Code::Str(v) => {
+				let label = builder.string(v)?;
+				let reg = builder.alloc();
+				builder.emit(format!("lea {label}(%rip), {reg}"));
+				Kind::Str
+			}
+			Code::BigInt(..) => Err("big integers are not supported by the assembly backend")?,
+			Code::Print(args) => {
+				builder.generate_print(args)?;
+				Kind::Void
+			}
+			Code::Let(name, value) => {
+				let kind = value.generate_asm(builder)?;
+				if kind == Kind::Void {
+					Err(format!("cannot declare `{}` with a void value", name.as_str()))?;
+				}
+				let reg = builder.top();
+				let slot = builder.declare(*name, kind);
+				builder.emit(format!("mov {reg}, {slot}(%rbp)"));
+				builder.free();
+				Kind::Void
+			}
+			Code::Get(name) => {
+				let (slot, kind) = builder.lookup(*name)?;
+				let reg = builder.alloc();
+				builder.emit(format!("mov {slot}(%rbp), {reg}"));
+				kind
+			}
+			Code::Set(name, value) => {
+				let (slot, kind) = builder.lookup(*name)?;
+				let value = value.generate_asm(builder)?;
+				if value != kind {
+					Err(format!(
+						"cannot assign {value:?} to `{}` of type {kind:?}",
+						name.as_str()
+					))?;
+				}
+				let reg = builder.top();
+				builder.emit(format!("mov {reg}, {slot}(%rbp)"));
+				builder.free();
+				Kind::Void
+			}
+			Code::Binary(op, lhs, rhs) => builder.generate_binary(*op, lhs, rhs)?,
+			Code::Unary(op, arg) => {
+				let kind = arg.generate_asm(builder)?;
+				let reg = builder.top();
+				match (op, kind) {
+					(UnaryOp::Neg, Kind::I64) => {
+						builder.emit(format!("neg {reg}"));
+						Kind::I64
+					}
+					(UnaryOp::Not, Kind::Bool | Kind::I64) => {
+						builder.emit(format!("test {reg}, {reg}"));
+						builder.emit("sete %al");
+						builder.emit(format!("movzbq %al, {reg}"));
+						Kind::Bool
+					}
+					(op, kind) => Err(format!("invalid operand for {op:?}: {kind:?}"))?,
+				}
+			}
+			Code::Block(list) => {
+				builder.generate_scope(list)?;
+				Kind::Void
+			}
+			Code::If(cond, then, other) => {
+				let else_label = builder.label();
+				builder.generate_cond(cond, &else_label)?;
+				builder.generate_body(then)?;
+				if let Some(other) = other {
+					let end = builder.label();
+					builder.emit(format!("jmp {end}"));
+					builder.place(&else_label);
+					builder.generate_body(other)?;
+					builder.place(&end);
+				} else {
+					builder.place(&else_label);
+				}
+				Kind::Void
+			}
+			Code::While(cond, body) => {
+				let start = builder.label();
+				let end = builder.label();
+				builder.place(&start);
+				builder.generate_cond(cond, &end)?;
+				builder.func.loops.push((start.clone(), end.clone()));
+				let result = builder.generate_body(body);
+				builder.func.loops.pop();
+				result?;
+				builder.emit(format!("jmp {start}"));
+				builder.place(&end);
+				Kind::Void
+			}
+			Code::Break | Code::Continue => {
+				let Some((start, end)) = builder.func.loops.last() else {
+					Err(format!("{self:?} outside of a loop"))?
+				};
+				let label = if let Code::Break = self { end } else { start };
+				builder.emit(format!("jmp {label}"));
+				Kind::Void
+			}
+			Code::Return(value) => {
+				let kind = match value {
+					Some(value) => value.generate_asm(builder)?,
+					None => Kind::Void,
+				};
+				match (builder.func.ret, kind) {
+					(None, Kind::Void) => builder.emit("xor %eax, %eax"),
+					(Some(Kind::Void), Kind::Void) => {}
+					(None, Kind::I64) => {}
+					(Some(ret), kind) if ret == kind => {}
+					(None, kind) => Err(format!("cannot return {kind:?} from main"))?,
+					(Some(ret), kind) => Err(format!("cannot return {kind:?} from function returning {ret:?}"))?,
+				}
+				if kind != Kind::Void {
+					let reg = builder.top();
+					builder.emit(format!("mov {reg}, %rax"));
+					builder.free();
+				}
+				let label = builder.func.ret_label.clone();
+				builder.emit(format!("jmp {label}"));
+				Kind::Void
+			}
+			Code::Func(def) => {
+				builder.generate_func(def)?;
+				Kind::Void
+			}
+			Code::At(_, code) => code.generate_asm(builder)?,
+			Code::Call(name, args) => {
+				let (func, def) = builder.lookup_func(*name)?;
+				if args.len() != def.args.len() {
+					Err(format!(
+						"`{}` expects {} arguments, but got {}",
+						name.as_str(),
+						def.args.len(),
+						args.len()
+					))?;
+				}
+
+				for (arg, param) in args.iter().zip(def.args.iter()) {
+					let kind = arg.generate_asm(builder)?;
+					if kind != param.kind {
+						Err(format!(
+							"invalid argument `{}` for `{}`: expected {:?}, got {:?}",
+							param.name.as_str(),
+							name.as_str(),
+							param.kind,
+							kind
+						))?;
+					}
+				}
+
+				// the arguments are on top of the temporaries, so move them
+				// from the last, which also restores spilled values
+				for reg in ARGS[..args.len()].iter().rev() {
+					let top = builder.top();
+					builder.emit(format!("mov {top}, {reg}"));
+					builder.free();
+				}
+				builder.call(func);
+
+				if def.ret == Kind::Void {
+					Kind::Void
+				} else {
+					let reg = builder.alloc();
+					builder.emit(format!("mov %rax, {reg}"));
+					def.ret
+				}
+			}
+		};
+		Ok(kind)
+	}
+}
+
+/// Builds and runs generated assembly with `gcc`.
+#[derive(Default)]
+pub struct Runner {
+	code: String,
+}
+
+/// Name of the generated assembly source.
+const SOURCE_NAME: &str = "main.s";
+
+impl Runner {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	pub fn append<T: AsRef<str>>(&mut self, code: T) {
+		self.code.push_str(code.as_ref())
+	}
+
+	pub fn code(&self) -> &str {
+		&self.code
+	}
+
+	pub fn execute(&mut self) -> Result<Output> {
+		let (dir, path) = self.compile()?;
+		let exe = Command::new(path)
+			.current_dir(dir.path())
+			.stderr(Stdio::piped())
+			.stdout(Stdio::piped())
+			.spawn()?;
+
+		let out = exe.wait_with_output()?;
+		Ok(out)
+	}
+
+	/// Assemble and link the program in a temporary directory.
+	pub fn compile(&mut self) -> Result<(temp::Dir, PathBuf)> {
+		let dir = temp::dir_with("bit_asm")?;
+		let path = PathBuf::from("./main.exe");
+		dir.file(SOURCE_NAME)?.write(&self.code)?;
+
+		let out = clang::Toolchain::gcc()
+			.command(SOURCE_NAME, &path)
+			.current_dir(dir.path())
+			.output()
+			.map_err(|err| format!("AS: could not run `gcc`: {err}"))?;
+		let result = if out.status.success() {
+			Ok(())
+		} else {
+			let stderr = String::from_utf8_lossy(&out.stderr);
+			Err(format!(
+				"AS: `gcc` exited with status {}\n\n  | {}\n",
+				out.status,
+				indent_with(stderr.trim(), "  | ")
+			)
+			.into())
+		};
+		dir.on_failure(result)?;
+		Ok((dir, path))
+	}
+}
+
+/// Size and speed of a compiled program.
+#[derive(Clone, Debug)]
+pub struct Stats {
+	pub source_size: usize,
+	pub exe_size: u64,
+	pub duration: Duration,
+	pub stdout: String,
+}
+
+/// Comparison of the assembly backend with the C backend.
+#[derive(Clone, Debug)]
+pub struct Comparison {
+	pub asm: Stats,
+	pub c: Stats,
+}
+
+/// Build the code with both the assembly and the C backend and compare
+/// the resulting programs.
+///
+/// Executables are run `runs` times and the fastest time is used.
+pub fn compare_with_c<'a>(store: &'a Store, code: &'a Code<'a>, runs: usize) -> Result<Comparison> {
+	let run = |dir: &temp::Dir, exe: &PathBuf, source_size: usize| -> Result<Stats> {
+		let exe_size = std::fs::metadata(dir.path().join(exe))?.len();
+		let mut duration = Duration::MAX;
+		let mut stdout = String::new();
+		for _ in 0..runs.max(1) {
+			let start = Instant::now();
+			let out = Command::new(exe).current_dir(dir.path()).output()?;
+			duration = duration.min(start.elapsed());
+			if !out.status.success() {
+				Err(format!("program exited with {}", out.status))?;
+			}
+			stdout = String::from_utf8(out.stdout)?;
+		}
+		Ok(Stats {
+			source_size,
+			exe_size,
+			duration,
+			stdout,
+		})
+	};
+
+	let mut builder = Builder::new(store);
+	code.generate_asm(&mut builder)?;
+	let mut asm = builder.build();
+	let (dir, exe) = asm.compile()?;
+	let asm = run(&dir, &exe, asm.code().len())?;
+
+	let mut builder = clang::Builder::new(store);
+	let main = code.generate_c(&mut builder)?;
+	let mut c = builder.build(main);
+	let (dir, exe) = c.compile()?;
+	let c = run(&dir, &exe, c.code().len())?;
+
+	Ok(Comparison { asm, c })
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn the_answer() -> Result<()> {
+		let store = Store::new();
+		let int = |v| &*store.add(Code::Int(v));
+		let get = |name| &*store.add(Code::Get(store.sym(name)));
+		let op = |op, a, b| &*store.add(Code::Binary(op, a, b));
+		let code = Code::Block(store.add_list([
+			Code::Let(store.sym("x"), int(10)),
+			Code::Let(
+				store.sym("ans"),
+				op(BinaryOp::Add, op(BinaryOp::Mul, get("x"), int(4)), int(2)),
+			),
+			Code::Print(store.add_list([
+				Code::Str(store.str("the answer is")),
+				*get("ans"),
+				*op(BinaryOp::Eq, get("ans"), int(42)),
+				*op(BinaryOp::Mod, int(-7), int(3)),
+				*op(BinaryOp::Div, int(-7), int(3)),
+				Code::Str(store.str("\"100%\"\n")),
+			])),
+		]));
+
+		let mut builder = Builder::new(&store);
+		code.generate_asm(&mut builder)?;
+		let out = builder.build().execute()?;
+		assert!(out.status.success());
+		assert_eq!(
+			String::from_utf8(out.stdout)?,
+			"the answer is 42 true -1 -2 \"100%\"\n\n"
+		);
+
+		// valid for the checker, but not for this backend
+		let str = |v| &*store.add(Code::Str(store.str(v)));
+		let code = Code::Print(store.add_list([*op(BinaryOp::Add, str("a"), str("b"))]));
+		let err = code.generate_asm(&mut Builder::new(&store)).unwrap_err();
+		assert_eq!(
+			err.to_string(),
+			"Str operators are not supported by the assembly backend"
+		);
+		Ok(())
+	}
+
+	#[test]
+	fn compare_backends() -> Result<()> {
+		let store = Store::new();
+		let int = |v| &*store.add(Code::Int(v));
+		let get = |name| &*store.add(Code::Get(store.sym(name)));
+		let op = |op, a, b| &*store.add(Code::Binary(op, a, b));
+
+		let fib = FuncDef {
+			name: store.sym("fib"),
+			args: store.add_list([Param {
+				name: store.sym("n"),
+				kind: Kind::I64,
+			}]),
+			ret: Kind::I64,
+			body: store.add(Code::Block(store.add_list([
+				Code::If(
+					op(BinaryOp::Lt, get("n"), int(2)),
+					store.add(Code::Return(Some(get("n")))),
+					None,
+				),
+				Code::Return(Some(op(
+					BinaryOp::Add,
+					store.add(Code::Call(
+						store.sym("fib"),
+						store.add_list([*op(BinaryOp::Sub, get("n"), int(1))]),
+					)),
+					store.add(Code::Call(
+						store.sym("fib"),
+						store.add_list([*op(BinaryOp::Sub, get("n"), int(2))]),
+					)),
+				))),
+			]))),
+		};
+
+		// deep expression to exercise register spilling
+		let mut deep = int(1);
+		for i in 2..=12 {
+			deep = op(BinaryOp::Add, int(i), op(BinaryOp::Mul, deep, int(1)));
+		}
+
+		let code = store.add(Code::Block(store.add_list([
+			Code::Func(store.add(fib)),
+			Code::Let(store.sym("i"), int(0)),
+			Code::While(
+				op(BinaryOp::Le, get("i"), int(25)),
+				store.add(Code::Block(store.add_list([
+					Code::If(
+						op(
+							BinaryOp::Or,
+							op(BinaryOp::Eq, get("i"), int(25)),
+							op(BinaryOp::Eq, op(BinaryOp::Mod, get("i"), int(10)), int(0)),
+						),
+						store.add(Code::Print(store.add_list([
+							Code::Str(store.str("fib")),
+							*get("i"),
+							Code::Call(store.sym("fib"), store.add_list([*get("i")])),
+						]))),
+						None,
+					),
+					Code::Set(store.sym("i"), op(BinaryOp::Add, get("i"), int(1))),
+				]))),
+			),
+			Code::Print(store.add_list([*deep, Code::Unary(UnaryOp::Not, int(0))])),
+		])));
+
+		let cmp = compare_with_c(&store, code, 3)?;
+		assert_eq!(cmp.asm.stdout, cmp.c.stdout);
+		assert_eq!(
+			cmp.asm.stdout,
+			"fib 0 0\nfib 10 55\nfib 20 6765\nfib 25 75025\n78 true\n"
+		);
+		assert!(cmp.asm.source_size > 0 && cmp.c.source_size > 0);
+		assert!(cmp.asm.exe_size > 0 && cmp.c.exe_size > 0);
+		Ok(())
+	}
+}
@@ -2,6 +2,20 @@ use bit::*;
 
 const SKIP_CODE: bool = true;
 
+/// Command line options.
+#[derive(Default)]
+struct Options {
+	/// Build generated programs with the sanitizers (`--sanitize`).
+	sanitize: bool,
+	/// Run generated programs in the bytecode VM instead of compiling
+	/// them with a C compiler (`--vm`).
+	vm: bool,
+	/// Show the IR before and after every optimisation pass (`--dump-ir`).
+	dump_ir: bool,
+	/// Run the program of the `eval` example (`--answer`).
+	answer: bool,
+}
+
 fn main() {
 	if let Err(err) = run() {
 		eprintln!("\nError: {err}\n");
@@ -17,13 +31,48 @@ fn run() -> Result<()> {
 		"@", "&", "`", "!", "?", "+", "-", "*", "/", "=", ":", ".", ",", ";", "(", ")", "[", "]", "{", "}", "<", ">",
 	]);
 
+	let mut options = Options::default();
 	for arg in std::env::args().skip(1) {
-		let src = store.load_source(arg)?;
+		if arg == "--sanitize" {
+			options.sanitize = true;
+			continue;
+		}
+		if arg == "--vm" {
+			options.vm = true;
+			continue;
+		}
+		if arg == "--dump-ir" {
+			options.dump_ir = true;
+			continue;
+		}
+		if arg == "--answer" {
+			options.answer = true;
+			continue;
+		}
+		// `--color=auto|always|never`, see `term::Mode`
+		if let Some(mode) = arg.strip_prefix("--color=") {
+			term::set_mode(mode.parse()?);
+			continue;
+		}
+
+		let src = store.load_source(&arg)?;
+
+		// textual IR is run directly, see `sexpr`
+		if arg.ends_with(".ir") {
+			let code = sexpr::Parser::new(&store).source(src).parse(src.text())?;
+			run_code(&store, code, &options)?;
+			continue;
+		}
+
 		if !run_numbers(src) {
 			std::process::exit(1);
 		}
 	}
 
+	if options.answer {
+		run_code(&store, answer_code(&store), &options)?;
+	}
+
 	if SKIP_CODE {
 		return Ok(());
 	}
@@ -65,6 +114,60 @@ fn show_tokens<T: Grammar>(lexer: &mut Lexer<T>, src: Source) -> Result<()> {
 	Ok(())
 }
 
+//====================================================================================================================//
+// DEMO - Code generation
+//====================================================================================================================//
+
+/// Code for the `eval` example, while there is no parser to generate it.
+fn answer_code(store: &Store) -> Code<'_> {
+	let int = |v| &*store.add(Code::Int(v));
+	let get = |name| &*store.add(Code::Get(store.sym(name)));
+	let op = |op, a, b| &*store.add(Code::Binary(op, a, b));
+	Code::Block(store.add_list([
+		Code::Let(store.sym("x"), int(10)),
+		Code::Let(store.sym("y"), int(4)),
+		Code::Let(store.sym("z"), int(2)),
+		Code::Let(
+			store.sym("ans"),
+			op(BinaryOp::Add, op(BinaryOp::Mul, get("x"), get("y")), get("z")),
+		),
+		Code::Print(store.add_list([
+			Code::Str(store.str("The answer to life, the universe, and everything is")),
+			*get("ans"),
+		])),
+	]))
+}
+
+fn run_code<'a>(store: &'a Store, code: Code<'a>, options: &Options) -> Result<()> {
+	let mut passes = PassManager::standard(store);
+	passes.set_dump(options.dump_ir);
+	let code = passes.run(code);
+	if options.dump_ir {
+		print!("{}", passes.dump_output());
+	}
+
+	if options.vm {
+		let code = store.add(code);
+		let mut builder = vm::Builder::new(store);
+		code.generate_vm(&mut builder)?;
+		let status = builder.build().run(&mut std::io::stdout())?;
+		if status != 0 {
+			Err(format!("program exited with {status}"))?;
+		}
+		return Ok(());
+	}
+
+	let mut builder = clang::Builder::new(store);
+	let main = code.generate_c(&mut builder)?;
+	let mut runner = builder.build(main);
+	runner.set_sanitize(options.sanitize);
+	let status = runner.run()?;
+	if !status.success() {
+		Err(format!("program exited with {status}"))?;
+	}
+	Ok(())
+}
+
 //====================================================================================================================//
 // DEMO - Running numbers
 //====================================================================================================================//
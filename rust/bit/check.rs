@@ -0,0 +1,450 @@
+use std::{
+	collections::HashMap,
+	fmt::{Display, Formatter},
+};
+
+use super::*;
+
+/// Type error found by the [`Checker`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CheckError<'a> {
+	pub message: String,
+	/// Location of the innermost code with source information.
+	pub span: Option<Span<'a>>,
+}
+
+impl<'a> Display for CheckError<'a> {
+	fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+		write!(f, "{}", self.message)?;
+		if let Some(span) = self.span {
+			write!(f, " (at {}:{}:{})", span.src.name(), span.line(), span.column())?;
+		}
+		Ok(())
+	}
+}
+
+/// Validation pass over [`Code`] that computes the [`Kind`] of every node.
+///
+/// Backends run the checker before generating any code, so they can rely
+/// on well-typed input. Declarations at the top level are kept between
+/// calls to [`Checker::check`], like in the backend builders.
+pub struct Checker<'a> {
+	scopes: Scopes<'a, (), ()>,
+	ret: Option<Kind>,
+	loops: usize,
+	span: Option<Span<'a>>,
+	kinds: HashMap<*const Code<'a>, Kind>,
+	errors: Vec<CheckError<'a>>,
+	/// Nesting of backend code generation, see [`Checker::enter`].
+	nesting: usize,
+}
+
+/// Check the code with a new [`Checker`] and return its kind.
+pub fn check<'a>(code: &Code<'a>) -> Result<Kind> {
+	Checker::new().check(code)
+}
+
+impl<'a> Default for Checker<'a> {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl<'a> Checker<'a> {
+	pub fn new() -> Self {
+		Self {
+			scopes: Scopes::new(),
+			ret: None,
+			loops: 0,
+			span: None,
+			kinds: Default::default(),
+			errors: Vec::new(),
+			nesting: 0,
+		}
+	}
+
+	/// Check the code and return its kind, or an error listing all the
+	/// problems found.
+	pub fn check(&mut self, code: &Code<'a>) -> Result<Kind> {
+		let errors = self.errors.len();
+		let kind = self.check_stmt(code);
+		let errors = &self.errors[errors..];
+		if !errors.is_empty() {
+			let errors = errors.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+			Err(errors.join("\n"))?;
+		}
+		Ok(kind.unwrap_or(Kind::Void))
+	}
+
+	/// Check the code if it is the outermost node generated by a backend.
+	///
+	/// Backends call this before generating every node, and
+	/// [`Checker::leave`] after it, so the whole tree is validated once
+	/// before generating any code for it.
+	pub fn enter(&mut self, code: &Code<'a>) -> Result<()> {
+		if self.nesting == 0 {
+			self.check(code)?;
+		}
+		self.nesting += 1;
+		Ok(())
+	}
+
+	pub fn leave(&mut self) {
+		self.nesting -= 1;
+	}
+
+	/// Kind computed for a checked node.
+	pub fn kind_of(&self, code: &Code<'a>) -> Option<Kind> {
+		self.kinds.get(&(code as *const _)).copied()
+	}
+
+	/// All errors found so far.
+	pub fn errors(&self) -> &[CheckError<'a>] {
+		&self.errors
+	}
+
+	fn error<T: Into<String>>(&mut self, message: T) -> Option<Kind> {
+		self.errors.push(CheckError {
+			message: message.into(),
+			span: self.span,
+		});
+		None
+	}
+
+	fn lookup(&mut self, name: Sym<'a>) -> Option<Kind> {
+		match self.scopes.lookup(name) {
+			// variables with invalid values were already reported
+			Ok((_, kind)) => Some(kind).filter(|&x| x != Kind::Void),
+			Err(err) => self.error(err.to_string()),
+		}
+	}
+
+	fn lookup_func(&mut self, name: Sym<'a>) -> Option<&'a FuncDef<'a>> {
+		match self.scopes.lookup_func(name) {
+			Ok((_, def)) => Some(def),
+			Err(err) => {
+				self.error(err.to_string());
+				None
+			}
+		}
+	}
+
+	fn declare_func(&mut self, def: &'a FuncDef<'a>) {
+		if let Err(err) = self.scopes.declare_func(def, || ()) {
+			self.error(err.to_string());
+		}
+	}
+
+	fn check_scope(&mut self, code: &[Code<'a>]) {
+		self.scopes.push();
+		self.check_list(code);
+		self.scopes.pop();
+	}
+
+	fn check_list(&mut self, code: &[Code<'a>]) {
+		// functions are visible in the entire scope, so declare them first
+		for it in code.iter() {
+			if let Code::Func(def) = it.inner() {
+				let at = it.span().or(self.span);
+				let span = std::mem::replace(&mut self.span, at);
+				self.declare_func(def);
+				self.span = span;
+			}
+		}
+
+		let mut exit = None;
+		for it in code.iter() {
+			if let Some(exit) = exit.take() {
+				let at = it.span().or(self.span);
+				let span = std::mem::replace(&mut self.span, at);
+				self.error(format!("unreachable code after `{exit}`"));
+				self.span = span;
+			}
+			self.check_stmt(it);
+			if exit.is_none() {
+				exit = it.exit();
+			}
+		}
+	}
+
+	fn check_stmt(&mut self, code: &Code<'a>) -> Option<Kind> {
+		let kind = self.check_code(code);
+		if let Some(kind) = kind {
+			self.kinds.insert(code as *const _, kind);
+		}
+		kind
+	}
+
+	fn check_body(&mut self, code: &Code<'a>) {
+		match code.inner() {
+			Code::Block(..) => {
+				self.check_stmt(code);
+			}
+			_ => self.check_scope(std::slice::from_ref(code)),
+		}
+	}
+
+	fn check_cond(&mut self, code: &Code<'a>) {
+		match self.check_stmt(code) {
+			Some(Kind::Bool | Kind::I64) | None => {}
+			Some(kind) => {
+				self.error(format!("invalid condition of type {kind:?}"));
+			}
+		}
+	}
+
+	fn check_func(&mut self, def: &'a FuncDef<'a>) {
+		// functions in a list are declared beforehand, and redefinitions
+		// were already reported there
+		let _ = self.scopes.declare_func(def, || ());
+
+		let ret = self.ret.replace(def.ret);
+		let loops = std::mem::take(&mut self.loops);
+
+		self.scopes.push_func();
+		for it in def.args.iter() {
+			if it.kind == Kind::Void {
+				self.error(format!("invalid void argument `{}`", it.name.as_str()));
+			}
+			self.scopes.declare(it.name, (), it.kind);
+		}
+		self.check_body(def.body);
+		self.scopes.pop_func();
+
+		self.loops = loops;
+		self.ret = ret;
+	}
+
+	fn check_code(&mut self, code: &Code<'a>) -> Option<Kind> {
+		let kind = match code {
+			Code::Int(..) => Kind::I64,
+			Code::BigInt(..) => Kind::BigInt,
+			Code::Str(..) => Kind::Str,
+			Code::Print(args) => {
+				for it in args.iter() {
+					self.check_stmt(it);
+				}
+				Kind::Void
+			}
+			Code::Let(name, value) => {
+				let kind = self.check_stmt(value);
+				if kind == Some(Kind::Void) {
+					self.error(format!("cannot declare `{}` with a void value", name.as_str()));
+				}
+				// declare even on errors to avoid reporting every use
+				self.scopes.declare(*name, (), kind.unwrap_or(Kind::Void));
+				Kind::Void
+			}
+			Code::Get(name) => self.lookup(*name)?,
+			Code::Set(name, value) => {
+				let var = self.lookup(*name);
+				let value = self.check_stmt(value);
+				if let (Some(var), Some(value)) = (var, value) {
+					if var != value {
+						self.error(format!(
+							"cannot assign {value:?} to `{}` of type {var:?}",
+							name.as_str()
+						));
+					}
+				}
+				Kind::Void
+			}
+			Code::Binary(op, lhs, rhs) => {
+				let lhs = self.check_stmt(lhs);
+				let rhs = self.check_stmt(rhs);
+				let (lhs, rhs) = (lhs?, rhs?);
+				match binary_kind(*op, lhs, rhs) {
+					Some(kind) => kind,
+					None => return self.error(format!("invalid operands for {op:?}: {lhs:?} and {rhs:?}")),
+				}
+			}
+			Code::Unary(op, arg) => {
+				let arg = self.check_stmt(arg)?;
+				match (op, arg) {
+					(UnaryOp::Neg, Kind::I64 | Kind::BigInt) => arg,
+					(UnaryOp::Not, Kind::Bool | Kind::I64) => Kind::Bool,
+					(UnaryOp::Len | UnaryOp::Chars, Kind::Str) => Kind::I64,
+					(op, kind) => return self.error(format!("invalid operand for {op:?}: {kind:?}")),
+				}
+			}
+			Code::Block(list) => {
+				self.check_scope(list);
+				Kind::Void
+			}
+			Code::If(cond, then, other) => {
+				self.check_cond(cond);
+				self.check_body(then);
+				if let Some(other) = other {
+					self.check_body(other);
+				}
+				Kind::Void
+			}
+			Code::While(cond, body) => {
+				self.check_cond(cond);
+				self.loops += 1;
+				self.check_body(body);
+				self.loops -= 1;
+				Kind::Void
+			}
+			Code::Break | Code::Continue => {
+				if self.loops == 0 {
+					self.error(format!("{code:?} outside of a loop"));
+				}
+				Kind::Void
+			}
+			Code::Return(value) => {
+				let kind = match value {
+					Some(value) => self.check_stmt(value),
+					None => Some(Kind::Void),
+				};
+				match (self.ret, kind) {
+					(_, None) => {}
+					(None, Some(Kind::Void | Kind::I64)) => {}
+					(Some(ret), Some(kind)) if ret == kind => {}
+					(None, Some(kind)) => {
+						self.error(format!("cannot return {kind:?} from main"));
+					}
+					(Some(ret), Some(kind)) => {
+						self.error(format!("cannot return {kind:?} from function returning {ret:?}"));
+					}
+				}
+				Kind::Void
+			}
+			Code::Func(def) => {
+				self.check_func(def);
+				Kind::Void
+			}
+			Code::Call(name, args) => {
+				let def = self.lookup_func(*name);
+				let kinds = args.iter().map(|x| self.check_stmt(x)).collect::<Vec<_>>();
+				let def = def?;
+				if args.len() != def.args.len() {
+					return self.error(format!(
+						"`{}` expects {} arguments, but got {}",
+						name.as_str(),
+						def.args.len(),
+						args.len()
+					));
+				}
+				for (param, kind) in def.args.iter().zip(kinds) {
+					match kind {
+						Some(kind) if kind != param.kind => {
+							self.error(format!(
+								"invalid argument `{}` for `{}`: expected {:?}, got {:?}",
+								param.name.as_str(),
+								name.as_str(),
+								param.kind,
+								kind
+							));
+						}
+						_ => {}
+					}
+				}
+				def.ret
+			}
+			Code::At(span, code) => {
+				let span = self.span.replace(*span);
+				let kind = self.check_stmt(code);
+				self.span = span;
+				kind?
+			}
+		};
+		Some(kind)
+	}
+}
+
+/// Result kind for a binary operation, or `None` if the operands are not
+/// valid for the operator.
+pub fn binary_kind(op: BinaryOp, lhs: Kind, rhs: Kind) -> Option<Kind> {
+	let kind = match (lhs, rhs) {
+		_ if op.is_logic() => {
+			if lhs != Kind::Bool || rhs != Kind::Bool {
+				return None;
+			}
+			Kind::Bool
+		}
+		(Kind::I64, Kind::I64) if op.is_compare() => Kind::Bool,
+		(Kind::I64, Kind::I64) => Kind::I64,
+		(Kind::Bool, Kind::Bool) if op == BinaryOp::Eq || op == BinaryOp::Ne => Kind::Bool,
+		(Kind::Str, Kind::Str) if op.is_compare() => Kind::Bool,
+		(Kind::Str, Kind::Str) if op == BinaryOp::Add => Kind::Str,
+		(Kind::BigInt, Kind::BigInt | Kind::I64) | (Kind::I64, Kind::BigInt) => {
+			if op.is_compare() {
+				Kind::Bool
+			} else if matches!(op, BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul) {
+				Kind::BigInt
+			} else {
+				return None;
+			}
+		}
+		_ => return None,
+	};
+	Some(kind)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn kinds() -> Result<()> {
+		let store = Store::new();
+		let int = |v| &*store.add(Code::Int(v));
+		let get = |name| &*store.add(Code::Get(store.sym(name)));
+		let op = |op, a, b| &*store.add(Code::Binary(op, a, b));
+
+		let sum = op(
+			BinaryOp::Add,
+			get("x"),
+			store.add(Code::BigInt(store.add_slice(&[1, 1]))),
+		);
+		let cmp = op(BinaryOp::Lt, get("x"), int(2));
+		let args = store.add_list([*sum, *cmp]);
+		let code = Code::Block(store.add_list([Code::Let(store.sym("x"), int(1)), Code::Print(args)]));
+
+		let mut checker = Checker::new();
+		assert_eq!(checker.check(&code)?, Kind::Void);
+		assert_eq!(checker.kind_of(&args[0]), Some(Kind::BigInt));
+		assert_eq!(checker.kind_of(&args[1]), Some(Kind::Bool));
+		assert_eq!(checker.kind_of(get("y")), None);
+		Ok(())
+	}
+
+	#[test]
+	fn errors_with_spans() {
+		let store = Store::new();
+		let src = store.load_string("test.bit", "let x = 1\nreturn\nprint y + 'a'\n");
+		let at = |sta, end, code| Code::At(src.span().slice(sta..end), store.add(code));
+		let int = |v| &*store.add(Code::Int(v));
+
+		let code = Code::Block(store.add_list([
+			at(0, 9, Code::Let(store.sym("x"), int(1))),
+			at(10, 16, Code::Return(None)),
+			at(
+				17,
+				30,
+				Code::Print(store.add_list([Code::Binary(
+					BinaryOp::Add,
+					store.add(Code::Get(store.sym("y"))),
+					store.add(Code::Str(store.str("a"))),
+				)])),
+			),
+			Code::Set(store.sym("x"), store.add(Code::Str(store.str("b")))),
+			Code::Binary(BinaryOp::Add, int(1), store.add(Code::Str(store.str("c")))),
+		]));
+
+		let mut checker = Checker::new();
+		let err = checker.check(&code).unwrap_err();
+		let errors = checker.errors().iter().map(|x| x.to_string()).collect::<Vec<_>>();
+		assert_eq!(
+			errors,
+			[
+				"unreachable code after `return` (at test.bit:3:1)",
+				"undeclared variable `y` (at test.bit:3:1)",
+				"cannot assign Str to `x` of type I64",
+				"invalid operands for Add: I64 and Str",
+			]
+		);
+		assert_eq!(err.to_string(), errors.join("\n"));
+	}
+}
@@ -1,34 +1,67 @@
 use std::{
 	fmt::Write,
-	path::PathBuf,
+	path::{Path, PathBuf},
 	process::{Command, ExitStatus, Output, Stdio},
+	sync::{Arc, Mutex},
 };
 
 use super::*;
 
-pub enum Kind {
-	Void,
-	Str,
-	I64,
-}
+pub mod cache;
+pub mod exec;
+pub mod package;
+pub mod runtime;
+pub mod sanitize;
+pub mod toolchain;
+
+pub use cache::*;
+pub use exec::*;
+pub use package::*;
+pub use runtime::Runtime;
+pub use sanitize::*;
+pub use toolchain::*;
 
 impl Kind {
 	pub fn decl(&self, out: &mut String) {
 		match self {
 			Kind::Void => out.push_str("void"),
-			Kind::Str => out.push_str("const char*"),
+			Kind::Bool => out.push_str("bool"),
+			Kind::Str => out.push_str("bit_str"),
 			Kind::I64 => out.push_str("int64_t"),
+			Kind::BigInt => out.push_str("bit_int"),
 		}
 	}
 
+	/// Format used to print the value with `printf`.
+	///
+	/// Strings and big integers are printed by the runtime instead, see
+	/// [`Kind::print`].
 	pub fn fmt(&self) -> Option<&'static str> {
 		let out = match self {
-			Kind::Void => return None,
-			Kind::Str => "%s",
+			Kind::Void | Kind::Str | Kind::BigInt => return None,
+			Kind::Bool => "%s",
 			Kind::I64 => "%\" PRId64 \"",
 		};
 		Some(out)
 	}
+
+	/// Runtime support code needed to use the type.
+	pub fn runtime(&self) -> Option<&'static Runtime> {
+		match self {
+			Kind::Str => Some(&runtime::STR),
+			Kind::BigInt => Some(&runtime::INT),
+			_ => None,
+		}
+	}
+
+	/// Runtime function used to print the value.
+	pub fn print(&self) -> Option<&'static str> {
+		match self {
+			Kind::Str => Some("bit_str_print"),
+			Kind::BigInt => Some("bit_int_print"),
+			_ => None,
+		}
+	}
 }
 
 pub struct Func {
@@ -41,25 +74,62 @@ impl Func {
 	pub fn i64(value: i64) -> Self {
 		let body = String::new();
 		let kind = Kind::I64;
-		let expr = if value < 0 {
-			format!("(-{value})")
+		let expr = if value == i64::MIN {
+			"INT64_MIN".to_string()
+		} else if value < 0 {
+			format!("({value})")
 		} else {
 			format!("{value}")
 		};
 		Self { body, expr, kind }
 	}
 
+	/// Big integer literal using the [`runtime::INT`] integer type.
+	pub fn big_int(limbs: &[u32]) -> Self {
+		let body = String::new();
+		let kind = Kind::BigInt;
+		let len = limbs.iter().rposition(|x| *x != 0).map(|x| x + 1).unwrap_or(0);
+		let expr = if len == 0 {
+			"bit_int_from_i64(0)".to_string()
+		} else {
+			let limbs = limbs[..len].iter().map(|x| format!("{x:#010X}")).collect::<Vec<_>>();
+			format!("bit_int_lit({len}, (const uint32_t[]){{ {} }})", limbs.join(", "))
+		};
+		Self { body, expr, kind }
+	}
+
+	/// String literal using the [`runtime::STR`] string type.
 	pub fn str(value: &str) -> Self {
 		let body = String::new();
 		let kind = Kind::Str;
-		let mut expr = String::new();
-		expr.push('"');
-		for chr in value.chars() {
-			output_char(chr, &mut expr);
-		}
-		expr.push('"');
+		let expr = format!("BIT_STR({})", c_string(value));
 		Self { body, expr, kind }
 	}
+
+	pub fn void(body: String) -> Self {
+		let expr = String::new();
+		let kind = Kind::Void;
+		Self { body, expr, kind }
+	}
+
+	/// Append the function as a statement, discarding its value.
+	fn push_stmt(self, out: &mut String) {
+		out.push_str(&self.body);
+		if !self.expr.is_empty() {
+			let _ = writeln!(out, "{};", self.expr);
+		}
+	}
+}
+
+/// Return a C string literal for the value.
+fn c_string(value: &str) -> String {
+	let mut out = String::new();
+	out.push('"');
+	for chr in value.chars() {
+		output_char(chr, &mut out);
+	}
+	out.push('"');
+	out
 }
 
 fn output_char(chr: char, out: &mut String) {
@@ -68,13 +138,15 @@ fn output_char(chr: char, out: &mut String) {
 		'\"' => "\\\"",
 		'\'' => "\\\'",
 		'\\' => "\\\\",
-		'\0' => "\\0",
+		'\0' => "\\000",
 		'\t' => "\\t",
 		'\n' => "\\n",
 		'\r' => "\\r",
 		'\x08' => "\\b",
+		// use octal escapes, as hex escapes would consume any hex digit
+		// following them
 		'\x01'..='\x07' | '\x0B' | '\x0C' | '\x0E'..='\x1F' | '\x7F' => {
-			let _ = write!(out, "\\x{:02X}", chr as u32);
+			let _ = write!(out, "\\{:03o}", chr as u32);
 			return;
 		}
 		'A'..='Z'
@@ -115,7 +187,7 @@ fn output_char(chr: char, out: &mut String) {
 		_ => {
 			let mut buf = [0; 4];
 			for b in chr.encode_utf8(&mut buf).bytes() {
-				let _ = write!(out, "\\x{:02X}", b);
+				let _ = write!(out, "\\{:03o}", b);
 			}
 			return;
 		}
@@ -125,17 +197,45 @@ fn output_char(chr: char, out: &mut String) {
 
 pub struct Builder<'a> {
 	store: &'a Store,
+	names: NameSet<'a>,
 	include_system: Vec<&'a str>,
 	include_header: Vec<&'a str>,
+	runtime: Vec<&'static Runtime>,
+	/// C names of the variables and functions in scope.
+	scopes: Scopes<'a, &'a str, &'a str>,
+	checker: Checker<'a>,
+	funcs: Vec<FuncCode<'a>>,
+	exports: Vec<Sym<'a>>,
+	unit: &'a str,
+	ret: Option<Kind>,
+	loops: usize,
 	vars: u64,
 }
 
+/// Generated code for a function definition.
+struct FuncCode<'a> {
+	name: &'a str,
+	proto: String,
+	body: String,
+	export: bool,
+	unit: &'a str,
+}
+
 impl<'a> Builder<'a> {
 	pub fn new(store: &'a Store) -> Self {
 		Self {
 			store,
+			names: NameSet::new(store),
 			include_system: Vec::new(),
 			include_header: Vec::new(),
+			runtime: Vec::new(),
+			scopes: Scopes::new(),
+			checker: Checker::new(),
+			funcs: Vec::new(),
+			exports: Vec::new(),
+			unit: "",
+			ret: None,
+			loops: 0,
 			vars: 0,
 		}
 	}
@@ -156,88 +256,519 @@ impl<'a> Builder<'a> {
 		}
 	}
 
+	/// Include runtime support code in the generated source.
+	pub fn include_runtime(&mut self, runtime: &'static Runtime) {
+		if !self.runtime.contains(&runtime) {
+			for it in runtime.includes {
+				self.include_system(it);
+			}
+			self.runtime.push(runtime);
+		}
+	}
+
+	/// Export a function with the given name, using its plain name in C.
+	///
+	/// Exported functions are declared in the public header of a [`Package`].
+	pub fn export(&mut self, name: Sym<'a>) {
+		if !self.exports.contains(&name) {
+			self.exports.push(name);
+		}
+	}
+
+	/// Set the translation unit for the functions generated after this
+	/// call. An empty name is the default unit for the package.
+	pub fn set_unit<T: AsRef<str>>(&mut self, name: T) {
+		self.unit = self.store.intern(name);
+	}
+
 	pub fn var(&mut self) -> u64 {
 		self.vars += 1;
 		self.vars
 	}
 
+	/// Write the C type for `kind`, including the runtime it needs.
+	pub fn decl(&mut self, kind: Kind, out: &mut String) {
+		if let Some(runtime) = kind.runtime() {
+			self.include_runtime(runtime);
+		}
+		kind.decl(out);
+	}
+
+	/// Store the expression in a new temporary variable declared in `body`
+	/// and return the variable name.
+	pub fn temp(&mut self, body: &mut String, kind: Kind, expr: &str) -> String {
+		let var = self.var();
+		self.decl(kind, body);
+		let _ = writeln!(body, " _${var}_ = {expr};");
+		format!("_${var}_")
+	}
+
+	/// Declare a new variable in the current scope and return its C name.
+	///
+	/// Every declaration gets a unique name from the [`NameSet`], so it is
+	/// safe to shadow variables and to use names that are reserved in C.
+	pub fn declare(&mut self, name: Sym<'a>, kind: Kind) -> &'a str {
+		let unique = self.unique(name);
+		self.scopes.declare(name, unique, kind);
+		unique
+	}
+
+	/// Declare a function in the current scope and return its C name.
+	pub fn declare_func(&mut self, def: &'a FuncDef<'a>) -> Result<&'a str> {
+		let (names, exports) = (&self.names, &self.exports);
+		self.scopes.declare_func(def, || {
+			let name = if exports.contains(&def.name) {
+				names.declare(def.name)
+			} else {
+				names.unique(def.name)
+			};
+			names.resolve(name)
+		})
+	}
+
+	pub fn lookup(&self, name: Sym<'a>) -> Result<(&'a str, Kind)> {
+		self.scopes.lookup(name)
+	}
+
+	pub fn lookup_func(&self, name: Sym<'a>) -> Result<(&'a str, &'a FuncDef<'a>)> {
+		self.scopes.lookup_func(name)
+	}
+
+	fn unique(&self, name: Sym<'a>) -> &'a str {
+		let unique = self.names.unique(name);
+		self.names.resolve(unique)
+	}
+
 	pub fn build(&self, main: Func) -> Runner {
-		let mut program = Runner::new();
+		let mut code = String::new();
 
 		for it in self.include_system.iter() {
-			program.append(format!("#include <{it}>\n"));
+			let _ = writeln!(code, "#include <{it}>");
 		}
 
 		for it in self.include_header.iter() {
-			program.append(format!("#include \"{it}\"\n"));
+			let _ = writeln!(code, "#include \"{it}\"");
 		}
 
-		program.append("\n");
-		program.append("int main(int argc, char *argv[]) {\n\t");
+		self.push_runtime(&mut code);
+
+		let mut body = String::new();
+		main.push_stmt(&mut body);
+		body.push_str("return 0;\n");
 
-		program.append(indent_with(main.body, "\t"));
-		if main.expr.len() > 0 {
-			program.append("\t");
-			program.append(main.expr);
-			program.append("\n");
+		if !self.funcs.is_empty() {
+			code.push('\n');
+			for it in self.funcs.iter() {
+				let _ = writeln!(code, "static {};", it.proto);
+			}
 		}
 
-		program.append("\treturn 0;\n");
-		program.append("}\n");
+		for it in self.funcs.iter() {
+			let _ = write!(code, "\nstatic {} {}", it.proto, it.body);
+			reset_line(&mut code, &it.body, SOURCE_NAME);
+		}
 
+		code.push_str("\nint main(int argc, char *argv[]) ");
+		push_block(&mut code, &body);
+		code.push('\n');
+		reset_line(&mut code, &body, SOURCE_NAME);
+
+		let mut program = Runner::new();
+		program.append(code);
 		program
 	}
+
+	fn push_runtime(&self, out: &mut String) {
+		for it in self.runtime.iter() {
+			out.push_str(it.code);
+		}
+	}
+
+	fn generate_scope(&mut self, code: &'a [Code<'a>]) -> Result<String> {
+		self.scopes.push();
+		let result = self.generate_list(code);
+		self.scopes.pop();
+		result
+	}
+
+	fn generate_list(&mut self, code: &'a [Code<'a>]) -> Result<String> {
+		// functions are visible in the entire scope, so declare them first
+		for def in scope::functions(code) {
+			self.declare_func(def)?;
+		}
+
+		let mut body = String::new();
+		for it in code.iter() {
+			let func = it.generate_c(self)?;
+			func.push_stmt(&mut body);
+		}
+		Ok(body)
+	}
+
+	fn generate_func(&mut self, def: &'a FuncDef<'a>) -> Result<()> {
+		let name = self.declare_func(def)?;
+
+		let ret = self.ret.replace(def.ret);
+		let loops = std::mem::replace(&mut self.loops, 0);
+		self.scopes.push_func();
+
+		let result = self.generate_func_code(name, def);
+
+		self.scopes.pop_func();
+		self.ret = ret;
+		self.loops = loops;
+
+		let (proto, body) = result?;
+		let export = self.exports.contains(&def.name);
+		if export && self.funcs.iter().any(|x| x.export && x.name == name) {
+			Err(format!(
+				"exported function `{}` is defined more than once",
+				def.name.as_str()
+			))?;
+		}
+
+		self.funcs.push(FuncCode {
+			name,
+			proto,
+			body,
+			export,
+			unit: self.unit,
+		});
+		Ok(())
+	}
+
+	fn generate_func_code(&mut self, name: &str, def: &'a FuncDef<'a>) -> Result<(String, String)> {
+		let mut proto = String::new();
+		self.decl(def.ret, &mut proto);
+		let _ = write!(proto, " {name}(");
+		for (n, it) in def.args.iter().enumerate() {
+			if it.kind == Kind::Void {
+				Err(format!("invalid void argument `{}`", it.name.as_str()))?;
+			}
+			if n > 0 {
+				proto.push_str(", ");
+			}
+			let arg = self.declare(it.name, it.kind);
+			self.decl(it.kind, &mut proto);
+			let _ = write!(proto, " {arg}");
+		}
+		if def.args.is_empty() {
+			proto.push_str("void");
+		}
+		proto.push(')');
+
+		let body = self.generate_body(def.body)?;
+		let mut code = String::new();
+		push_block(&mut code, &body);
+		code.push('\n');
+		Ok((proto, code))
+	}
+
+	/// Generate the body of a control structure. Blocks are inlined in the
+	/// structure body instead of being nested.
+	fn generate_body(&mut self, code: &'a Code<'a>) -> Result<String> {
+		match code {
+			Code::Block(list) => self.generate_scope(list),
+			Code::At(span, inner @ Code::Block(..)) => {
+				let mut body = line_directive(span);
+				body.push_str(&self.generate_body(inner)?);
+				Ok(body)
+			}
+			code => self.generate_scope(std::slice::from_ref(code)),
+		}
+	}
+
+	fn generate_cond(&mut self, code: &'a Code<'a>) -> Result<Func> {
+		let cond = code.generate_c(self)?;
+		match cond.kind {
+			Kind::Bool | Kind::I64 => Ok(cond),
+			kind => Err(format!("invalid condition of type {kind:?}"))?,
+		}
+	}
 }
 
 impl<'a> Code<'a> {
-	pub fn generate_c(&self, builder: &mut Builder) -> Result<Func> {
+	pub fn generate_c(&self, builder: &mut Builder<'a>) -> Result<Func> {
+		builder.checker.enter(self)?;
+		let out = self.generate_c_node(builder);
+		builder.checker.leave();
+		out
+	}
+
+	fn generate_c_node(&self, builder: &mut Builder<'a>) -> Result<Func> {
 		let out = match self {
 			Code::Int(v) => {
 				builder.include_system("inttypes.h");
 				Func::i64(*v)
 			}
-			Code::Str(v) => Func::str(v),
+			Code::BigInt(v) => {
+				builder.include_runtime(&runtime::INT);
+				Func::big_int(v)
+			}
+			Code::Str(v) => {
+				builder.include_runtime(&runtime::STR);
+				Func::str(v)
+			}
 			Code::Print(args) => {
 				builder.include_system("stdio.h");
 				let mut body = String::new();
 				let mut code = String::new();
 				let mut vals = String::new();
 				let mut empty = true;
-				code.push_str("printf(\"");
+
+				// some values are printed by the runtime, so the `printf` call
+				// is split around them
+				let flush = |body: &mut String, code: &mut String, vals: &mut String| {
+					if !code.is_empty() {
+						let _ = writeln!(body, "printf(\"{code}\"{vals});");
+						code.clear();
+						vals.clear();
+					}
+				};
+
 				for it in args.iter() {
 					let func = it.generate_c(builder)?;
+					if func.kind == Kind::Void {
+						func.push_stmt(&mut body);
+						continue;
+					}
 					body.push_str(&func.body);
 
-					let var = if func.expr.len() > 0 {
+					let var = if !func.expr.is_empty() {
 						let var = builder.var();
-						func.kind.decl(&mut body);
-						let _ = write!(body, " _${var}_ = {};\n", func.expr);
+						builder.decl(func.kind, &mut body);
+						let _ = writeln!(body, " _${var}_ = {};", func.expr);
 						var
 					} else {
 						0
 					};
 
-					if let Some(fmt) = func.kind.fmt() {
-						if !empty {
-							code.push(' ');
+					if !empty {
+						code.push(' ');
+					}
+					empty = false;
+
+					if let Some(print) = func.kind.print() {
+						flush(&mut body, &mut code, &mut vals);
+						if var > 0 {
+							let _ = writeln!(body, "{print}(_${var}_);");
 						}
-						empty = false;
+					} else if let Some(fmt) = func.kind.fmt() {
 						code.push_str(fmt);
-
 						if var > 0 {
-							let _ = write!(vals, ", _${var}_");
+							if func.kind == Kind::Bool {
+								let _ = write!(vals, ", _${var}_ ? \"true\" : \"false\"");
+							} else {
+								let _ = write!(vals, ", _${var}_");
+							}
 						}
 					}
 				}
-				code.push_str("\\n\"");
-				code.push_str(&vals);
-				code.push_str(");\n");
+				code.push_str("\\n");
+				flush(&mut body, &mut code, &mut vals);
+				Func::void(body)
+			}
+			Code::Let(name, value) => {
+				let value = value.generate_c(builder)?;
+				if value.kind == Kind::Void {
+					Err(format!("cannot declare `{}` with a void value", name.as_str()))?;
+				}
+
+				let mut body = value.body;
+				let var = builder.declare(*name, value.kind);
+				builder.decl(value.kind, &mut body);
+				let _ = writeln!(body, " {var} = {};", value.expr);
+				Func::void(body)
+			}
+			Code::Get(name) => {
+				let (var, kind) = builder.lookup(*name)?;
+				Func {
+					body: String::new(),
+					expr: var.to_string(),
+					kind,
+				}
+			}
+			Code::Set(name, value) => {
+				let (var, kind) = builder.lookup(*name)?;
+				let value = value.generate_c(builder)?;
+				if value.kind != kind {
+					Err(format!(
+						"cannot assign {:?} to `{}` of type {kind:?}",
+						value.kind,
+						name.as_str()
+					))?;
+				}
+
+				let mut body = value.body;
+				let _ = writeln!(body, "{var} = {};", value.expr);
+				Func::void(body)
+			}
+			Code::Binary(op, lhs, rhs) => generate_binary(builder, *op, lhs, rhs)?,
+			Code::Unary(op, arg) => {
+				let arg = arg.generate_c(builder)?;
+				let (expr, kind) = match (op, arg.kind) {
+					(UnaryOp::Neg, Kind::I64) => (format!("(-{})", arg.expr), Kind::I64),
+					(UnaryOp::Neg, Kind::BigInt) => (format!("bit_int_neg({})", arg.expr), Kind::BigInt),
+					(UnaryOp::Not, Kind::Bool | Kind::I64) => {
+						builder.include_system("stdbool.h");
+						(format!("(!{})", arg.expr), Kind::Bool)
+					}
+					(UnaryOp::Len | UnaryOp::Chars, Kind::Str) => {
+						builder.include_system("inttypes.h");
+						let func = if *op == UnaryOp::Len {
+							"bit_str_len"
+						} else {
+							"bit_str_chars"
+						};
+						(format!("{func}({})", arg.expr), Kind::I64)
+					}
+					(op, kind) => Err(format!("invalid operand for {op:?}: {kind:?}"))?,
+				};
+				Func {
+					body: arg.body,
+					expr,
+					kind,
+				}
+			}
+			Code::Block(list) => {
+				let mut body = String::new();
+				push_block(&mut body, &builder.generate_scope(list)?);
+				body.push('\n');
+				Func::void(body)
+			}
+			Code::If(cond, then, other) => {
+				let cond = builder.generate_cond(cond)?;
+				let then = builder.generate_body(then)?;
+
+				let mut body = cond.body;
+				let _ = write!(body, "if ({}) ", cond.expr);
+				push_block(&mut body, &then);
+				if let Some(other) = other {
+					let other = builder.generate_body(other)?;
+					body.push_str(" else ");
+					push_block(&mut body, &other);
+				}
+				body.push('\n');
+				Func::void(body)
+			}
+			Code::While(cond, code) => {
+				let cond = builder.generate_cond(cond)?;
+
+				builder.loops += 1;
+				let code = builder.generate_body(code);
+				builder.loops -= 1;
+				let code = code?;
+
+				let mut body = String::new();
+				if !cond.body.is_empty() {
+					// conditions with statements are evaluated inside the loop
+					// so that they run again on every iteration
+					let mut inner = cond.body;
+					let _ = writeln!(inner, "if (!({})) break;", cond.expr);
+					inner.push_str(&code);
+					body.push_str("while (1) ");
+					push_block(&mut body, &inner);
+				} else {
+					let _ = write!(body, "while ({}) ", cond.expr);
+					push_block(&mut body, &code);
+				}
+				body.push('\n');
+				Func::void(body)
+			}
+			Code::Break | Code::Continue => {
+				if builder.loops == 0 {
+					Err(format!("{self:?} outside of a loop"))?;
+				}
+				let body = if let Code::Break = self {
+					"break;\n"
+				} else {
+					"continue;\n"
+				};
+				Func::void(body.to_string())
+			}
+			Code::Return(value) => {
+				let value = match value {
+					Some(value) => Some(value.generate_c(builder)?),
+					None => None,
+				};
+				let kind = value.as_ref().map(|x| x.kind).unwrap_or(Kind::Void);
+
+				let mut body = String::new();
+				match (builder.ret, value) {
+					(None, None) => body.push_str("return 0;\n"),
+					(None, Some(value)) if kind == Kind::I64 => {
+						body.push_str(&value.body);
+						let _ = writeln!(body, "return (int){};", value.expr);
+					}
+					(Some(Kind::Void), None) => body.push_str("return;\n"),
+					(Some(ret), Some(value)) if ret == kind => {
+						body.push_str(&value.body);
+						let _ = writeln!(body, "return {};", value.expr);
+					}
+					(None, _) => Err(format!("cannot return {kind:?} from main"))?,
+					(Some(ret), _) => Err(format!("cannot return {kind:?} from function returning {ret:?}"))?,
+				}
+				Func::void(body)
+			}
+			Code::Func(def) => {
+				builder.generate_func(def)?;
+				Func::void(String::new())
+			}
+			Code::At(span, code) => {
+				let mut func = code.generate_c(builder)?;
+				if !func.body.is_empty() {
+					func.body.insert_str(0, &line_directive(span));
+				}
+				func
+			}
+			Code::Call(name, args) => {
+				let (func, def) = builder.lookup_func(*name)?;
+				if args.len() != def.args.len() {
+					Err(format!(
+						"`{}` expects {} arguments, but got {}",
+						name.as_str(),
+						def.args.len(),
+						args.len()
+					))?;
+				}
+
+				let mut list = Vec::new();
+				for (arg, param) in args.iter().zip(def.args.iter()) {
+					let arg = arg.generate_c(builder)?;
+					if arg.kind != param.kind {
+						Err(format!(
+							"invalid argument `{}` for `{}`: expected {:?}, got {:?}",
+							param.name.as_str(),
+							name.as_str(),
+							param.kind,
+							arg.kind
+						))?;
+					}
+					list.push(arg);
+				}
+
+				// arguments must be evaluated in order, so if any argument
+				// needs statements, store all of them in temporaries
+				let mut body = String::new();
+				let mut expr = format!("{func}(");
+				let temps = list.iter().any(|x| !x.body.is_empty());
+				for (n, arg) in list.into_iter().enumerate() {
+					if n > 0 {
+						expr.push_str(", ");
+					}
+					body.push_str(&arg.body);
+					if temps {
+						expr.push_str(&builder.temp(&mut body, arg.kind, &arg.expr));
+					} else {
+						expr.push_str(&arg.expr);
+					}
+				}
+				expr.push(')');
 
-				body.push_str(&code);
 				Func {
 					body,
-					expr: String::new(),
-					kind: Kind::Void,
+					expr,
+					kind: def.ret,
 				}
 			}
 		};
@@ -245,9 +776,140 @@ impl<'a> Code<'a> {
 	}
 }
 
+fn generate_binary<'a>(builder: &mut Builder<'a>, op: BinaryOp, lhs: &Code<'a>, rhs: &Code<'a>) -> Result<Func> {
+	let lhs = lhs.generate_c(builder)?;
+	let rhs = rhs.generate_c(builder)?;
+	let Some(kind) = binary_kind(op, lhs.kind, rhs.kind) else {
+		Err(format!(
+			"invalid operands for {op:?}: {:?} and {:?}",
+			lhs.kind, rhs.kind
+		))?
+	};
+	if kind == Kind::Bool {
+		builder.include_system("stdbool.h");
+	}
+
+	let mut body = lhs.body;
+	if op.is_logic() {
+		let c_op = if op == BinaryOp::And { "&&" } else { "||" };
+		if rhs.body.is_empty() {
+			let expr = format!("({} {c_op} {})", lhs.expr, rhs.expr);
+			return Ok(Func {
+				body,
+				expr,
+				kind: Kind::Bool,
+			});
+		}
+
+		// the right side has statements, so short-circuit explicitly
+		let var = builder.temp(&mut body, Kind::Bool, &lhs.expr);
+		let mut inner = rhs.body;
+		let _ = writeln!(inner, "{var} = {};", rhs.expr);
+		let cond = if op == BinaryOp::And { "" } else { "!" };
+		let _ = write!(body, "if ({cond}{var}) ");
+		push_block(&mut body, &inner);
+		body.push('\n');
+		return Ok(Func {
+			body,
+			expr: var,
+			kind: Kind::Bool,
+		});
+	}
+
+	// make sure the left side is evaluated before any statement on the right
+	let mut lhs_expr = if !rhs.body.is_empty() {
+		builder.temp(&mut body, lhs.kind, &lhs.expr)
+	} else {
+		lhs.expr
+	};
+	body.push_str(&rhs.body);
+
+	let c_op = match op {
+		BinaryOp::Add => "+",
+		BinaryOp::Sub => "-",
+		BinaryOp::Mul => "*",
+		BinaryOp::Div => "/",
+		BinaryOp::Mod => "%",
+		BinaryOp::Eq => "==",
+		BinaryOp::Ne => "!=",
+		BinaryOp::Lt => "<",
+		BinaryOp::Le => "<=",
+		BinaryOp::Gt => ">",
+		BinaryOp::Ge => ">=",
+		BinaryOp::And | BinaryOp::Or => unreachable!(),
+	};
+
+	// integers are promoted when mixed with big integers
+	let mut rhs_expr = rhs.expr;
+	let big = lhs.kind == Kind::BigInt || rhs.kind == Kind::BigInt;
+	if big && lhs.kind == Kind::I64 {
+		lhs_expr = format!("bit_int_from_i64({lhs_expr})");
+	}
+	if big && rhs.kind == Kind::I64 {
+		rhs_expr = format!("bit_int_from_i64({rhs_expr})");
+	}
+
+	let expr = if lhs.kind == Kind::Str && op == BinaryOp::Add {
+		format!("bit_str_concat({lhs_expr}, {rhs_expr})")
+	} else if lhs.kind == Kind::Str {
+		format!("(bit_str_cmp({lhs_expr}, {rhs_expr}) {c_op} 0)")
+	} else if big && op.is_compare() {
+		format!("(bit_int_cmp({lhs_expr}, {rhs_expr}) {c_op} 0)")
+	} else if big {
+		let func = match op {
+			BinaryOp::Add => "bit_int_add",
+			BinaryOp::Sub => "bit_int_sub",
+			_ => "bit_int_mul",
+		};
+		format!("{func}({lhs_expr}, {rhs_expr})")
+	} else {
+		format!("({lhs_expr} {c_op} {rhs_expr})")
+	};
+
+	Ok(Func { body, expr, kind })
+}
+
+/// Name of the generated source file used by the [`Runner`].
+const SOURCE_NAME: &str = "main.c";
+
+/// Return a `#line` directive mapping the next line to the span location.
+fn line_directive(span: &Span) -> String {
+	let line = span.line();
+	let file = c_string(span.src.name());
+	format!("#line {line} {file}\n")
+}
+
+/// If the chunk appended to the output has line directives, restore the
+/// output location to the generated source.
+fn reset_line(out: &mut String, chunk: &str, file: &str) {
+	if chunk.contains("#line ") {
+		let line = out.bytes().filter(|&x| x == b'\n').count() + 2;
+		let file = c_string(file);
+		let _ = writeln!(out, "#line {line} {file}");
+	}
+}
+
+/// Append the code as an indented C block, without a trailing newline.
+fn push_block(out: &mut String, code: &str) {
+	out.push_str("{\n");
+	for line in code.lines() {
+		if !line.is_empty() {
+			out.push('\t');
+		}
+		out.push_str(line);
+		out.push('\n');
+	}
+	out.push('}');
+}
+
 #[derive(Default)]
 pub struct Runner {
 	code: String,
+	toolchain: Toolchain,
+	cache: Option<Arc<Cache>>,
+	diagnostics: Vec<Diagnostic>,
+	sanitize: bool,
+	findings: Vec<Finding>,
 }
 
 impl Runner {
@@ -259,6 +921,40 @@ impl Runner {
 		self.code.push_str(code.as_ref())
 	}
 
+	pub fn code(&self) -> &str {
+		&self.code
+	}
+
+	pub fn toolchain(&self) -> &Toolchain {
+		&self.toolchain
+	}
+
+	pub fn set_toolchain(&mut self, toolchain: Toolchain) {
+		self.toolchain = toolchain;
+	}
+
+	/// Use a build cache to skip compiling programs that were already
+	/// compiled with the same toolchain.
+	pub fn set_cache(&mut self, cache: Arc<Cache>) {
+		self.cache = Some(cache);
+	}
+
+	/// Diagnostics generated by the last compilation.
+	pub fn diagnostics(&self) -> &[Diagnostic] {
+		&self.diagnostics
+	}
+
+	/// Build with debug information and the address and undefined behavior
+	/// sanitizers. Programs with sanitizer findings fail to run.
+	pub fn set_sanitize(&mut self, sanitize: bool) {
+		self.sanitize = sanitize;
+	}
+
+	/// Sanitizer findings from the last execution.
+	pub fn findings(&self) -> &[Finding] {
+		&self.findings
+	}
+
 	pub fn run(&mut self) -> Result<ExitStatus> {
 		let (dir, path) = match self.compile() {
 			Ok(res) => res,
@@ -268,84 +964,187 @@ impl Runner {
 			}
 		};
 
+		for it in self.diagnostics.iter() {
+			term::Stream::Stderr.output(term::YELLOW, format!("CC: {it}\n"))?;
+		}
+
 		let mut cmd = cmd::new(path).cwd(dir.path());
-		cmd.output(|out| {
+		for (name, value) in self.env() {
+			cmd = cmd.env(name, value);
+		}
+
+		let stderr = Arc::new(Mutex::new(String::new()));
+		let output = stderr.clone();
+		let status = dir.on_failure(cmd.lines(move |out| {
 			match out {
-				cmd::Output::StdErr(err) => error(err),
+				cmd::Output::StdErr(err) => {
+					output.lock().unwrap().push_str(&err);
+					error(err)
+				}
 				cmd::Output::StdOut(out) => {
 					let color = term::GREEN;
-					term::output(std::io::stdout(), color, out)?;
+					term::Stream::Stdout.output(color, out)?;
 				}
 			}
 			Ok(())
-		})
+		}))?;
+
+		let stderr = std::mem::take(&mut *stderr.lock().unwrap());
+		dir.on_failure(self.check_findings(&stderr, dir.path()))?;
+		dir.keep_if_failed(!status.success());
+		Ok(status)
 	}
 
 	pub fn execute(&mut self) -> Result<Output> {
 		let (dir, path) = self.compile()?;
 		let exe = Command::new(path)
 			.current_dir(dir.path())
+			.envs(self.env())
 			.stderr(Stdio::piped())
 			.stdout(Stdio::piped())
 			.spawn()?;
 
-		let out = exe.wait_with_output()?;
+		let out = dir.on_failure(exe.wait_with_output().map_err(Into::into))?;
+		dir.on_failure(self.check_findings(&String::from_utf8_lossy(&out.stderr), dir.path()))?;
+		Ok(out)
+	}
+
+	/// Compile and execute the program with the given options.
+	pub fn execute_with(&mut self, options: &ExecOptions) -> Result<ExecResult> {
+		let (dir, path) = self.compile()?;
+		let mut options = options.clone();
+		for (name, value) in self.env() {
+			options = options.env(name, value);
+		}
+
+		let out = dir.on_failure(exec(path, dir.path(), &options))?;
+		dir.on_failure(self.check_findings(&String::from_utf8_lossy(&out.stderr), dir.path()))?;
 		Ok(out)
 	}
 
 	pub fn compile(&mut self) -> Result<(temp::Dir, PathBuf)> {
-		let dir = temp::dir()?;
+		let dir = temp::dir_with("bit_c")?;
+		let path = PathBuf::from("./main.exe");
 
-		let mut src = dir.file("main.c")?;
-		src.write(&self.code)?;
-		let src = src.into_path();
+		let toolchain = self.build_toolchain();
+		let key = self.cache.as_ref().map(|cache| cache.key(&self.code, &toolchain));
+		if let (Some(cache), Some(key)) = (&self.cache, &key) {
+			if let Some(entry) = cache.get(key)? {
+				// link the executable, so that it is not affected by the entry
+				// being evicted while we are still using it, and compile it
+				// again if it was already evicted
+				let exe = dir.path().join(&path);
+				if std::fs::hard_link(&entry.exe, &exe).is_ok() || std::fs::copy(&entry.exe, &exe).is_ok() {
+					self.diagnostics = Diagnostic::parse(&entry.log);
+					return Ok((dir, path));
+				}
+			}
+		}
 
-		let gcc = Command::new("gcc")
-			.current_dir(dir.path())
-			.arg(&src)
-			.arg("-o")
-			.arg("main.exe")
-			.stderr(Stdio::piped())
-			.stdout(Stdio::piped())
-			.spawn()?;
+		dir.file(SOURCE_NAME)?.write(&self.code)?;
 
-		let gcc = gcc.wait_with_output()?;
+		self.diagnostics.clear();
+		let cc = toolchain.command(SOURCE_NAME, &path);
+		let stderr = dir.on_failure(self.run_tool(cc, dir.path()))?;
 
-		let mut errs = String::new();
-		if !gcc.status.success() {
-			let _ = write!(errs, "CC: exited with status {}", gcc.status);
+		if let (Some(cache), Some(key)) = (&self.cache, &key) {
+			cache.put(key, dir.path().join(&path), &stderr)?;
 		}
 
-		if gcc.stderr.len() > 0 {
-			let stderr = std::str::from_utf8(&gcc.stderr)?.trim();
-			if stderr.len() > 0 {
-				if errs.len() > 0 {
-					errs.push_str("\n\n");
-					let _ = write!(
-						errs,
-						"CC: command generated error output\n\n  | {}\n",
-						indent_with(stderr, "  | ")
-					);
-				} else {
-					error(format!(
-						"CC: command generated error output\n\n  | {}\n\n",
-						indent_with(stderr, "  | ")
-					));
-				}
+		Ok((dir, path))
+	}
+
+	fn build_toolchain(&self) -> Toolchain {
+		let mut toolchain = self.toolchain.clone();
+		if self.sanitize {
+			for it in SANITIZE_FLAGS {
+				toolchain = toolchain.flag(it);
 			}
 		}
+		toolchain
+	}
+
+	fn env(&self) -> Vec<(&'static str, &'static str)> {
+		if self.sanitize {
+			SANITIZE_ENV.to_vec()
+		} else {
+			Vec::new()
+		}
+	}
+
+	/// Parse sanitizer findings from the program error output, failing if
+	/// there are any.
+	fn check_findings(&mut self, stderr: &str, dir: &Path) -> Result<()> {
+		self.findings.clear();
+		if !self.sanitize {
+			return Ok(());
+		}
+
+		let dir = dir.canonicalize()?;
+		self.findings = Finding::parse(stderr);
+		for it in self.findings.iter_mut() {
+			it.strip_dir(&dir);
+		}
+
+		if self.findings.is_empty() {
+			return Ok(());
+		}
+
+		let mut errs = format!("sanitizer reported {} finding(s)\n", self.findings.len());
+		for it in self.findings.iter() {
+			let _ = write!(errs, "\n  | {it}");
+			for frame in it.frames.iter() {
+				let _ = write!(errs, "\n  |     {frame}");
+			}
+			errs.push('\n');
+		}
+		Err(errs)?
+	}
+
+	/// Run a toolchain command, collecting its diagnostics and returning
+	/// the error output.
+	fn run_tool(&mut self, mut cmd: Command, dir: &Path) -> Result<String> {
+		let name = cmd.get_program().to_string_lossy().to_string();
+		let out = cmd
+			.current_dir(dir)
+			.stderr(Stdio::piped())
+			.stdout(Stdio::piped())
+			.spawn()
+			.map_err(|err| format!("CC: could not run `{name}`: {err}"))?;
 
-		if errs.len() > 0 {
+		let out = out.wait_with_output()?;
+		let stderr = String::from_utf8_lossy(&out.stderr).to_string();
+		let diagnostics = Diagnostic::parse(&stderr);
+
+		if !out.status.success() {
+			let mut errs = format!("CC: `{name}` exited with status {}", out.status);
+			let stderr = stderr.trim();
+			if !diagnostics.is_empty() {
+				errs.push('\n');
+				for it in diagnostics.iter() {
+					let _ = write!(errs, "\n  | {it}");
+				}
+				errs.push('\n');
+			} else if !stderr.is_empty() {
+				let _ = write!(
+					errs,
+					"\n\nCC: command generated error output\n\n  | {}\n",
+					indent_with(stderr, "  | ")
+				);
+			}
+			self.diagnostics.extend(diagnostics);
 			return Err(errs)?;
 		}
 
-		let path = PathBuf::from("./main.exe");
-		Ok((dir, path))
+		self.diagnostics.extend(diagnostics);
+		Ok(stderr)
 	}
 }
 
 #[cfg(test)]
 mod tests {
+	use std::time::Duration;
+
 	use super::*;
 
 	#[test]
@@ -368,6 +1167,382 @@ mod tests {
 		Ok(())
 	}
 
+	#[test]
+	fn variables_and_arithmetic() -> Result<()> {
+		let store = Store::new();
+		let code = Code::Block(store.add_list([
+			Code::Let(store.sym("x"), store.add(Code::Int(10))),
+			Code::Let(store.sym("y"), store.add(Code::Int(4))),
+			Code::Let(store.sym("z"), store.add(Code::Int(2))),
+			Code::Let(
+				store.sym("ans"),
+				store.add(Code::Binary(
+					BinaryOp::Add,
+					store.add(Code::Binary(
+						BinaryOp::Mul,
+						store.add(Code::Get(store.sym("x"))),
+						store.add(Code::Get(store.sym("y"))),
+					)),
+					store.add(Code::Get(store.sym("z"))),
+				)),
+			),
+			Code::Print(store.add_list([
+				Code::Str(store.str("The answer to life, the universe, and everything is")),
+				Code::Get(store.sym("ans")),
+			])),
+		]));
+
+		let out = execute(&store, code)?;
+		assert_eq!(out, "The answer to life, the universe, and everything is 42\n");
+		Ok(())
+	}
+
+	#[test]
+	fn strings() -> Result<()> {
+		let store = Store::new();
+		let str = |v| &*store.add(Code::Str(store.str(v)));
+		let get = |name| &*store.add(Code::Get(store.sym(name)));
+		let op = |op, a, b| Code::Binary(op, a, b);
+		let unary = |op, a| Code::Unary(op, a);
+
+		let code = Code::Block(store.add_list([
+			Code::Let(store.sym("a"), str("a\0b")),
+			Code::Let(
+				store.sym("s"),
+				store.add(op(BinaryOp::Add, get("a"), str("-çã\u{1F600}"))),
+			),
+			Code::Print(store.add_list([
+				Code::Get(store.sym("s")),
+				unary(UnaryOp::Len, get("s")),
+				unary(UnaryOp::Chars, get("s")),
+			])),
+			Code::Print(store.add_list([
+				op(BinaryOp::Eq, get("a"), str("a\0b")),
+				op(BinaryOp::Eq, get("a"), str("a")),
+				op(BinaryOp::Lt, str("a"), get("a")),
+				op(BinaryOp::Gt, str("b"), get("a")),
+			])),
+			Code::Print(store.add_list([*str("\x01\x7F?\"\\\t"), *str("\u{E9}A")])),
+		]));
+
+		let out = execute(&store, code)?;
+		assert_eq!(out, "a\0b-çã\u{1F600} 12 7\ntrue false true true\n\x01\x7F?\"\\\t éA\n");
+
+		// a string parameter only passed through still needs the runtime
+		let echo = FuncDef {
+			name: store.sym("echo"),
+			args: store.add_list([Param {
+				name: store.sym("s"),
+				kind: Kind::Str,
+			}]),
+			ret: Kind::Str,
+			body: store.add(Code::Return(Some(get("s")))),
+		};
+		let code =
+			Code::Block(store.add_list([Code::Func(store.add(echo)), Code::Print(store.add_list([Code::Int(1)]))]));
+		assert_eq!(execute(&store, code)?, "1\n");
+		Ok(())
+	}
+
+	#[test]
+	fn big_integers() -> Result<()> {
+		let store = Store::new();
+		let int = |v| &*store.add(Code::Int(v));
+		let big = |v| -> Result<&Code> {
+			let limbs = int::parse_int(v, 10)?;
+			Ok(store.add(Code::BigInt(store.add_list(limbs))))
+		};
+		let get = |name| &*store.add(Code::Get(store.sym(name)));
+		let op = |op, a, b| Code::Binary(op, a, b);
+		let neg = |a| &*store.add(Code::Unary(UnaryOp::Neg, a));
+
+		let code = Code::Block(store.add_list([
+			Code::Print(store.add_list([op(
+				BinaryOp::Add,
+				int(1),
+				big("340282366920938463463374607431768211455")?,
+			)])),
+			Code::Let(store.sym("b"), big("123456789012345678901234567890")?),
+			Code::Let(store.sym("c"), big("987654321098765432109876543210")?),
+			Code::Print(store.add_list([
+				op(BinaryOp::Mul, get("b"), get("c")),
+				op(BinaryOp::Sub, int(5), get("b")),
+			])),
+			Code::Print(store.add_list([op(
+				BinaryOp::Sub,
+				store.add(op(BinaryOp::Mul, neg(get("b")), int(1_000_000_000))),
+				int(7),
+			)])),
+			Code::Print(store.add_list([
+				*big("0")?,
+				op(BinaryOp::Add, get("b"), neg(get("b"))),
+				Code::BigInt(store.add_list([0, 0, 1])),
+				op(BinaryOp::Gt, get("c"), get("b")),
+				op(BinaryOp::Lt, neg(get("b")), int(i64::MIN)),
+				op(
+					BinaryOp::Eq,
+					int(i64::MIN),
+					store.add(op(BinaryOp::Sub, int(-1), big("9223372036854775807")?)),
+				),
+			])),
+		]));
+
+		let out = execute(&store, code)?;
+		assert_eq!(
+			out.trim_end(),
+			text(
+				"
+					340282366920938463463374607431768211456
+					121932631137021795226185032733622923332237463801111263526900 -123456789012345678901234567885
+					-123456789012345678901234567890000000007
+					0 0 18446744073709551616 true true true
+				"
+			)
+		);
+		Ok(())
+	}
+
+	#[test]
+	fn control_flow() -> Result<()> {
+		let store = Store::new();
+		let int = |v| &*store.add(Code::Int(v));
+		let get = |name| &*store.add(Code::Get(store.sym(name)));
+		let op = |op, a, b| &*store.add(Code::Binary(op, a, b));
+		let set = |name, v| Code::Set(store.sym(name), v);
+
+		// sum odd numbers up to 9
+		let loop_body = Code::Block(store.add_list([
+			set("i", op(BinaryOp::Add, get("i"), int(1))),
+			Code::If(
+				op(BinaryOp::Eq, op(BinaryOp::Mod, get("i"), int(2)), int(0)),
+				store.add(Code::Continue),
+				None,
+			),
+			Code::If(op(BinaryOp::Gt, get("i"), int(9)), store.add(Code::Break), None),
+			set("sum", op(BinaryOp::Add, get("sum"), get("i"))),
+		]));
+
+		let code = Code::Block(store.add_list([
+			Code::Let(store.sym("i"), int(0)),
+			Code::Let(store.sym("sum"), int(0)),
+			Code::While(op(BinaryOp::Lt, get("i"), int(100)), store.add(loop_body)),
+			Code::Print(store.add_list([Code::Str(store.str("sum:")), *get("sum")])),
+			Code::Print(store.add_list([
+				*op(BinaryOp::Ge, get("i"), int(10)),
+				Code::Unary(UnaryOp::Not, op(BinaryOp::Ne, get("sum"), int(25))),
+				Code::Unary(UnaryOp::Neg, get("i")),
+			])),
+			Code::Block(store.add_list([
+				Code::Let(store.sym("i"), store.add(Code::Str(store.str("inner")))),
+				Code::Print(store.add_list([*get("i")])),
+			])),
+			Code::If(
+				op(BinaryOp::Eq, get("i"), int(-1)),
+				store.add(Code::Print(store.add_list([Code::Str(store.str("then"))]))),
+				Some(store.add(Code::Print(store.add_list([Code::Str(store.str("else"))])))),
+			),
+		]));
+
+		let out = execute(&store, code)?;
+		assert_eq!(out, "sum: 25\ntrue true -11\ninner\nelse\n");
+		Ok(())
+	}
+
+	#[test]
+	fn recursive_functions() -> Result<()> {
+		let store = Store::new();
+		let int = |v| &*store.add(Code::Int(v));
+		let get = |name| &*store.add(Code::Get(store.sym(name)));
+		let op = |op, a, b| &*store.add(Code::Binary(op, a, b));
+		let ret = |v| Code::Return(Some(v));
+		let param = |name, kind| Param {
+			name: store.sym(name),
+			kind,
+		};
+
+		let fib = FuncDef {
+			name: store.sym("fib"),
+			args: store.add_list([param("n", Kind::I64)]),
+			ret: Kind::I64,
+			body: store.add(Code::Block(store.add_list([
+				Code::If(op(BinaryOp::Lt, get("n"), int(2)), store.add(ret(get("n"))), None),
+				ret(op(
+					BinaryOp::Add,
+					store.add(call(&store, "fib", &[*op(BinaryOp::Sub, get("n"), int(1))])),
+					store.add(call(&store, "fib", &[*op(BinaryOp::Sub, get("n"), int(2))])),
+				)),
+			]))),
+		};
+
+		let is_even = FuncDef {
+			name: store.sym("is_even"),
+			args: store.add_list([param("n", Kind::I64)]),
+			ret: Kind::Bool,
+			body: store.add(Code::Block(store.add_list([
+				Code::If(
+					op(BinaryOp::Eq, get("n"), int(0)),
+					store.add(ret(op(BinaryOp::Eq, int(0), int(0)))),
+					None,
+				),
+				ret(store.add(call(&store, "is_odd", &[*op(BinaryOp::Sub, get("n"), int(1))]))),
+			]))),
+		};
+
+		let is_odd = FuncDef {
+			name: store.sym("is_odd"),
+			args: store.add_list([param("n", Kind::I64)]),
+			ret: Kind::Bool,
+			body: store.add(Code::Block(store.add_list([
+				Code::If(
+					op(BinaryOp::Eq, get("n"), int(0)),
+					store.add(ret(op(BinaryOp::Ne, int(0), int(0)))),
+					None,
+				),
+				ret(store.add(call(&store, "is_even", &[*op(BinaryOp::Sub, get("n"), int(1))]))),
+			]))),
+		};
+
+		let say = FuncDef {
+			name: store.sym("say"),
+			args: store.add_list([param("msg", Kind::Str)]),
+			ret: Kind::Void,
+			body: store.add(Code::Print(store.add_list([*get("msg")]))),
+		};
+
+		let code = Code::Block(store.add_list([
+			Code::Print(store.add_list([Code::Str(store.str("fib(30) =")), call(&store, "fib", &[*int(30)])])),
+			Code::Print(store.add_list([call(&store, "is_even", &[*int(10)]), call(&store, "is_odd", &[*int(7)])])),
+			call(&store, "say", &[Code::Str(store.str("done"))]),
+			Code::Func(store.add(fib)),
+			Code::Func(store.add(is_even)),
+			Code::Func(store.add(is_odd)),
+			Code::Func(store.add(say)),
+		]));
+
+		let out = execute(&store, code)?;
+		assert_eq!(out, "fib(30) = 832040\ntrue true\ndone\n");
+		Ok(())
+	}
+
+	#[test]
+	fn invalid_calls() -> Result<()> {
+		let store = Store::new();
+		let func = |name, var| {
+			let arg = Param {
+				name: store.sym("n"),
+				kind: Kind::I64,
+			};
+			let body = Code::Return(Some(store.add(Code::Get(store.sym(var)))));
+			&*store.add(FuncDef {
+				name: store.sym(name),
+				args: store.add_list([arg]),
+				ret: Kind::I64,
+				body: store.add(body),
+			})
+		};
+
+		let generate = |code: &[Code]| {
+			let mut builder = Builder::new(&store);
+			let code = Code::Block(store.add_slice(code));
+			code.generate_c(&mut builder).err().map(|x| x.to_string())
+		};
+
+		let f = func("f", "n");
+		let call = |args| call(&store, "f", args);
+
+		let err = generate(&[Code::Func(f), call(&[])]);
+		assert_eq!(err.unwrap(), "`f` expects 1 arguments, but got 0");
+
+		let err = generate(&[Code::Func(f), call(&[Code::Str("a")])]);
+		assert!(err.unwrap().starts_with("invalid argument `n` for `f`"));
+
+		let err = generate(&[Code::Func(f), Code::Func(func("f", "n"))]);
+		assert_eq!(err.unwrap(), "function `f` is already defined");
+
+		// functions cannot access variables from the outer scope
+		let let_x = Code::Let(store.sym("x"), store.add(Code::Int(1)));
+		let err = generate(&[let_x, Code::Func(func("g", "x"))]);
+		assert_eq!(err.unwrap(), "cannot access `x` from an inner function");
+
+		Ok(())
+	}
+
+	#[test]
+	fn line_directives() -> Result<()> {
+		let store = Store::new();
+		let src = store.load_string("test.bit", "let x = 1\nprint 'hello'\n");
+		let line1 = src.span().slice(0..9);
+		let line2 = src.span().slice(10..23);
+		assert_eq!((line2.line(), line2.column()), (2, 1));
+
+		let code = Code::Block(store.add_list([
+			Code::At(line1, store.add(Code::Let(store.sym("x"), store.add(Code::Int(1))))),
+			Code::At(
+				line2,
+				store.add(Code::Print(store.add_list([Code::Str(store.str("hello"))]))),
+			),
+		]));
+
+		let mut builder = Builder::new(&store);
+		let func = code.generate_c(&mut builder)?;
+		let mut runner = builder.build(func);
+		assert!(runner
+			.code
+			.contains("\t\t#line 1 \"test.bit\"\n\t\tint64_t x_0_ = 1;\n"));
+		assert!(runner.code.contains("\t\t#line 2 \"test.bit\"\n"));
+
+		// the location is restored after the generated code
+		let lines = runner.code.lines().collect::<Vec<_>>();
+		let (n, last) = lines
+			.iter()
+			.enumerate()
+			.rev()
+			.find(|x| x.1.starts_with("#line"))
+			.unwrap();
+		assert_eq!(*last, format!("#line {} \"main.c\"", n + 2));
+
+		runner.set_toolchain(Toolchain::gcc().flag("-Wall"));
+		let out = runner.execute()?;
+		assert_eq!(String::from_utf8(out.stdout)?, "hello\n");
+
+		let diag = runner.diagnostics();
+		assert_eq!(diag.len(), 1);
+		assert_eq!(diag[0].file, "test.bit");
+		assert_eq!(diag[0].line, Some(1));
+		assert!(diag[0].message.contains("unused variable"));
+		Ok(())
+	}
+
+	#[test]
+	fn invalid_code() {
+		let store = Store::new();
+		let mut builder = Builder::new(&store);
+
+		let code = Code::Get(store.sym("x"));
+		let err = code.generate_c(&mut builder).err().unwrap();
+		assert_eq!(err.to_string(), "undeclared variable `x`");
+
+		let code = Code::Break;
+		assert!(code.generate_c(&mut builder).is_err());
+
+		let code = Code::Binary(BinaryOp::Add, store.add(Code::Int(1)), store.add(Code::Str("a")));
+		assert!(code.generate_c(&mut builder).is_err());
+	}
+
+	fn call<'a>(store: &'a Store, name: &str, args: &[Code<'a>]) -> Code<'a> {
+		Code::Call(store.sym(name), store.add_slice(args))
+	}
+
+	fn execute<'a>(store: &'a Store, code: Code<'a>) -> Result<String> {
+		let mut builder = Builder::new(store);
+		let func = code.generate_c(&mut builder)?;
+		let mut runner = builder.build(func);
+		let out = runner.execute()?;
+		assert!(out.status.success());
+		let stdout = String::from_utf8(out.stdout)?;
+		Ok(stdout)
+	}
+
 	#[test]
 	#[cfg(off)]
 	fn compile_and_run() -> Result<()> {
@@ -390,6 +1565,213 @@ mod tests {
 		Ok(())
 	}
 
+	#[test]
+	fn compiler_diagnostics() -> Result<()> {
+		let mut main = Runner::new();
+		main.set_toolchain(Toolchain::gcc().flag("-Wall"));
+		main.append(text(
+			r#"
+				int main(int argc, char *argv[]) {
+					int x;
+					return 0;
+				}
+			"#,
+		));
+
+		let out = main.execute()?;
+		assert!(out.status.success());
+
+		let diag = main.diagnostics();
+		assert_eq!(diag.len(), 1);
+		assert_eq!(diag[0].file, "main.c");
+		assert_eq!(diag[0].line, Some(2));
+		assert_eq!(diag[0].severity, Severity::Warning);
+		assert!(diag[0].message.contains("unused variable"));
+
+		let mut main = Runner::new();
+		main.set_toolchain(Toolchain::gcc());
+		main.append("int main() { return x; }\n");
+
+		let err = main.execute().unwrap_err().to_string();
+		assert!(err.contains("main.c:1:21: error:"));
+		assert_eq!(main.diagnostics()[0].severity, Severity::Error);
+
+		Ok(())
+	}
+
+	#[test]
+	fn toolchain_options() -> Result<()> {
+		let mut main = Runner::new();
+		main.set_toolchain(Toolchain::gcc().opt("2").std("c99").lib("m"));
+		main.append(text(
+			r#"
+				#include <math.h>
+				#include <stdio.h>
+
+				int main(int argc, char *argv[]) {
+					volatile double x = 2.0;
+					printf("%.3f\n", sqrt(x));
+					return 0;
+				}
+			"#,
+		));
+
+		let out = main.execute()?;
+		assert!(out.status.success());
+		assert_eq!(String::from_utf8(out.stdout)?, "1.414\n");
+		assert_eq!(main.diagnostics(), []);
+
+		let mut main = Runner::new();
+		main.set_toolchain(Toolchain::new("bit-missing-cc"));
+		main.append("int main() { return 0; }\n");
+		let err = main.execute().unwrap_err().to_string();
+		assert!(err.starts_with("CC: could not run `bit-missing-cc`"));
+
+		Ok(())
+	}
+
+	#[test]
+	fn cached_compilation() -> Result<()> {
+		let dir = temp::dir()?;
+		let cache = Arc::new(Cache::new(dir.path(), Cache::DEFAULT_SIZE)?);
+
+		let code = "#include <stdio.h>\nint main() { int x; printf(\"cached\\n\"); }\n";
+		for _ in 0..3 {
+			let mut main = Runner::new();
+			main.set_toolchain(Toolchain::gcc().flag("-Wall"));
+			main.set_cache(cache.clone());
+			main.append(code);
+
+			let out = main.execute()?;
+			assert_eq!(String::from_utf8(out.stdout)?, "cached\n");
+			assert_eq!(main.diagnostics().len(), 1);
+		}
+
+		let stats = cache.stats();
+		assert_eq!(stats.misses, 1);
+		assert_eq!(stats.hits, 2);
+		Ok(())
+	}
+
+	#[test]
+	fn execute_with_options() -> Result<()> {
+		let mut main = Runner::new();
+		main.append(text(
+			r#"
+				#include <ctype.h>
+				#include <stdio.h>
+				#include <stdlib.h>
+
+				int main(int argc, char *argv[]) {
+					for (int i = 1; i < argc; i++) {
+						printf("arg: %s\n", argv[i]);
+					}
+					printf("env: %s\n", getenv("BIT_TEST_VAR"));
+					int c;
+					while ((c = getchar()) != EOF) {
+						putchar(toupper(c));
+					}
+					fprintf(stderr, "done");
+					return 3;
+				}
+			"#,
+		));
+
+		let opts = ExecOptions::new()
+			.arg("a")
+			.arg("b c")
+			.env("BIT_TEST_VAR", "value")
+			.stdin("some input\n");
+		let out = main.execute_with(&opts)?;
+
+		assert!(!out.success());
+		assert!(!out.timed_out);
+		assert!(!out.truncated);
+		assert_eq!(out.code, Some(3));
+		assert_eq!(out.signal, None);
+		assert_eq!(out.stdout()?, "arg: a\narg: b c\nenv: value\nSOME INPUT\n");
+		assert_eq!(out.stderr()?, "done");
+
+		let opts = ExecOptions::new()
+			.env("BIT_TEST_VAR", "x")
+			.stdin("0123456789")
+			.max_output(12);
+		let out = main.execute_with(&opts)?;
+		assert!(out.truncated);
+		assert_eq!(out.stdout()?, "env: x\n01234");
+
+		// characters crossing the limit are left out
+		let out = main.execute_with(&opts.stdin("0123é56789"))?;
+		assert!(out.truncated);
+		assert_eq!(out.stdout()?, "env: x\n0123");
+
+		Ok(())
+	}
+
+	#[test]
+	fn execute_timeout() -> Result<()> {
+		let mut main = Runner::new();
+		main.append("int main() { for (;;) {} }\n");
+
+		let opts = ExecOptions::new().timeout(Duration::from_millis(100));
+		let out = main.execute_with(&opts)?;
+		assert!(out.timed_out);
+		assert!(!out.success());
+		assert_eq!(out.code, None);
+		assert_eq!(out.signal, Some(9));
+		assert!(out.duration >= Duration::from_millis(100));
+		assert!(out.duration < Duration::from_secs(5));
+		Ok(())
+	}
+
+	#[test]
+	fn sanitizer_findings() -> Result<()> {
+		let mut runner = Runner::new();
+		runner.set_sanitize(true);
+		runner.append(text(
+			r#"
+				#include <stdint.h>
+				#include <stdlib.h>
+
+				int main(int argc, char *argv[]) {
+					int *p = malloc(10 * sizeof(int));
+					int64_t x = INT64_MAX - 1 + argc;
+				#line 3 "eval"
+					x = x + 1;
+				#line 7 "eval"
+					p[10] = (int)x;
+					return 0;
+				}
+			"#,
+		));
+
+		let err = runner.execute().unwrap_err().to_string();
+		assert!(err.starts_with("sanitizer reported 2 finding(s)\n"));
+
+		let list = runner.findings();
+		assert_eq!(list[0].sanitizer, Sanitizer::Undefined);
+		assert_eq!(list[0].kind, "signed integer overflow");
+		let location = list[0].location.as_ref().unwrap();
+		assert_eq!((location.file.as_str(), location.line), ("eval", 3));
+
+		assert_eq!(list[1].sanitizer, Sanitizer::Address);
+		assert_eq!(list[1].kind, "heap-buffer-overflow");
+		assert_eq!(list[1].location.as_ref().unwrap().to_string(), "eval:7");
+		assert_eq!(list[1].frames[0].func.as_deref(), Some("main"));
+
+		// clean programs run normally
+		let store = Store::new();
+		let code = Code::Print(store.add_list([Code::Str(store.str("ok"))]));
+		let mut builder = Builder::new(&store);
+		let main = code.generate_c(&mut builder)?;
+		let mut runner = builder.build(main);
+		runner.set_sanitize(true);
+		let out = runner.execute()?;
+		assert_eq!(String::from_utf8(out.stdout)?, "ok\n");
+		assert!(runner.findings().is_empty());
+		Ok(())
+	}
+
 	#[test]
 	fn hello_world() -> Result<()> {
 		let mut main = Runner::new();
@@ -0,0 +1,360 @@
+use std::{
+	collections::HashMap,
+	path::{Path, PathBuf},
+	process::Command,
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		Mutex,
+	},
+	time::{Duration, SystemTime},
+};
+
+use super::*;
+
+/// Content-addressed cache for compiled programs.
+///
+/// Entries are keyed by the hash of the C source, the [`Toolchain`] used
+/// to compile it and the compiler version. Each entry stores the executable
+/// and the compiler error output, so diagnostics are the same on a cache
+/// hit.
+///
+/// The cache is bounded by size. When it grows over the limit, the least
+/// recently used entries are evicted, including temporary files left by
+/// interrupted writes.
+pub struct Cache {
+	dir: PathBuf,
+	max_size: u64,
+	hits: AtomicU64,
+	misses: AtomicU64,
+	evictions: AtomicU64,
+	/// Output of `cc --version` for each compiler used in a key.
+	versions: Mutex<HashMap<String, String>>,
+}
+
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct CacheStats {
+	pub hits: u64,
+	pub misses: u64,
+	pub evictions: u64,
+}
+
+/// Cached compilation result.
+pub struct CacheEntry {
+	pub exe: PathBuf,
+	pub log: String,
+}
+
+const EXE_EXT: &str = "exe";
+const LOG_EXT: &str = "log";
+const TMP_EXT: &str = "tmp";
+
+/// Age after which temporary files are no longer being written.
+const TMP_AGE: Duration = Duration::from_secs(10 * 60);
+
+impl Cache {
+	pub const DEFAULT_SIZE: u64 = 256 * 1024 * 1024;
+
+	/// Open a cache at the given directory, creating it if necessary.
+	pub fn new<T: AsRef<Path>>(dir: T, max_size: u64) -> Result<Self> {
+		let dir = dir.as_ref();
+		std::fs::create_dir_all(dir).map_err(|err| format!("creating cache dir `{}`: {err}", dir.display()))?;
+		let dir = dir.canonicalize()?;
+		Ok(Self {
+			dir,
+			max_size,
+			hits: Default::default(),
+			misses: Default::default(),
+			evictions: Default::default(),
+			versions: Default::default(),
+		})
+	}
+
+	/// Open the default cache at `bit-cache` in the system temp directory.
+	pub fn open_default() -> Result<Self> {
+		let dir = std::env::temp_dir().join("bit-cache");
+		Self::new(dir, Self::DEFAULT_SIZE)
+	}
+
+	pub fn dir(&self) -> &Path {
+		&self.dir
+	}
+
+	pub fn stats(&self) -> CacheStats {
+		CacheStats {
+			hits: self.hits.load(Ordering::Relaxed),
+			misses: self.misses.load(Ordering::Relaxed),
+			evictions: self.evictions.load(Ordering::Relaxed),
+		}
+	}
+
+	/// Compute the cache key for a program.
+	///
+	/// The key only depends on its inputs, so it is the same between runs
+	/// and Rust versions.
+	pub fn key(&self, code: &str, toolchain: &Toolchain) -> String {
+		let cmd = toolchain.command("main.c", "main.exe");
+		let mut hash = Fnv::new();
+		hash.field(code.as_bytes());
+		hash.field(self.version(toolchain).as_bytes());
+		hash.field(cmd.get_program().as_encoded_bytes());
+		for it in cmd.get_args() {
+			hash.field(it.as_encoded_bytes());
+		}
+		format!("{:032x}", hash.0)
+	}
+
+	/// Version of the toolchain compiler, which is only run once.
+	fn version(&self, toolchain: &Toolchain) -> String {
+		let cc = toolchain.compiler();
+		let mut versions = self.versions.lock().unwrap();
+		let version = versions.entry(cc.to_string()).or_insert_with(|| {
+			// a missing compiler fails to compile anyway
+			match Command::new(cc).arg("--version").output() {
+				Ok(out) => {
+					let mut version = String::from_utf8_lossy(&out.stdout).to_string();
+					version.push_str(&String::from_utf8_lossy(&out.stderr));
+					version
+				}
+				Err(_) => String::new(),
+			}
+		});
+		version.clone()
+	}
+
+	/// Lookup a cached entry, marking it as recently used.
+	pub fn get(&self, key: &str) -> Result<Option<CacheEntry>> {
+		let exe = self.path(key, EXE_EXT);
+		if !exe.is_file() {
+			self.misses.fetch_add(1, Ordering::Relaxed);
+			return Ok(None);
+		}
+
+		let log = std::fs::read_to_string(self.path(key, LOG_EXT)).unwrap_or_default();
+		if let Ok(file) = std::fs::File::options().append(true).open(&exe) {
+			let _ = file.set_modified(SystemTime::now());
+		}
+
+		self.hits.fetch_add(1, Ordering::Relaxed);
+		Ok(Some(CacheEntry { exe, log }))
+	}
+
+	/// Store a compiled executable in the cache and evict old entries if
+	/// the cache is over its size limit.
+	pub fn put<T: AsRef<Path>>(&self, key: &str, exe: T, log: &str) -> Result<PathBuf> {
+		// write to temporary files and rename, so that concurrent readers
+		// never see a partially written entry
+		let uniq = rand::random::<u32>();
+		let tmp_exe = self.path(key, &format!("{EXE_EXT}.{uniq}.tmp"));
+		std::fs::copy(exe.as_ref(), &tmp_exe)?;
+
+		// the log goes first, as the executable marks the entry as valid
+		let path = self.path(key, EXE_EXT);
+		temp::atomic_write(self.path(key, LOG_EXT), log)?;
+		std::fs::rename(&tmp_exe, &path)?;
+
+		self.evict(self.max_size)?;
+		Ok(path)
+	}
+
+	/// Current size of all cache entries in bytes.
+	pub fn size(&self) -> Result<u64> {
+		let size = self.entries()?.iter().map(|x| x.size).sum();
+		Ok(size)
+	}
+
+	/// Remove least recently used entries until the cache size is at most
+	/// `max_size` bytes.
+	pub fn evict(&self, max_size: u64) -> Result<()> {
+		let mut entries = self.entries()?;
+		let mut size: u64 = entries.iter().map(|x| x.size).sum();
+		if size <= max_size {
+			return Ok(());
+		}
+
+		entries.sort_by_key(|x| x.used);
+		for it in entries {
+			if size <= max_size {
+				break;
+			}
+
+			// entries may be removed concurrently, so ignore errors
+			for path in it.paths.iter() {
+				let _ = std::fs::remove_file(path);
+			}
+			size -= it.size;
+			self.evictions.fetch_add(1, Ordering::Relaxed);
+		}
+		Ok(())
+	}
+
+	pub fn clear(&self) -> Result<()> {
+		self.evict(0)
+	}
+
+	fn path(&self, key: &str, ext: &str) -> PathBuf {
+		self.dir.join(format!("{key}.{ext}"))
+	}
+
+	fn entries(&self) -> Result<Vec<Entry>> {
+		let mut entries = Vec::new();
+		for it in std::fs::read_dir(&self.dir)? {
+			let it = it?;
+			let path = it.path();
+			let ext = path.extension().and_then(|x| x.to_str());
+			if ext != Some(EXE_EXT) && ext != Some(TMP_EXT) {
+				continue;
+			}
+
+			let Ok(meta) = it.metadata() else {
+				continue;
+			};
+			let used = meta.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+
+			// temporaries of interrupted writes are entries of their own,
+			// but the ones still being written are not evicted
+			if ext == Some(TMP_EXT) {
+				if used.elapsed().is_ok_and(|x| x > TMP_AGE) {
+					entries.push(Entry {
+						paths: vec![path],
+						size: meta.len(),
+						used,
+					});
+				}
+				continue;
+			}
+
+			let Some(key) = path.file_stem().and_then(|x| x.to_str()) else {
+				continue;
+			};
+			let log = self.path(key, LOG_EXT);
+			let log_size = std::fs::metadata(&log).map(|x| x.len()).unwrap_or(0);
+			entries.push(Entry {
+				paths: vec![path, log],
+				size: meta.len() + log_size,
+				used,
+			});
+		}
+		Ok(entries)
+	}
+}
+
+struct Entry {
+	paths: Vec<PathBuf>,
+	size: u64,
+	used: SystemTime,
+}
+
+/// 128-bit FNV-1a hash.
+struct Fnv(u128);
+
+impl Fnv {
+	const OFFSET: u128 = 0x6c62272e07bb014262b821756295c58d;
+	const PRIME: u128 = 0x0000000001000000000000000000013b;
+
+	fn new() -> Self {
+		Self(Self::OFFSET)
+	}
+
+	fn write(&mut self, bytes: &[u8]) {
+		for &byte in bytes {
+			self.0 ^= byte as u128;
+			self.0 = self.0.wrapping_mul(Self::PRIME);
+		}
+	}
+
+	/// Hash a length prefixed field, so fields cannot run into each other.
+	fn field(&mut self, bytes: &[u8]) {
+		self.write(&(bytes.len() as u64).to_le_bytes());
+		self.write(bytes);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::Duration;
+
+	use super::*;
+
+	#[test]
+	fn cache_entries() -> Result<()> {
+		let dir = temp::dir()?;
+		let cache = Cache::new(dir.path().join("cache"), 1000)?;
+
+		let gcc = Toolchain::gcc();
+		let key = cache.key("int main() {}", &gcc);
+		assert_eq!(key.len(), 32);
+		assert_eq!(key, cache.key("int main() {}", &Toolchain::gcc()));
+		assert!(key != cache.key("int main() { }", &gcc));
+		assert!(key != cache.key("int main() {}", &Toolchain::gcc().opt("2")));
+		assert!(key != cache.key("int main() {}", &Toolchain::clang()));
+
+		// the hash is stable, unlike the standard library hashers
+		let mut hash = Fnv::new();
+		hash.write(b"a");
+		assert_eq!(hash.0, 0xd228cb696f1a8caf78912b704e4a8964);
+
+		assert!(cache.get(&key)?.is_none());
+
+		let mut exe = dir.file("a.exe")?;
+		exe.write([1u8; 100])?;
+		cache.put(&key, exe.path(), "some warning")?;
+
+		let entry = cache.get(&key)?.unwrap();
+		assert_eq!(std::fs::read(entry.exe)?, [1u8; 100]);
+		assert_eq!(entry.log, "some warning");
+		assert_eq!(cache.size()?, 112);
+
+		assert_eq!(
+			cache.stats(),
+			CacheStats {
+				hits: 1,
+				misses: 1,
+				evictions: 0,
+			}
+		);
+
+		Ok(())
+	}
+
+	#[test]
+	fn cache_eviction() -> Result<()> {
+		let dir = temp::dir()?;
+		let cache = Cache::new(dir.path().join("cache"), 250)?;
+
+		let mut exe = dir.file("a.exe")?;
+		exe.write([0u8; 100])?;
+
+		let keys = ["a", "b", "c"].map(|x| cache.key(x, &Toolchain::gcc()));
+		let set_used = |key: &str, secs: u64| -> Result<()> {
+			let file = std::fs::File::options().append(true).open(cache.path(key, EXE_EXT))?;
+			file.set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(secs))?;
+			Ok(())
+		};
+
+		cache.put(&keys[0], exe.path(), "")?;
+		cache.put(&keys[1], exe.path(), "")?;
+		set_used(&keys[0], 2000)?;
+		set_used(&keys[1], 1000)?;
+
+		// adding a third entry evicts the least recently used
+		cache.put(&keys[2], exe.path(), "")?;
+		assert!(cache.get(&keys[0])?.is_some());
+		assert!(cache.get(&keys[1])?.is_none());
+		assert!(cache.get(&keys[2])?.is_some());
+		assert_eq!(cache.stats().evictions, 1);
+
+		// temporaries left by an interrupted write are evicted once stale
+		let tmp = cache.dir().join(format!("{}.{EXE_EXT}.1.{TMP_EXT}", keys[0]));
+		std::fs::write(&tmp, [0u8; 10])?;
+		let size = cache.size()?;
+		let file = std::fs::File::options().append(true).open(&tmp)?;
+		file.set_modified(SystemTime::now() - TMP_AGE * 2)?;
+		assert_eq!(cache.size()?, size + 10);
+
+		cache.clear()?;
+		assert_eq!(cache.size()?, 0);
+		assert!(!tmp.exists());
+
+		Ok(())
+	}
+}
@@ -0,0 +1,172 @@
+use std::{
+	path::{Path, PathBuf},
+	process::ExitStatus,
+	sync::{Arc, Mutex},
+	time::{Duration, Instant},
+};
+
+use super::*;
+
+/// Options for executing a compiled program.
+#[derive(Clone, Debug, Default)]
+pub struct ExecOptions {
+	stdin: Vec<u8>,
+	args: Vec<String>,
+	env: Vec<(String, String)>,
+	cwd: Option<PathBuf>,
+	timeout: Option<Duration>,
+	max_output: Option<usize>,
+}
+
+impl ExecOptions {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	pub fn stdin<T: Into<Vec<u8>>>(mut self, input: T) -> Self {
+		self.stdin = input.into();
+		self
+	}
+
+	pub fn arg<T: Into<String>>(mut self, arg: T) -> Self {
+		self.args.push(arg.into());
+		self
+	}
+
+	pub fn env<T: Into<String>, U: Into<String>>(mut self, name: T, value: U) -> Self {
+		self.env.push((name.into(), value.into()));
+		self
+	}
+
+	/// Working directory for the program. Defaults to the build directory.
+	pub fn cwd<T: AsRef<Path>>(mut self, path: T) -> Self {
+		self.cwd = Some(path.as_ref().to_owned());
+		self
+	}
+
+	/// Wall-clock limit for the program, after which it is killed.
+	pub fn timeout(mut self, timeout: Duration) -> Self {
+		self.timeout = Some(timeout);
+		self
+	}
+
+	/// Maximum number of bytes captured from each of stdout and stderr.
+	///
+	/// Output past the limit is discarded and the result is marked as
+	/// truncated.
+	pub fn max_output(mut self, bytes: usize) -> Self {
+		self.max_output = Some(bytes);
+		self
+	}
+}
+
+/// Result of executing a program with [`ExecOptions`].
+#[derive(Debug)]
+pub struct ExecResult {
+	pub status: ExitStatus,
+	pub code: Option<i32>,
+	pub signal: Option<i32>,
+	pub timed_out: bool,
+	pub truncated: bool,
+	pub duration: Duration,
+	pub stdout: Vec<u8>,
+	pub stderr: Vec<u8>,
+}
+
+impl ExecResult {
+	pub fn success(&self) -> bool {
+		self.status.success() && !self.timed_out
+	}
+
+	pub fn stdout(&self) -> Result<&str> {
+		Ok(std::str::from_utf8(&self.stdout)?)
+	}
+
+	pub fn stderr(&self) -> Result<&str> {
+		Ok(std::str::from_utf8(&self.stderr)?)
+	}
+}
+
+/// Execute a program in the given directory.
+///
+/// The output is captured as text, with invalid UTF-8 replaced.
+pub fn exec<T: AsRef<Path>, U: AsRef<Path>>(exe: T, dir: U, options: &ExecOptions) -> Result<ExecResult> {
+	let exe = dir.as_ref().join(exe);
+	let cwd = options.cwd.as_deref().unwrap_or(dir.as_ref());
+
+	let mut cmd = cmd::new(&exe).cwd(cwd).stdin(options.stdin.clone());
+	for arg in options.args.iter() {
+		cmd = cmd.arg(arg);
+	}
+	for (name, value) in options.env.iter() {
+		cmd = cmd.env(name, value);
+	}
+	if let Some(timeout) = options.timeout {
+		cmd = cmd.timeout(timeout);
+	}
+
+	let max = options.max_output.unwrap_or(usize::MAX);
+	let output = Arc::new(Mutex::new(Captured::default()));
+	let capture = output.clone();
+
+	let start = Instant::now();
+	let status = cmd
+		.output_status(move |out| {
+			capture.lock().unwrap().push(out, max);
+			Ok(())
+		})
+		.map_err(|err| format!("could not run `{}`: {err}", exe.display()))?;
+	let duration = start.elapsed();
+	let output = std::mem::take(&mut *output.lock().unwrap());
+
+	#[cfg(unix)]
+	let signal = std::os::unix::process::ExitStatusExt::signal(&status);
+	#[cfg(not(unix))]
+	let signal = None;
+
+	Ok(ExecResult {
+		status,
+		code: status.code(),
+		signal,
+		timed_out: cmd.timed_out(),
+		truncated: output.truncated,
+		duration,
+		stdout: output.stdout,
+		stderr: output.stderr,
+	})
+}
+
+/// Output of a program, keeping at most a maximum number of bytes of each
+/// stream.
+#[derive(Default)]
+struct Captured {
+	stdout: Vec<u8>,
+	stderr: Vec<u8>,
+	/// Set for each stream once it is truncated.
+	full: [bool; 2],
+	truncated: bool,
+}
+
+impl Captured {
+	fn push(&mut self, output: cmd::Output, max: usize) {
+		let (buffer, full, text) = match output {
+			cmd::Output::StdOut(text) => (&mut self.stdout, &mut self.full[0], text),
+			cmd::Output::StdErr(text) => (&mut self.stderr, &mut self.full[1], text),
+		};
+		if *full {
+			return;
+		}
+
+		// output past the limit is still read so the program does not block,
+		// and is never cut in the middle of a character
+		let mut len = text.len().min(max - buffer.len());
+		while !text.is_char_boundary(len) {
+			len -= 1;
+		}
+		buffer.extend_from_slice(&text.as_bytes()[..len]);
+		if len < text.len() {
+			*full = true;
+			self.truncated = true;
+		}
+	}
+}
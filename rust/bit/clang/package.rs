@@ -0,0 +1,354 @@
+use std::{
+	fmt::Write,
+	path::{Path, PathBuf},
+};
+
+use super::*;
+
+/// Generated C file.
+#[derive(Clone, Debug)]
+pub struct Unit {
+	pub name: String,
+	pub code: String,
+}
+
+/// Set of C files generated for a program or library.
+///
+/// The header declares the exported functions, while the units contain
+/// the function definitions. The optional `main` unit contains the
+/// program entry point.
+#[derive(Clone, Debug)]
+pub struct Package {
+	pub name: String,
+	pub header: Unit,
+	pub units: Vec<Unit>,
+	pub main: Option<Unit>,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Artifact {
+	Executable,
+	StaticLib,
+	SharedLib,
+}
+
+impl Package {
+	pub fn files(&self) -> impl Iterator<Item = &Unit> {
+		std::iter::once(&self.header)
+			.chain(self.units.iter())
+			.chain(self.main.iter())
+	}
+
+	/// Write all package files to the given directory.
+	pub fn write<T: AsRef<Path>>(&self, dir: T) -> Result<()> {
+		let dir = dir.as_ref();
+		std::fs::create_dir_all(dir)?;
+		for it in self.files() {
+			std::fs::write(dir.join(&it.name), &it.code)?;
+		}
+		Ok(())
+	}
+
+	/// File name for the package artifact.
+	pub fn artifact_name(&self, artifact: Artifact) -> String {
+		use std::env::consts::*;
+		let name = &self.name;
+		match artifact {
+			Artifact::Executable => format!("{name}{EXE_SUFFIX}"),
+			Artifact::StaticLib => format!("lib{name}.a"),
+			Artifact::SharedLib => format!("{DLL_PREFIX}{name}{DLL_SUFFIX}"),
+		}
+	}
+}
+
+impl<'a> Builder<'a> {
+	/// Generate a package with the given name.
+	///
+	/// Functions from [`Builder::export`] are declared in the `{name}.h`
+	/// public header. Each unit from [`Builder::set_unit`] generates its own
+	/// file, with the default unit generating `{name}.c`.
+	pub fn package<T: AsRef<str>>(&self, name: T, main: Option<Func>) -> Package {
+		let name = name.as_ref();
+
+		let mut includes = String::new();
+		for it in self.include_system.iter() {
+			let _ = writeln!(includes, "#include <{it}>");
+		}
+		for it in self.include_header.iter() {
+			let _ = writeln!(includes, "#include \"{it}\"");
+		}
+		let _ = writeln!(includes, "#include \"{name}.h\"");
+
+		// internal functions can be called from any unit
+		let mut internal = String::new();
+		for it in self.funcs.iter().filter(|x| !x.export) {
+			let _ = writeln!(internal, "{};", it.proto);
+		}
+
+		let mut units: Vec<&str> = vec![""];
+		for it in self.funcs.iter() {
+			if !units.contains(&it.unit) {
+				units.push(it.unit);
+			}
+		}
+
+		let units = units
+			.into_iter()
+			.map(|unit| {
+				let file = format!("{}.c", if unit.is_empty() { name } else { unit });
+				let mut code = includes.clone();
+				if !internal.is_empty() {
+					code.push('\n');
+					code.push_str(&internal);
+				}
+				for it in self.funcs.iter().filter(|x| x.unit == unit) {
+					let _ = write!(code, "\n{} {}", it.proto, it.body);
+					reset_line(&mut code, &it.body, &file);
+				}
+				Unit { name: file, code }
+			})
+			.collect();
+
+		let main = main.map(|main| {
+			let file = SOURCE_NAME.to_string();
+			let mut body = String::new();
+			main.push_stmt(&mut body);
+			body.push_str("return 0;\n");
+
+			let mut code = includes.clone();
+			if !internal.is_empty() {
+				code.push('\n');
+				code.push_str(&internal);
+			}
+			code.push_str("\nint main(int argc, char *argv[]) ");
+			push_block(&mut code, &body);
+			code.push('\n');
+			reset_line(&mut code, &body, &file);
+			Unit { name: file, code }
+		});
+
+		Package {
+			name: name.to_string(),
+			header: self.header(name),
+			units,
+			main,
+		}
+	}
+
+	fn header(&self, name: &str) -> Unit {
+		let guard = name
+			.chars()
+			.map(|c| {
+				if c.is_ascii_alphanumeric() {
+					c.to_ascii_uppercase()
+				} else {
+					'_'
+				}
+			})
+			.collect::<String>();
+
+		let mut code = String::new();
+		let _ = writeln!(code, "#ifndef {guard}_H");
+		let _ = writeln!(code, "#define {guard}_H");
+
+		// only the headers needed for the declared types and the runtime,
+		// which is included in the header as it declares the string type
+		let types = ["inttypes.h", "stdbool.h"];
+		let types = self
+			.include_system
+			.iter()
+			.filter(|x| types.contains(x) || self.runtime.iter().any(|rt| rt.includes.contains(x)))
+			.collect::<Vec<_>>();
+		if !types.is_empty() {
+			code.push('\n');
+			for it in types {
+				let _ = writeln!(code, "#include <{it}>");
+			}
+		}
+		self.push_runtime(&mut code);
+
+		code.push('\n');
+		for it in self.funcs.iter().filter(|x| x.export) {
+			let _ = writeln!(code, "{};", it.proto);
+		}
+		code.push_str("\n#endif\n");
+
+		Unit {
+			name: format!("{name}.h"),
+			code,
+		}
+	}
+}
+
+impl Runner {
+	/// Write the package to the output directory and build the artifact,
+	/// returning its path.
+	pub fn build_package<T: AsRef<Path>>(&mut self, package: &Package, artifact: Artifact, dir: T) -> Result<PathBuf> {
+		let dir = dir.as_ref();
+		package.write(dir)?;
+		let dir = dir.canonicalize()?;
+
+		let mut sources = package.units.iter().collect::<Vec<_>>();
+		if artifact == Artifact::Executable {
+			let Some(main) = &package.main else {
+				Err(format!("package `{}` has no main", package.name))?
+			};
+			sources.push(main);
+		}
+
+		self.diagnostics.clear();
+		let pic = artifact == Artifact::SharedLib;
+		let mut objects = Vec::new();
+		for it in sources {
+			let obj = Path::new(&it.name).with_extension("o");
+			let cmd = self.toolchain.compile_command(&it.name, &obj, pic);
+			self.run_tool(cmd, &dir)?;
+			objects.push(obj);
+		}
+
+		let name = package.artifact_name(artifact);
+		let cmd = match artifact {
+			Artifact::Executable => self.toolchain.link_command(&objects, &name, false),
+			Artifact::SharedLib => self.toolchain.link_command(&objects, &name, true),
+			Artifact::StaticLib => {
+				// the archiver appends to existing archives
+				let path = dir.join(&name);
+				if path.exists() {
+					std::fs::remove_file(path)?;
+				}
+				self.toolchain.archive_command(&objects, &name)
+			}
+		};
+		self.run_tool(cmd, &dir)?;
+
+		Ok(dir.join(name))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::process::Command;
+
+	use super::*;
+
+	#[test]
+	fn package_units() -> Result<()> {
+		let store = Store::new();
+		let mut builder = Builder::new(&store);
+		let main = build_math(&store, &mut builder)?;
+		let package = builder.package("math", Some(main));
+
+		let names = package.files().map(|x| x.name.as_str()).collect::<Vec<_>>();
+		assert_eq!(names, ["math.h", "math.c", "util.c", "main.c"]);
+
+		let header = &package.header.code;
+		assert!(header.starts_with("#ifndef MATH_H\n#define MATH_H\n"));
+		assert!(header.contains("\nint64_t add(int64_t a_0_, int64_t b_0_);\n"));
+		assert!(!header.contains("twice"));
+
+		let util = &package.units[1].code;
+		assert!(util.contains("#include \"math.h\"\n"));
+		assert!(util.contains("\nint64_t twice_0_(int64_t x_0_);\n"));
+		assert!(util.contains("\nint64_t twice_0_(int64_t x_0_) {\n"));
+
+		let dir = temp::dir()?;
+		let mut runner = Runner::new();
+		runner.set_toolchain(Toolchain::gcc());
+		let exe = runner.build_package(&package, Artifact::Executable, dir.path().join("out"))?;
+		assert!(exe.ends_with(package.artifact_name(Artifact::Executable)));
+
+		let out = exec(&exe, dir.path(), &ExecOptions::new())?;
+		assert_eq!(out.stdout()?, "8\n");
+		Ok(())
+	}
+
+	#[test]
+	fn package_libraries() -> Result<()> {
+		let store = Store::new();
+		let mut builder = Builder::new(&store);
+		build_math(&store, &mut builder)?;
+		let package = builder.package("math", None);
+
+		let user = text(
+			r#"
+				#include <stdio.h>
+				#include "math.h"
+
+				int main() {
+					printf("%d\n", (int)add(20, 22));
+					return 0;
+				}
+			"#,
+		);
+
+		let dir = temp::dir()?;
+		let mut runner = Runner::new();
+		runner.set_toolchain(Toolchain::gcc());
+
+		for artifact in [Artifact::StaticLib, Artifact::SharedLib] {
+			let out = dir.path().join(format!("{artifact:?}"));
+			let lib = runner.build_package(&package, artifact, &out)?;
+			assert!(lib.is_file());
+
+			std::fs::write(out.join("user.c"), &user)?;
+			let cc = Toolchain::gcc()
+				.flag(format!("-L{}", out.display()))
+				.flag(format!("-Wl,-rpath,{}", out.display()))
+				.lib("math");
+			let status = cc.command("user.c", "user.exe").current_dir(&out).status()?;
+			assert!(status.success());
+
+			let out = Command::new(out.join("user.exe")).output()?;
+			assert_eq!(String::from_utf8(out.stdout)?, "42\n");
+		}
+
+		let err = runner.build_package(&package, Artifact::Executable, dir.path());
+		assert_eq!(err.unwrap_err().to_string(), "package `math` has no main");
+		Ok(())
+	}
+
+	/// Exported `add(a, b)` using `twice(x)` from a separate unit.
+	fn build_math<'a>(store: &'a Store, builder: &mut Builder<'a>) -> Result<Func> {
+		let get = |name| &*store.add(Code::Get(store.sym(name)));
+		let param = |name| Param {
+			name: store.sym(name),
+			kind: Kind::I64,
+		};
+
+		let add = FuncDef {
+			name: store.sym("add"),
+			args: store.add_list([param("a"), param("b")]),
+			ret: Kind::I64,
+			body: store.add(Code::Return(Some(store.add(Code::Binary(
+				BinaryOp::Add,
+				get("a"),
+				get("b"),
+			))))),
+		};
+
+		let twice = FuncDef {
+			name: store.sym("twice"),
+			args: store.add_list([param("x")]),
+			ret: Kind::I64,
+			body: store.add(Code::Return(Some(store.add(Code::Binary(
+				BinaryOp::Mul,
+				get("x"),
+				store.add(Code::Int(2)),
+			))))),
+		};
+
+		let call = |name, args: &'a [Code<'a>]| Code::Call(store.sym(name), args);
+		let twice_3 = call("twice", store.add_list([Code::Int(3)]));
+		let add_2 = call("add", store.add_list([Code::Int(2), twice_3]));
+
+		builder.export(store.sym("add"));
+		let add = Code::Func(store.add(add)).generate_c(builder)?;
+		builder.set_unit("util");
+		let twice = Code::Func(store.add(twice)).generate_c(builder)?;
+		assert_eq!(add.expr, "");
+		assert_eq!(twice.expr, "");
+
+		let main = Code::Print(store.add_list([add_2]));
+		main.generate_c(builder)
+	}
+}
@@ -0,0 +1,212 @@
+/// Support code included in the generated C source.
+///
+/// Runtime functions are `static inline`, so they can be included in
+/// multiple units and in package headers without unused warnings.
+#[derive(Debug, Eq, PartialEq)]
+pub struct Runtime {
+	pub includes: &'static [&'static str],
+	pub code: &'static str,
+}
+
+/// Length-aware UTF-8 strings.
+///
+/// Strings are a `{ptr, len}` pair and may contain `\0`. String literals
+/// use the `BIT_STR` macro. Concatenation allocates a new string that is
+/// never freed.
+pub static STR: Runtime = Runtime {
+	includes: &["stddef.h", "stdint.h", "stdio.h", "stdlib.h", "string.h"],
+	code: r#"
+typedef struct {
+	const char *ptr;
+	size_t len;
+} bit_str;
+
+#define BIT_STR(s) ((bit_str){ (s), sizeof(s) - 1 })
+
+static inline bit_str bit_str_concat(bit_str a, bit_str b) {
+	if (a.len == 0) return b;
+	if (b.len == 0) return a;
+	char *ptr = malloc(a.len + b.len);
+	if (!ptr) abort();
+	memcpy(ptr, a.ptr, a.len);
+	memcpy(ptr + a.len, b.ptr, b.len);
+	return (bit_str){ ptr, a.len + b.len };
+}
+
+static inline int bit_str_cmp(bit_str a, bit_str b) {
+	size_t len = a.len < b.len ? a.len : b.len;
+	int cmp = len ? memcmp(a.ptr, b.ptr, len) : 0;
+	if (cmp != 0) return cmp;
+	return a.len < b.len ? -1 : a.len > b.len ? 1 : 0;
+}
+
+static inline int64_t bit_str_len(bit_str s) {
+	return (int64_t)s.len;
+}
+
+static inline int64_t bit_str_chars(bit_str s) {
+	int64_t count = 0;
+	for (size_t i = 0; i < s.len; i++) {
+		// count every byte that is not a UTF-8 continuation byte
+		if (((unsigned char)s.ptr[i] & 0xC0) != 0x80) count++;
+	}
+	return count;
+}
+
+static inline void bit_str_print(bit_str s) {
+	fwrite(s.ptr, 1, s.len, stdout);
+}
+"#,
+};
+
+/// Arbitrary-precision integers.
+///
+/// Integers are stored as sign and magnitude, with the magnitude as little
+/// endian `u32` limbs without leading zeros. Like strings, the result of
+/// every operation is newly allocated and never freed.
+pub static INT: Runtime = Runtime {
+	includes: &["inttypes.h", "stddef.h", "stdint.h", "stdio.h", "stdlib.h", "string.h"],
+	code: r#"
+typedef struct {
+	int neg;
+	size_t len;
+	uint32_t *limbs;
+} bit_int;
+
+static inline bit_int bit_int_alloc(size_t len) {
+	uint32_t *limbs = calloc(len ? len : 1, sizeof(uint32_t));
+	if (!limbs) abort();
+	return (bit_int){ 0, len, limbs };
+}
+
+static inline bit_int bit_int_norm(bit_int a) {
+	while (a.len > 0 && a.limbs[a.len - 1] == 0) a.len--;
+	if (a.len == 0) a.neg = 0;
+	return a;
+}
+
+static inline bit_int bit_int_lit(size_t len, const uint32_t *limbs) {
+	bit_int out = bit_int_alloc(len);
+	memcpy(out.limbs, limbs, len * sizeof(uint32_t));
+	return bit_int_norm(out);
+}
+
+static inline bit_int bit_int_from_i64(int64_t value) {
+	uint64_t mag = value < 0 ? (uint64_t)(-(value + 1)) + 1 : (uint64_t)value;
+	bit_int out = bit_int_alloc(2);
+	out.neg = value < 0;
+	out.limbs[0] = (uint32_t)mag;
+	out.limbs[1] = (uint32_t)(mag >> 32);
+	return bit_int_norm(out);
+}
+
+static inline int bit_int_cmp_mag(bit_int a, bit_int b) {
+	if (a.len != b.len) return a.len < b.len ? -1 : 1;
+	for (size_t i = a.len; i > 0; i--) {
+		if (a.limbs[i - 1] != b.limbs[i - 1]) return a.limbs[i - 1] < b.limbs[i - 1] ? -1 : 1;
+	}
+	return 0;
+}
+
+static inline bit_int bit_int_add_mag(bit_int a, bit_int b) {
+	if (a.len < b.len) { bit_int t = a; a = b; b = t; }
+	bit_int out = bit_int_alloc(a.len + 1);
+	uint64_t carry = 0;
+	for (size_t i = 0; i < a.len; i++) {
+		uint64_t sum = (uint64_t)a.limbs[i] + (i < b.len ? b.limbs[i] : 0) + carry;
+		out.limbs[i] = (uint32_t)sum;
+		carry = sum >> 32;
+	}
+	out.limbs[a.len] = (uint32_t)carry;
+	return bit_int_norm(out);
+}
+
+// requires |a| >= |b|
+static inline bit_int bit_int_sub_mag(bit_int a, bit_int b) {
+	bit_int out = bit_int_alloc(a.len);
+	int64_t borrow = 0;
+	for (size_t i = 0; i < a.len; i++) {
+		int64_t diff = (int64_t)a.limbs[i] - (i < b.len ? b.limbs[i] : 0) - borrow;
+		borrow = diff < 0;
+		out.limbs[i] = (uint32_t)(diff + (borrow << 32));
+	}
+	return bit_int_norm(out);
+}
+
+static inline bit_int bit_int_neg(bit_int a) {
+	a.neg = a.len > 0 && !a.neg;
+	return a;
+}
+
+static inline bit_int bit_int_add(bit_int a, bit_int b) {
+	bit_int out;
+	if (a.neg == b.neg) {
+		out = bit_int_add_mag(a, b);
+		out.neg = a.neg;
+	} else if (bit_int_cmp_mag(a, b) >= 0) {
+		out = bit_int_sub_mag(a, b);
+		out.neg = a.neg;
+	} else {
+		out = bit_int_sub_mag(b, a);
+		out.neg = b.neg;
+	}
+	return bit_int_norm(out);
+}
+
+static inline bit_int bit_int_sub(bit_int a, bit_int b) {
+	return bit_int_add(a, bit_int_neg(b));
+}
+
+static inline bit_int bit_int_mul(bit_int a, bit_int b) {
+	bit_int out = bit_int_alloc(a.len + b.len);
+	for (size_t i = 0; i < a.len; i++) {
+		uint64_t carry = 0;
+		for (size_t j = 0; j < b.len; j++) {
+			uint64_t cur = (uint64_t)a.limbs[i] * b.limbs[j] + out.limbs[i + j] + carry;
+			out.limbs[i + j] = (uint32_t)cur;
+			carry = cur >> 32;
+		}
+		out.limbs[i + b.len] = (uint32_t)carry;
+	}
+	out.neg = a.neg != b.neg;
+	return bit_int_norm(out);
+}
+
+static inline int bit_int_cmp(bit_int a, bit_int b) {
+	if (a.neg != b.neg) return a.neg ? -1 : 1;
+	int cmp = bit_int_cmp_mag(a, b);
+	return a.neg ? -cmp : cmp;
+}
+
+static inline void bit_int_print(bit_int a) {
+	if (a.len == 0) {
+		printf("0");
+		return;
+	}
+
+	// divide by 10^9 repeatedly, collecting the remainders as chunks
+	// of nine decimal digits
+	bit_int num = bit_int_lit(a.len, a.limbs);
+	uint32_t *chunks = malloc((a.len * 10 / 9 + 2) * sizeof(uint32_t));
+	if (!chunks) abort();
+	size_t count = 0;
+	while (num.len > 0) {
+		uint64_t rem = 0;
+		for (size_t i = num.len; i > 0; i--) {
+			uint64_t cur = (rem << 32) | num.limbs[i - 1];
+			num.limbs[i - 1] = (uint32_t)(cur / 1000000000);
+			rem = cur % 1000000000;
+		}
+		chunks[count++] = (uint32_t)rem;
+		num = bit_int_norm(num);
+	}
+
+	printf("%s%" PRIu32, a.neg ? "-" : "", chunks[count - 1]);
+	for (size_t i = count - 1; i > 0; i--) {
+		printf("%09" PRIu32, chunks[i - 1]);
+	}
+	free(chunks);
+	free(num.limbs);
+}
+"#,
+};
@@ -0,0 +1,281 @@
+use std::{
+	fmt::{Display, Formatter},
+	path::Path,
+};
+
+use super::*;
+
+/// Compiler flags used to build programs with the sanitizers.
+pub const SANITIZE_FLAGS: [&str; 3] = ["-g", "-fno-omit-frame-pointer", "-fsanitize=address,undefined"];
+
+/// Environment for running sanitized programs.
+///
+/// Leak detection is disabled because the runtime never frees strings
+/// and integers.
+pub const SANITIZE_ENV: [(&str, &str); 2] = [
+	("ASAN_OPTIONS", "detect_leaks=0"),
+	("UBSAN_OPTIONS", "print_stacktrace=1"),
+];
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Sanitizer {
+	Address,
+	Undefined,
+}
+
+/// Error reported by a sanitizer at runtime.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Finding {
+	pub sanitizer: Sanitizer,
+	/// Error kind (e.g. `heap-buffer-overflow`, `signed integer overflow`).
+	pub kind: String,
+	pub message: String,
+	pub location: Option<Location>,
+	pub frames: Vec<Frame>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Location {
+	pub file: String,
+	pub line: usize,
+	pub column: Option<usize>,
+}
+
+/// Stack frame from a sanitizer report.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Frame {
+	pub func: Option<String>,
+	pub location: Option<Location>,
+	/// Binary containing the frame, for frames without debug information.
+	pub module: Option<String>,
+}
+
+impl Finding {
+	/// Parse the AddressSanitizer and UndefinedBehaviorSanitizer reports
+	/// from the program error output.
+	///
+	/// Only the first stack trace of each report is used, which is where
+	/// the error happened.
+	pub fn parse(output: &str) -> Vec<Finding> {
+		let mut out: Vec<Finding> = Vec::new();
+		let mut frames = false;
+		for line in output.lines() {
+			if let Some(finding) = Self::parse_header(line) {
+				out.push(finding);
+				frames = true;
+				continue;
+			}
+
+			let Some(last) = out.last_mut() else {
+				continue;
+			};
+			if !frames {
+				continue;
+			}
+
+			if let Some(frame) = Frame::parse(line) {
+				last.frames.push(frame);
+			} else if !last.frames.is_empty() {
+				frames = false;
+			}
+		}
+
+		for it in out.iter_mut() {
+			if it.location.is_none() {
+				it.location = it.frames.iter().find_map(|x| x.location.clone());
+			}
+		}
+		out
+	}
+
+	/// Make file paths relative to the given directory.
+	///
+	/// Debug information uses absolute paths, so `#line` mapped locations
+	/// refer to the source name inside the build directory.
+	pub fn strip_dir<T: AsRef<Path>>(&mut self, dir: T) {
+		let dir = dir.as_ref();
+		let frames = self.frames.iter_mut().filter_map(|x| x.location.as_mut());
+		for it in self.location.iter_mut().chain(frames) {
+			if let Ok(file) = Path::new(&it.file).strip_prefix(dir) {
+				it.file = file.to_string_lossy().to_string();
+			}
+		}
+	}
+
+	fn parse_header(line: &str) -> Option<Finding> {
+		if let Some((_, message)) = line.split_once("ERROR: AddressSanitizer: ") {
+			let kind = message.split_whitespace().next().unwrap_or_default();
+			return Some(Finding {
+				sanitizer: Sanitizer::Address,
+				kind: kind.to_string(),
+				message: message.to_string(),
+				location: None,
+				frames: Vec::new(),
+			});
+		}
+
+		let (location, message) = line.split_once(": runtime error: ")?;
+		let kind = message.split_once(':').map(|x| x.0).unwrap_or(message);
+		Some(Finding {
+			sanitizer: Sanitizer::Undefined,
+			kind: kind.to_string(),
+			message: message.to_string(),
+			location: Location::parse(location),
+			frames: Vec::new(),
+		})
+	}
+}
+
+impl Frame {
+	/// Parse a frame in the `#0 0x4011d6 in main /path/main.c:5:3` format.
+	fn parse(line: &str) -> Option<Frame> {
+		let line = line.trim_start().strip_prefix('#')?;
+		let (index, line) = line.split_once(' ')?;
+		index.parse::<usize>().ok()?;
+
+		let line = line.trim_start();
+		let line = line.split_once(' ').map(|x| x.1).unwrap_or_default().trim();
+		let (func, rest) = match line.strip_prefix("in ") {
+			Some(line) => match line.split_once(' ') {
+				Some((func, rest)) => (Some(func), rest.trim()),
+				None => (Some(line), ""),
+			},
+			None => (None, line),
+		};
+
+		let mut frame = Frame {
+			func: func.map(|x| x.to_string()),
+			location: None,
+			module: None,
+		};
+		if let Some(module) = rest.strip_prefix('(').and_then(|x| x.strip_suffix(')')) {
+			let module = module.rsplit_once('+').map(|x| x.0).unwrap_or(module);
+			frame.module = Some(module.to_string());
+		} else {
+			frame.location = Location::parse(rest);
+		}
+		Some(frame)
+	}
+}
+
+impl Location {
+	/// Parse a `file:line` or `file:line:column` location.
+	fn parse(text: &str) -> Option<Location> {
+		let (head, tail) = text.rsplit_once(':')?;
+		let tail = tail.parse::<usize>().ok()?;
+		let location = match head.rsplit_once(':') {
+			Some((file, line)) if line.parse::<usize>().is_ok() => Location {
+				file: file.to_string(),
+				line: line.parse().unwrap(),
+				column: Some(tail),
+			},
+			_ => Location {
+				file: head.to_string(),
+				line: tail,
+				column: None,
+			},
+		};
+		Some(location)
+	}
+}
+
+impl Display for Finding {
+	fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+		let name = match self.sanitizer {
+			Sanitizer::Address => "AddressSanitizer",
+			Sanitizer::Undefined => "UndefinedBehaviorSanitizer",
+		};
+		write!(f, "{name}: {}", self.message)?;
+		if let Some(location) = &self.location {
+			write!(f, " (at {location})")?;
+		}
+		Ok(())
+	}
+}
+
+impl Display for Location {
+	fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+		write!(f, "{}:{}", self.file, self.line)?;
+		if let Some(column) = self.column {
+			write!(f, ":{column}")?;
+		}
+		Ok(())
+	}
+}
+
+impl Display for Frame {
+	fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+		let func = self.func.as_deref().unwrap_or("??");
+		write!(f, "{func}")?;
+		if let Some(location) = &self.location {
+			write!(f, " at {location}")?;
+		} else if let Some(module) = &self.module {
+			write!(f, " in {module}")?;
+		}
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parse_findings() {
+		let output = text(
+			r#"
+				eval:3:11: runtime error: signed integer overflow: 9223372036854775807 + 1 cannot be represented in type 'long int'
+				    #0 0x562c12baa1fd in f /tmp/san/eval:3
+				    #1 0x562c12baa236 in main /tmp/san/eval:7
+				    #2 0x7f78ec045249  (/lib/x86_64-linux-gnu/libc.so.6+0x27249)
+
+				=================================================================
+				==21006==ERROR: AddressSanitizer: heap-buffer-overflow on address 0x604000000038 at pc 0x562c12baa2dc
+				WRITE of size 4 at 0x604000000038 thread T0
+				    #0 0x562c12baa2db in main /tmp/san/main.c:12:8
+				    #1 0x7f78ec045304 in __libc_start_main (/lib/x86_64-linux-gnu/libc.so.6+0x27304)
+
+				0x604000000038 is located 0 bytes to the right of 40-byte region [0x604000000010,0x604000000038)
+				allocated by thread T0 here:
+				    #0 0x7f78ecab89cf in __interceptor_malloc ../../../../src/libsanitizer/asan/asan_malloc_linux.cpp:69
+				    #1 0x562c12baa220 in main /tmp/san/eval:6
+
+				SUMMARY: AddressSanitizer: heap-buffer-overflow /tmp/san/eval:7 in main
+				==21006==ABORTING
+			"#,
+		);
+
+		let mut list = Finding::parse(&output);
+		for it in list.iter_mut() {
+			it.strip_dir("/tmp/san");
+		}
+		assert_eq!(list.len(), 2);
+
+		let ub = &list[0];
+		assert_eq!(ub.sanitizer, Sanitizer::Undefined);
+		assert_eq!(ub.kind, "signed integer overflow");
+		assert_eq!(
+			ub.to_string(),
+			"UndefinedBehaviorSanitizer: signed integer overflow: 9223372036854775807 + 1 cannot be represented in type 'long int' (at eval:3:11)"
+		);
+		let frames = ub.frames.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+		assert_eq!(
+			frames,
+			["f at eval:3", "main at eval:7", "?? in /lib/x86_64-linux-gnu/libc.so.6"]
+		);
+
+		let asan = &list[1];
+		assert_eq!(asan.sanitizer, Sanitizer::Address);
+		assert_eq!(asan.kind, "heap-buffer-overflow");
+		assert_eq!(asan.frames.len(), 2);
+		assert_eq!(
+			asan.location,
+			Some(Location {
+				file: "main.c".into(),
+				line: 12,
+				column: Some(8),
+			})
+		);
+		assert_eq!(asan.frames[1].func.as_deref(), Some("__libc_start_main"));
+	}
+}
@@ -0,0 +1,327 @@
+use std::{
+	fmt::{Display, Formatter},
+	path::{Path, PathBuf},
+	process::Command,
+};
+
+use super::*;
+
+/// Configuration for the C compiler used to build generated code.
+#[derive(Clone, Debug, Hash)]
+pub struct Toolchain {
+	cc: String,
+	ar: String,
+	flags: Vec<String>,
+	opt: Option<String>,
+	std: Option<String>,
+	libs: Vec<String>,
+	include: Vec<PathBuf>,
+}
+
+impl Toolchain {
+	/// Use the compiler from the `CC` environment variable, defaulting to
+	/// `gcc` if it is not set.
+	///
+	/// The variable can contain additional flags (e.g. `CC="clang -m32"`).
+	pub fn from_env() -> Self {
+		let cc = std::env::var("CC").unwrap_or_default();
+		let mut args = cc.split_whitespace();
+		let mut out = Self::new(args.next().unwrap_or("gcc"));
+		for it in args {
+			out = out.flag(it);
+		}
+		out
+	}
+
+	pub fn new<T: Into<String>>(cc: T) -> Self {
+		let ar = std::env::var("AR").unwrap_or_else(|_| "ar".into());
+		Self {
+			cc: cc.into(),
+			ar,
+			flags: Vec::new(),
+			opt: None,
+			std: None,
+			libs: Vec::new(),
+			include: Vec::new(),
+		}
+	}
+
+	pub fn gcc() -> Self {
+		Self::new("gcc")
+	}
+
+	pub fn clang() -> Self {
+		Self::new("clang")
+	}
+
+	pub fn tcc() -> Self {
+		Self::new("tcc")
+	}
+
+	pub fn cc() -> Self {
+		Self::new("cc")
+	}
+
+	pub fn compiler(&self) -> &str {
+		&self.cc
+	}
+
+	/// Archiver used to build static libraries. Defaults to `AR` or `ar`.
+	pub fn ar<T: Into<String>>(mut self, ar: T) -> Self {
+		self.ar = ar.into();
+		self
+	}
+
+	pub fn flag<T: Into<String>>(mut self, flag: T) -> Self {
+		self.flags.push(flag.into());
+		self
+	}
+
+	/// Optimisation level passed as `-O{level}` (e.g. `0`, `2`, `s`).
+	pub fn opt<T: Into<String>>(mut self, level: T) -> Self {
+		self.opt = Some(level.into());
+		self
+	}
+
+	/// C standard passed as `-std={std}` (e.g. `c99`, `c11`).
+	pub fn std<T: Into<String>>(mut self, std: T) -> Self {
+		self.std = Some(std.into());
+		self
+	}
+
+	/// Link library passed as `-l{name}`.
+	pub fn lib<T: Into<String>>(mut self, name: T) -> Self {
+		self.libs.push(name.into());
+		self
+	}
+
+	pub fn include<T: AsRef<Path>>(mut self, path: T) -> Self {
+		self.include.push(path.as_ref().to_owned());
+		self
+	}
+
+	/// Return the compiler command to build `src` into the `out` executable.
+	pub fn command<T: AsRef<Path>, U: AsRef<Path>>(&self, src: T, out: U) -> Command {
+		let mut cmd = self.cc_command();
+		cmd.arg(src.as_ref()).arg("-o").arg(out.as_ref());
+		self.link_args(&mut cmd);
+		cmd
+	}
+
+	/// Return the compiler command to build `src` into the `out` object
+	/// file, without linking.
+	pub fn compile_command<T: AsRef<Path>, U: AsRef<Path>>(&self, src: T, out: U, pic: bool) -> Command {
+		let mut cmd = self.cc_command();
+		if pic {
+			cmd.arg("-fPIC");
+		}
+		cmd.arg("-c").arg(src.as_ref()).arg("-o").arg(out.as_ref());
+		cmd
+	}
+
+	/// Return the command to link objects into an executable or, if
+	/// `shared` is set, into a shared library.
+	pub fn link_command<T: AsRef<Path>, U: AsRef<Path>>(&self, objects: &[T], out: U, shared: bool) -> Command {
+		let mut cmd = Command::new(&self.cc);
+		cmd.args(&self.flags);
+		if shared {
+			cmd.arg("-shared");
+		}
+		cmd.args(objects.iter().map(|x| x.as_ref()));
+		cmd.arg("-o").arg(out.as_ref());
+		self.link_args(&mut cmd);
+		cmd
+	}
+
+	/// Return the command to create a static library from objects.
+	pub fn archive_command<T: AsRef<Path>, U: AsRef<Path>>(&self, objects: &[T], out: U) -> Command {
+		let mut cmd = Command::new(&self.ar);
+		cmd.arg("rcs").arg(out.as_ref());
+		cmd.args(objects.iter().map(|x| x.as_ref()));
+		cmd
+	}
+
+	fn cc_command(&self) -> Command {
+		let mut cmd = Command::new(&self.cc);
+		cmd.args(&self.flags);
+		if let Some(opt) = &self.opt {
+			cmd.arg(format!("-O{opt}"));
+		}
+		if let Some(std) = &self.std {
+			cmd.arg(format!("-std={std}"));
+		}
+		for it in self.include.iter() {
+			cmd.arg("-I").arg(it);
+		}
+		cmd
+	}
+
+	fn link_args(&self, cmd: &mut Command) {
+		for it in self.libs.iter() {
+			cmd.arg(format!("-l{it}"));
+		}
+	}
+}
+
+impl Default for Toolchain {
+	fn default() -> Self {
+		Self::from_env()
+	}
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Severity {
+	Error,
+	Warning,
+	Note,
+}
+
+impl Display for Severity {
+	fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+		match self {
+			Severity::Error => write!(f, "error"),
+			Severity::Warning => write!(f, "warning"),
+			Severity::Note => write!(f, "note"),
+		}
+	}
+}
+
+/// Message generated by the compiler.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Diagnostic {
+	pub file: String,
+	pub line: Option<usize>,
+	pub column: Option<usize>,
+	pub severity: Severity,
+	pub message: String,
+}
+
+impl Diagnostic {
+	/// Parse the diagnostics from the compiler error output.
+	///
+	/// This understands the `file:line:column: severity: message` format
+	/// used by gcc and clang, and the column-less variant from tcc. Lines
+	/// not in that format (e.g. source excerpts) are ignored.
+	pub fn parse(output: &str) -> Vec<Diagnostic> {
+		output.lines().filter_map(Self::parse_line).collect()
+	}
+
+	fn parse_line(line: &str) -> Option<Diagnostic> {
+		const SEVERITY: [(&str, Severity); 4] = [
+			(": fatal error: ", Severity::Error),
+			(": error: ", Severity::Error),
+			(": warning: ", Severity::Warning),
+			(": note: ", Severity::Note),
+		];
+
+		let (pos, len, severity) = SEVERITY
+			.iter()
+			.filter_map(|(str, severity)| line.find(str).map(|pos| (pos, str.len(), *severity)))
+			.min_by_key(|(pos, ..)| *pos)?;
+
+		let message = line[pos + len..].trim().to_string();
+		let mut file = &line[..pos];
+		let mut numbers = Vec::new();
+		while numbers.len() < 2 {
+			let Some((head, tail)) = file.rsplit_once(':') else {
+				break;
+			};
+			let Ok(number) = tail.parse::<usize>() else {
+				break;
+			};
+			numbers.push(number);
+			file = head;
+		}
+
+		let (line, column) = match numbers[..] {
+			[column, line] => (Some(line), Some(column)),
+			[line] => (Some(line), None),
+			_ => (None, None),
+		};
+
+		Some(Diagnostic {
+			file: file.to_string(),
+			line,
+			column,
+			severity,
+			message,
+		})
+	}
+}
+
+impl Display for Diagnostic {
+	fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+		write!(f, "{}", self.file)?;
+		if let Some(line) = self.line {
+			write!(f, ":{line}")?;
+		}
+		if let Some(column) = self.column {
+			write!(f, ":{column}")?;
+		}
+		write!(f, ": {}: {}", self.severity, self.message)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parse_diagnostics() {
+		let output = text(
+			r#"
+				main.c: In function 'main':
+				main.c:3:13: warning: unused variable 'x' [-Wunused-variable]
+				    3 |         int x;
+				      |             ^
+				main.c:4:9: error: 'y' undeclared (first use in this function)
+				main.c:4:9: note: each undeclared identifier is reported only once
+				main.c:7: error: ';' expected (got "}")
+				collect2: error: ld returned 1 exit status
+				cc1: fatal error: missing.c: No such file or directory
+			"#,
+		);
+
+		let diag = Diagnostic::parse(&output);
+		let list = diag.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+		assert_eq!(
+			list,
+			[
+				"main.c:3:13: warning: unused variable 'x' [-Wunused-variable]",
+				"main.c:4:9: error: 'y' undeclared (first use in this function)",
+				"main.c:4:9: note: each undeclared identifier is reported only once",
+				"main.c:7: error: ';' expected (got \"}\")",
+				"collect2: error: ld returned 1 exit status",
+				"cc1: error: missing.c: No such file or directory",
+			]
+		);
+
+		assert_eq!(
+			diag[0],
+			Diagnostic {
+				file: "main.c".into(),
+				line: Some(3),
+				column: Some(13),
+				severity: Severity::Warning,
+				message: "unused variable 'x' [-Wunused-variable]".into(),
+			}
+		);
+	}
+
+	#[test]
+	fn toolchain_flags() {
+		let cc = Toolchain::clang()
+			.flag("-Wall")
+			.opt("2")
+			.std("c11")
+			.include("some/dir")
+			.lib("m");
+		let cmd = cc.command("main.c", "main.exe");
+		let args = cmd.get_args().map(|x| x.to_string_lossy()).collect::<Vec<_>>();
+		assert_eq!(cmd.get_program(), "clang");
+		assert_eq!(
+			args,
+			["-Wall", "-O2", "-std=c11", "-I", "some/dir", "main.c", "-o", "main.exe", "-lm"]
+		);
+	}
+}
@@ -1,21 +1,50 @@
 use std::{
 	ffi::OsStr,
-	io::{ErrorKind, Read},
+	io::{ErrorKind, Read, Write},
 	path::Path,
-	process::{Command, ExitStatus, Stdio},
-	sync::mpsc,
+	process::{Child, Command, ExitStatus, Stdio},
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		mpsc, Arc,
+	},
 	thread,
+	time::{Duration, Instant},
 };
 
 use super::*;
 
+mod lines;
+pub mod pool;
+pub mod transcript;
+
+pub use pool::*;
+pub use transcript::*;
+
+/// Interval between checks for the timeout and kill requests while a
+/// command is running.
+const POLL: Duration = Duration::from_millis(5);
+
 pub struct Cmd {
 	inner: Command,
+	stdin: Option<Input>,
+	timeout: Option<Duration>,
+	timed_out: bool,
+	kill: Option<Kill>,
+	line_delay: Duration,
+	max_line: Option<usize>,
 }
 
 pub fn new<T: AsRef<OsStr>>(name: T) -> Cmd {
 	let inner = Command::new(name);
-	Cmd { inner }
+	Cmd {
+		inner,
+		stdin: None,
+		timeout: None,
+		timed_out: false,
+		kill: None,
+		line_delay: Duration::from_millis(100),
+		max_line: None,
+	}
 }
 
 #[derive(Debug)]
@@ -24,6 +53,35 @@ pub enum Output {
 	StdOut(String),
 }
 
+/// Input for the command. Without one, the command inherits the stdin of
+/// the current process.
+enum Input {
+	Bytes(Vec<u8>),
+	Writer(Box<StdinWriter>),
+}
+
+type StdinWriter = dyn FnOnce(&mut dyn Write) -> std::io::Result<()> + Send;
+
+/// Handle to kill a running [`Cmd`] from another thread.
+///
+/// Killing before the command starts makes it be killed as soon as it is
+/// spawned. Killing after the command exits still kills any process it
+/// started that keeps its output open.
+#[derive(Clone, Default)]
+pub struct Kill {
+	flag: Arc<AtomicBool>,
+}
+
+impl Kill {
+	pub fn kill(&self) {
+		self.flag.store(true, Ordering::SeqCst);
+	}
+
+	pub fn is_killed(&self) -> bool {
+		self.flag.load(Ordering::SeqCst)
+	}
+}
+
 impl Cmd {
 	pub fn cwd<T: AsRef<Path>>(mut self, path: T) -> Self {
 		self.inner.current_dir(path);
@@ -35,14 +93,123 @@ impl Cmd {
 		self
 	}
 
-	pub fn output<T: FnMut(Output) -> Result<()> + 'static>(&mut self, mut output: T) -> Result<ExitStatus> {
+	pub fn env<T: AsRef<OsStr>, U: AsRef<OsStr>>(mut self, name: T, value: U) -> Self {
+		self.inner.env(name, value);
+		self
+	}
+
+	pub fn env_remove<T: AsRef<OsStr>>(mut self, name: T) -> Self {
+		self.inner.env_remove(name);
+		self
+	}
+
+	/// Start the command with an empty environment, except for variables
+	/// set afterwards with [`Cmd::env`].
+	pub fn env_clear(mut self) -> Self {
+		self.inner.env_clear();
+		self
+	}
+
+	/// Write the given bytes to the command input, then close it.
+	pub fn stdin<T: Into<Vec<u8>>>(mut self, input: T) -> Self {
+		self.stdin = Some(Input::Bytes(input.into()));
+		self
+	}
+
+	/// Stream the command input from a separate thread. The input is closed
+	/// when the writer returns.
+	pub fn stdin_with<T: FnOnce(&mut dyn Write) -> std::io::Result<()> + Send + 'static>(mut self, writer: T) -> Self {
+		self.stdin = Some(Input::Writer(Box::new(writer)));
+		self
+	}
+
+	/// Kill the command and its process group if it runs for longer than
+	/// `timeout`, in which case [`Cmd::output`] fails.
+	///
+	/// Use [`Cmd::output_status`] to get the status of the killed command
+	/// instead.
+	pub fn timeout(mut self, timeout: Duration) -> Self {
+		self.timeout = Some(timeout);
+		self
+	}
+
+	/// Handle to kill the command and its process group from another thread.
+	///
+	/// A killed command is not an error: [`Cmd::output`] returns the exit
+	/// status of the killed process.
+	pub fn kill(&mut self) -> Kill {
+		self.kill.get_or_insert_with(Kill::default).clone()
+	}
+
+	pub fn output<T: FnMut(Output) -> Result<()> + 'static>(&mut self, output: T) -> Result<ExitStatus> {
+		let status = self.output_status(output)?;
+		self.check_timeout()?;
+		Ok(status)
+	}
+
+	/// Run the command like [`Cmd::output`], except that reaching the
+	/// timeout is not an error. See [`Cmd::timed_out`].
+	pub fn output_status<T: FnMut(Output) -> Result<()> + 'static>(&mut self, mut output: T) -> Result<ExitStatus> {
+		self.run(|out| match out {
+			Some(out) => output(out),
+			None => Ok(()),
+		})
+	}
+
+	/// True if the last run was killed by the [`Cmd::timeout`].
+	pub fn timed_out(&self) -> bool {
+		self.timed_out
+	}
+
+	fn check_timeout(&self) -> Result<()> {
+		if let (true, Some(timeout)) = (self.timed_out, self.timeout) {
+			let name = self.inner.get_program().to_string_lossy();
+			Err(format!("`{name}` timed out after {timeout:?}"))?;
+		}
+		Ok(())
+	}
+
+	/// Run the command, calling `handler` with each chunk of output in the
+	/// order they arrive, and with `None` whenever no output arrived for a
+	/// short interval.
+	///
+	/// Reaching the timeout is not an error here, it only sets `timed_out`.
+	fn run<T: FnMut(Option<Output>) -> Result<()>>(&mut self, mut handler: T) -> Result<ExitStatus> {
 		self.inner.stderr(Stdio::piped());
 		self.inner.stdout(Stdio::piped());
+		if self.stdin.is_some() {
+			self.inner.stdin(Stdio::piped());
+		}
+
+		// run in a separate process group, so killing also reaches any
+		// process started by the command
+		let group = self.timeout.is_some() || self.kill.is_some();
+		#[cfg(unix)]
+		if group {
+			std::os::unix::process::CommandExt::process_group(&mut self.inner, 0);
+		}
 
+		let start = Instant::now();
+		self.timed_out = false;
 		let mut child = self.inner.spawn()?;
 		let stderr = child.stderr.take().unwrap();
 		let stdout = child.stdout.take().unwrap();
 
+		let t_in = match (self.stdin.take(), child.stdin.take()) {
+			(Some(input), Some(mut stdin)) => Some(thread::spawn(move || {
+				let result = match input {
+					Input::Bytes(bytes) => stdin.write_all(&bytes),
+					Input::Writer(writer) => writer(&mut stdin),
+				};
+				// the command may exit without reading its input
+				match result {
+					Err(err) if err.kind() != ErrorKind::BrokenPipe => Err(err),
+					_ => Ok(()),
+				}
+			})),
+			_ => None,
+		};
+
 		let (tx, rx) = mpsc::channel();
 
 		let tx_err = tx.clone();
@@ -50,16 +217,72 @@ impl Cmd {
 		let t1 = thread::spawn(|| chunk_output(stderr, tx_err, true));
 		let t2 = thread::spawn(|| chunk_output(stdout, tx_out, false));
 
-		for it in rx {
-			output(it)?;
+		let mut timed_out = false;
+		let mut should_kill = || {
+			let killed = self.kill.as_ref().is_some_and(|x| x.is_killed());
+			timed_out = timed_out || self.timeout.is_some_and(|x| start.elapsed() >= x);
+			killed || timed_out
+		};
+
+		let mut failed = None;
+		loop {
+			// keep killing until the output is closed, as processes started
+			// by the command may keep it open after the command exits
+			if should_kill() {
+				kill_child(&mut child, group);
+			}
+
+			let result = match rx.recv_timeout(POLL) {
+				Ok(it) => handler(Some(it)),
+				Err(mpsc::RecvTimeoutError::Timeout) => handler(None),
+				Err(mpsc::RecvTimeoutError::Disconnected) => break,
+			};
+			if let Err(err) = result {
+				kill_child(&mut child, group);
+				failed = Some(err);
+				break;
+			}
+		}
+
+		// the command may also close its output and keep running
+		drop(rx);
+		let status = loop {
+			if let Some(status) = child.try_wait()? {
+				break status;
+			}
+			if should_kill() {
+				kill_child(&mut child, group);
+			}
+			thread::sleep(POLL);
+		};
+
+		let t1 = t1.join().map_err(|_| "thread join failed")?;
+		let t2 = t2.join().map_err(|_| "thread join failed")?;
+		let t_in = t_in.map(|x| x.join().map_err(|_| "thread join failed"));
+		if let Some(err) = failed {
+			return Err(err);
+		}
+		t1?;
+		t2?;
+		if let Some(t_in) = t_in {
+			t_in??;
 		}
 
-		t1.join().map_err(|_| "thread join failed")??;
-		t2.join().map_err(|_| "thread join failed")??;
+		self.timed_out = timed_out;
+		Ok(status)
+	}
+}
 
-		let result = child.wait()?;
-		Ok(result)
+/// Kill the child process, and its process group if it has its own.
+fn kill_child(child: &mut Child, group: bool) {
+	// the child is the leader of its group until it is waited for
+	#[cfg(unix)]
+	if group {
+		let _ = sys::kill(-(child.id() as i32), sys::SIGKILL);
 	}
+	#[cfg(not(unix))]
+	let _ = group;
+	let _ = child.kill();
 }
 
 fn chunk_output<T: Read>(mut output: T, sender: mpsc::Sender<Output>, stderr: bool) -> Result<()> {
@@ -177,6 +400,95 @@ mod tests {
 		Ok(())
 	}
 
+	#[test]
+	fn env_and_stdin() -> Result<()> {
+		let cmd = new("sh")
+			.arg("-c")
+			.arg("echo \"$BIT_CMD_TEST_SET:$BIT_CMD_TEST_REMOVED\"; cat")
+			.env("BIT_CMD_TEST_SET", "yes")
+			.env("BIT_CMD_TEST_REMOVED", "no")
+			.env_remove("BIT_CMD_TEST_REMOVED")
+			.stdin("from stdin\n");
+		let (out, status) = capture(cmd)?;
+		assert!(status.success());
+		assert_eq!(out, "yes:\nfrom stdin\n");
+
+		let cmd = new("/bin/sh")
+			.arg("-c")
+			.arg("echo \"[$HOME]\"; wc -l")
+			.env_clear()
+			.stdin_with(|input| {
+				for i in 0..1000 {
+					writeln!(input, "line {i}")?;
+				}
+				Ok(())
+			});
+		let (out, _) = capture(cmd)?;
+		assert_eq!(out.split_whitespace().collect::<Vec<_>>(), ["[]", "1000"]);
+		Ok(())
+	}
+
+	#[test]
+	fn timeout_and_kill() -> Result<()> {
+		// the background process keeps the output open unless the whole
+		// process group is killed
+		let start = Instant::now();
+		let cmd = new("sh")
+			.arg("-c")
+			.arg("sleep 10 & sleep 10")
+			.timeout(Duration::from_millis(100));
+		let err = capture(cmd).unwrap_err().to_string();
+		assert!(err.starts_with("`sh` timed out after"), "{err}");
+		assert!(start.elapsed() < Duration::from_secs(5));
+
+		// the timeout also applies after the command exits, while processes
+		// it started keep the output open
+		let start = Instant::now();
+		let cmd = new("sh")
+			.arg("-c")
+			.arg("sleep 10 &")
+			.timeout(Duration::from_millis(100));
+		let err = capture(cmd).unwrap_err().to_string();
+		assert!(err.starts_with("`sh` timed out after"), "{err}");
+		assert!(start.elapsed() < Duration::from_secs(5));
+
+		// and when the command closes its output and keeps running
+		let start = Instant::now();
+		let cmd = new("sh")
+			.arg("-c")
+			.arg("exec >&- 2>&-; sleep 10")
+			.timeout(Duration::from_millis(100));
+		let err = capture(cmd).unwrap_err().to_string();
+		assert!(err.starts_with("`sh` timed out after"), "{err}");
+		assert!(start.elapsed() < Duration::from_secs(5));
+
+		let start = Instant::now();
+
+		let mut cmd = new("sh").arg("-c").arg("echo started; sleep 10 & sleep 10");
+		let kill = cmd.kill();
+		let t = thread::spawn(move || {
+			thread::sleep(Duration::from_millis(100));
+			kill.kill();
+		});
+		let (out, status) = capture(cmd)?;
+		t.join().unwrap();
+		assert_eq!(out, "started\n");
+		assert!(!status.success());
+		assert!(start.elapsed() < Duration::from_secs(5));
+		Ok(())
+	}
+
+	fn capture(mut cmd: Cmd) -> Result<(String, ExitStatus)> {
+		let (tx, rx) = mpsc::channel();
+		let status = cmd.output(move |out| {
+			if let Output::StdOut(out) = out {
+				tx.send(out)?;
+			}
+			Ok(())
+		})?;
+		Ok((rx.try_iter().collect(), status))
+	}
+
 	struct SplitReader {
 		input: &'static str,
 		pos: usize,
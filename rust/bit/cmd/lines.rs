@@ -0,0 +1,141 @@
+use super::*;
+
+impl Cmd {
+	/// Time without new output after which [`Cmd::lines`] delivers a
+	/// partial line. Defaults to 100ms.
+	pub fn line_delay(mut self, delay: Duration) -> Self {
+		self.line_delay = delay;
+		self
+	}
+
+	/// Maximum number of characters in a line for [`Cmd::lines`]. Longer
+	/// lines are split.
+	pub fn max_line(mut self, max: usize) -> Self {
+		self.max_line = Some(max);
+		self
+	}
+
+	/// Run the command calling `output` with whole lines of each stream.
+	///
+	/// Lines include the line break. Partial lines are delivered without one,
+	/// either when the stream has been idle for the [`Cmd::line_delay`], when
+	/// they reach the [`Cmd::max_line`] length, or when the command exits.
+	pub fn lines<T: FnMut(Output) -> Result<()> + 'static>(&mut self, mut output: T) -> Result<ExitStatus> {
+		let mut buffer = LineBuffer {
+			delay: self.line_delay,
+			max: self.max_line,
+			stdout: Pending::default(),
+			stderr: Pending::default(),
+		};
+		let status = self.run(|out| match out {
+			Some(out) => buffer.push(out, &mut output),
+			None => buffer.flush(false, &mut output),
+		})?;
+		buffer.flush(true, &mut output)?;
+		self.check_timeout()?;
+		Ok(status)
+	}
+}
+
+struct LineBuffer {
+	delay: Duration,
+	max: Option<usize>,
+	stdout: Pending,
+	stderr: Pending,
+}
+
+#[derive(Default)]
+struct Pending {
+	text: String,
+	last: Option<Instant>,
+}
+
+impl LineBuffer {
+	fn push<T: FnMut(Output) -> Result<()>>(&mut self, out: Output, output: &mut T) -> Result<()> {
+		let (is_err, text) = match out {
+			Output::StdErr(text) => (true, text),
+			Output::StdOut(text) => (false, text),
+		};
+		let pending = if is_err { &mut self.stderr } else { &mut self.stdout };
+		pending.text.push_str(&text);
+		pending.last = Some(Instant::now());
+		while let Some(line) = next_line(&mut pending.text, self.max) {
+			output(if is_err {
+				Output::StdErr(line)
+			} else {
+				Output::StdOut(line)
+			})?;
+		}
+		Ok(())
+	}
+
+	/// Deliver partial lines that have been idle for too long, or all of
+	/// them if `all` is set.
+	fn flush<T: FnMut(Output) -> Result<()>>(&mut self, all: bool, output: &mut T) -> Result<()> {
+		let delay = self.delay;
+		let idle = |pending: &Pending| all || pending.last.is_some_and(|x| x.elapsed() >= delay);
+		if !self.stdout.text.is_empty() && idle(&self.stdout) {
+			output(Output::StdOut(std::mem::take(&mut self.stdout.text)))?;
+		}
+		if !self.stderr.text.is_empty() && idle(&self.stderr) {
+			output(Output::StdErr(std::mem::take(&mut self.stderr.text)))?;
+		}
+		Ok(())
+	}
+}
+
+/// Remove the next complete line from the text, splitting lines with more
+/// than `max` characters.
+fn next_line(text: &mut String, max: Option<usize>) -> Option<String> {
+	let end = text.find('\n').map(|n| n + 1);
+	if let Some(max) = max {
+		if let Some((pos, _)) = text.char_indices().nth(max) {
+			if end.is_none_or(|end| pos < end - 1) {
+				return Some(text.drain(..pos).collect());
+			}
+		}
+	}
+	let end = end?;
+	Some(text.drain(..end).collect())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn whole_lines() -> Result<()> {
+		let script = "printf 'a'; sleep 0.02; printf 'b\\nc'; sleep 0.3; printf 'd\\n1234567'; echo e >&2; printf 'x'";
+		let (tx, rx) = mpsc::channel();
+		let status = new("sh")
+			.arg("-c")
+			.arg(script)
+			.line_delay(Duration::from_millis(150))
+			.max_line(5)
+			.lines(move |out| {
+				tx.send(match out {
+					Output::StdOut(line) => line,
+					Output::StdErr(line) => format!("! {line}"),
+				})?;
+				Ok(())
+			})?;
+		assert!(status.success());
+
+		let mut lines = rx.try_iter().collect::<Vec<_>>();
+		// the order of the streams is not defined
+		lines.sort_by_key(|x| x.starts_with('!'));
+		assert_eq!(lines, ["ab\n", "c", "d\n", "12345", "67x", "! e\n"]);
+		Ok(())
+	}
+
+	#[test]
+	fn split_lines() {
+		let mut text = "abcd\nabcde\né€日本語\n".to_string();
+		let mut lines = Vec::new();
+		while let Some(line) = next_line(&mut text, Some(4)) {
+			lines.push(line);
+		}
+		assert_eq!(lines, ["abcd\n", "abcd", "e\n", "é€日本", "語\n"]);
+		assert_eq!(text, "");
+	}
+}
@@ -0,0 +1,241 @@
+use std::{
+	any::Any,
+	collections::HashMap,
+	fmt::{Display, Formatter, Write},
+	panic::{self, AssertUnwindSafe},
+};
+
+use super::*;
+
+/// Runs many commands with limited parallelism.
+///
+/// The output of each job is recorded and delivered as a whole when the job
+/// finishes, so the output of concurrent jobs is never shuffled.
+pub struct Pool {
+	parallel: usize,
+	fail_fast: bool,
+	jobs: Vec<(String, Cmd)>,
+}
+
+/// Finished job, see [`Pool::run`].
+pub struct Job {
+	pub label: String,
+	pub status: Status,
+	pub duration: Duration,
+	pub transcript: Transcript,
+}
+
+#[derive(Clone, Debug)]
+pub enum Status {
+	Exit(ExitStatus),
+	/// The command could not run.
+	Error(String),
+	/// The job was not started because an earlier job failed.
+	Skipped,
+}
+
+impl Status {
+	pub fn success(&self) -> bool {
+		matches!(self, Status::Exit(status) if status.success())
+	}
+}
+
+impl Display for Status {
+	fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+		match self {
+			Status::Exit(status) => write!(f, "{status}"),
+			Status::Error(err) => write!(f, "error: {err}"),
+			Status::Skipped => write!(f, "skipped"),
+		}
+	}
+}
+
+impl Job {
+	/// Output of the job with every line prefixed by its label.
+	pub fn text(&self) -> String {
+		let mut out = String::new();
+		for line in self.transcript.text().lines() {
+			let _ = writeln!(out, "[{}] {line}", self.label);
+		}
+		out
+	}
+}
+
+impl Pool {
+	/// Pool running up to `parallel` jobs at the same time.
+	pub fn new(parallel: usize) -> Self {
+		Self {
+			parallel: parallel.max(1),
+			fail_fast: false,
+			jobs: Vec::new(),
+		}
+	}
+
+	/// Pool running as many jobs as there are CPUs.
+	pub fn with_cpus() -> Self {
+		let cpus = thread::available_parallelism().map(|x| x.get()).unwrap_or(1);
+		Self::new(cpus)
+	}
+
+	/// Stop at the first failed job, killing the running jobs and skipping
+	/// the ones not started. By default, all jobs run.
+	pub fn fail_fast(mut self, fail_fast: bool) -> Self {
+		self.fail_fast = fail_fast;
+		self
+	}
+
+	pub fn add<T: Into<String>>(&mut self, label: T, cmd: Cmd) {
+		self.jobs.push((label.into(), cmd));
+	}
+
+	/// Run all jobs, calling `done` as each job finishes.
+	///
+	/// Returns the jobs in the order they were added. Skipped jobs are not
+	/// passed to `done`.
+	pub fn run<T: FnMut(&Job) -> Result<()>>(self, mut done: T) -> Result<Vec<Job>> {
+		let (tx, rx) = mpsc::channel();
+		let mut jobs = self.jobs.into_iter().enumerate();
+		let mut results = Vec::new();
+		let mut running = HashMap::new();
+		let mut failed = false;
+
+		let result = loop {
+			while running.len() < self.parallel && !failed {
+				let Some((index, (label, mut cmd))) = jobs.next() else {
+					break;
+				};
+				running.insert(index, cmd.kill());
+				let tx = tx.clone();
+				thread::spawn(move || {
+					let start = Instant::now();
+					let transcript = Transcript::new();
+					let recorder = transcript.clone();
+					// a panic must still report the job, or `run` would wait
+					// for it forever
+					let result = panic::catch_unwind(AssertUnwindSafe(|| {
+						cmd.output(move |out| {
+							recorder.record(&out);
+							Ok(())
+						})
+					}));
+					let status = match result {
+						Ok(Ok(status)) => Status::Exit(status),
+						Ok(Err(err)) => Status::Error(err.to_string()),
+						Err(err) => Status::Error(panic_message(err)),
+					};
+					let job = Job {
+						label,
+						status,
+						duration: start.elapsed(),
+						transcript,
+					};
+					let _ = tx.send((index, job));
+				});
+			}
+
+			if running.is_empty() {
+				break Ok(());
+			}
+
+			let (index, job) = rx.recv().map_err(|_| "job thread failed")?;
+			running.remove(&index);
+			if self.fail_fast && !job.status.success() && !failed {
+				failed = true;
+				for kill in running.values() {
+					kill.kill();
+				}
+			}
+			let result = done(&job);
+			results.push((index, job));
+			if result.is_err() {
+				break result;
+			}
+		};
+
+		if let Err(err) = result {
+			for kill in running.values() {
+				kill.kill();
+			}
+			return Err(err);
+		}
+
+		for (index, (label, _)) in jobs {
+			let job = Job {
+				label,
+				status: Status::Skipped,
+				duration: Duration::ZERO,
+				transcript: Transcript::new(),
+			};
+			results.push((index, job));
+		}
+		results.sort_by_key(|x| x.0);
+		Ok(results.into_iter().map(|x| x.1).collect())
+	}
+}
+
+fn panic_message(err: Box<dyn Any + Send>) -> String {
+	let msg = match err.downcast_ref::<&str>() {
+		Some(msg) => msg.to_string(),
+		None => err.downcast_ref::<String>().cloned().unwrap_or_default(),
+	};
+	format!("job panicked: {msg}")
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn keep_going() -> Result<()> {
+		let start = Instant::now();
+		let mut pool = Pool::new(2);
+		for n in 1..=4 {
+			let script = format!("echo start {n}; sleep 0.2; echo end {n}; exit {}", n % 2);
+			pool.add(format!("job{n}"), new("sh").arg("-c").arg(script));
+		}
+
+		let mut finished = Vec::new();
+		let jobs = pool.run(|job| {
+			finished.push(job.label.clone());
+			Ok(())
+		})?;
+
+		let elapsed = start.elapsed();
+		assert!(elapsed >= Duration::from_millis(400) && elapsed < Duration::from_secs(2));
+		assert_eq!(finished.len(), 4);
+
+		let labels = jobs.iter().map(|x| x.label.as_str()).collect::<Vec<_>>();
+		assert_eq!(labels, ["job1", "job2", "job3", "job4"]);
+		let success = jobs.iter().map(|x| x.status.success()).collect::<Vec<_>>();
+		assert_eq!(success, [false, true, false, true]);
+		assert_eq!(jobs[2].text(), "[job3] start 3\n[job3] end 3\n");
+		assert_eq!(jobs[0].status.to_string(), "exit status: 1");
+		Ok(())
+	}
+
+	#[test]
+	fn fail_fast() -> Result<()> {
+		let start = Instant::now();
+		let mut pool = Pool::new(2).fail_fast(true);
+		pool.add("slow", new("sh").arg("-c").arg("sleep 10"));
+		pool.add("fail", new("sh").arg("-c").arg("sleep 0.1; exit 3"));
+		pool.add("never", new("sh").arg("-c").arg("echo never"));
+		pool.add("missing", new("/bit/missing/command"));
+
+		let jobs = pool.run(|_| Ok(()))?;
+		assert!(start.elapsed() < Duration::from_secs(5));
+
+		let status = jobs.iter().map(|x| x.status.to_string()).collect::<Vec<_>>();
+		assert_eq!(status, ["signal: 9 (SIGKILL)", "exit status: 3", "skipped", "skipped"]);
+		assert_eq!(jobs[2].text(), "");
+
+		let mut pool = Pool::new(1);
+		pool.add("missing", new("/bit/missing/command"));
+		let jobs = pool.run(|_| Ok(()))?;
+		assert!(matches!(jobs[0].status, Status::Error(..)));
+
+		let err = panic::catch_unwind(|| panic!("boom {}", 1)).unwrap_err();
+		assert_eq!(panic_message(err), "job panicked: boom 1");
+		Ok(())
+	}
+}
@@ -0,0 +1,202 @@
+use std::{
+	fmt::Write,
+	sync::{Arc, Mutex},
+	time::{Duration, Instant},
+};
+
+use super::*;
+
+/// Prefix for stderr lines in [`Transcript::text`].
+pub const STDERR_PREFIX: &str = "! ";
+
+/// Ordered record of the output of a command.
+///
+/// Chunks are kept in the order they arrived, tagged with their stream and
+/// the time since the transcript was created. Clones share the same record,
+/// so one can be moved into the [`Cmd::output`] callback.
+#[derive(Clone)]
+pub struct Transcript {
+	start: Instant,
+	chunks: Arc<Mutex<Vec<Chunk>>>,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Stream {
+	StdOut,
+	StdErr,
+}
+
+#[derive(Clone, Debug)]
+pub struct Chunk {
+	pub stream: Stream,
+	pub time: Duration,
+	pub text: String,
+}
+
+impl Default for Transcript {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl Transcript {
+	pub fn new() -> Self {
+		Self {
+			start: Instant::now(),
+			chunks: Default::default(),
+		}
+	}
+
+	pub fn record(&self, output: &Output) {
+		let (stream, text) = match output {
+			Output::StdOut(text) => (Stream::StdOut, text),
+			Output::StdErr(text) => (Stream::StdErr, text),
+		};
+		let chunk = Chunk {
+			stream,
+			time: self.start.elapsed(),
+			text: text.clone(),
+		};
+		self.chunks.lock().unwrap().push(chunk);
+	}
+
+	pub fn chunks(&self) -> Vec<Chunk> {
+		self.chunks.lock().unwrap().clone()
+	}
+
+	/// Output of both streams in arrival order, with stderr lines prefixed
+	/// by [`STDERR_PREFIX`].
+	///
+	/// Lines never mix streams: a line interrupted by the other stream is
+	/// ended before the other stream's output.
+	pub fn text(&self) -> String {
+		let mut out = String::new();
+		let mut line = None;
+		for chunk in self.chunks.lock().unwrap().iter() {
+			for text in chunk.text.split_inclusive('\n') {
+				if line.is_some_and(|x| x != chunk.stream) {
+					out.push('\n');
+					line = None;
+				}
+				if line.is_none() && chunk.stream == Stream::StdErr {
+					out.push_str(STDERR_PREFIX);
+				}
+				out.push_str(text);
+				line = if text.ends_with('\n') { None } else { Some(chunk.stream) };
+			}
+		}
+		out
+	}
+
+	pub fn stdout(&self) -> String {
+		self.stream(Stream::StdOut)
+	}
+
+	pub fn stderr(&self) -> String {
+		self.stream(Stream::StdErr)
+	}
+
+	pub fn stream(&self, stream: Stream) -> String {
+		let chunks = self.chunks.lock().unwrap();
+		chunks
+			.iter()
+			.filter(|x| x.stream == stream)
+			.map(|x| x.text.as_str())
+			.collect()
+	}
+
+	/// One JSON object per chunk, with the time in seconds:
+	///
+	/// ```text
+	/// {"time":0.001250,"stream":"stdout","text":"hello\n"}
+	/// ```
+	pub fn json_lines(&self) -> String {
+		let mut out = String::new();
+		for chunk in self.chunks.lock().unwrap().iter() {
+			let stream = match chunk.stream {
+				Stream::StdOut => "stdout",
+				Stream::StdErr => "stderr",
+			};
+			let _ = write!(
+				out,
+				"{{\"time\":{:.6},\"stream\":\"{stream}\",\"text\":",
+				chunk.time.as_secs_f64()
+			);
+			json_string(&mut out, &chunk.text);
+			out.push_str("}\n");
+		}
+		out
+	}
+}
+
+impl Cmd {
+	/// Run the command recording its output.
+	pub fn transcript(&mut self) -> Result<(Transcript, ExitStatus)> {
+		let transcript = Transcript::new();
+		let recorder = transcript.clone();
+		let status = self.output(move |out| {
+			recorder.record(&out);
+			Ok(())
+		})?;
+		Ok((transcript, status))
+	}
+}
+
+fn json_string(out: &mut String, text: &str) {
+	out.push('"');
+	for chr in text.chars() {
+		match chr {
+			'"' => out.push_str("\\\""),
+			'\\' => out.push_str("\\\\"),
+			'\n' => out.push_str("\\n"),
+			'\r' => out.push_str("\\r"),
+			'\t' => out.push_str("\\t"),
+			chr if chr < ' ' => {
+				let _ = write!(out, "\\u{:04x}", chr as u32);
+			}
+			chr => out.push(chr),
+		}
+	}
+	out.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn interleaved_output() -> Result<()> {
+		// sleep between writes, since each stream is read by its own thread
+		let script = [
+			"echo out1",
+			"echo err1 >&2",
+			"printf partial",
+			"echo err2 >&2",
+			"echo done",
+		]
+		.join("; sleep 0.05; ");
+		let (transcript, status) = new("sh").arg("-c").arg(script).transcript()?;
+		assert!(status.success());
+		assert_eq!(transcript.text(), "out1\n! err1\npartial\n! err2\ndone\n");
+		assert_eq!(transcript.stdout(), "out1\npartialdone\n");
+		assert_eq!(transcript.stderr(), "err1\nerr2\n");
+
+		let chunks = transcript.chunks();
+		assert!(chunks.windows(2).all(|x| x[0].time <= x[1].time));
+		Ok(())
+	}
+
+	#[test]
+	fn json_lines() {
+		let transcript = Transcript::new();
+		transcript.record(&Output::StdOut("say \"hi\"\n".into()));
+		transcript.record(&Output::StdErr("\t\u{1}".into()));
+
+		let json = transcript.json_lines();
+		let lines = json.lines().collect::<Vec<_>>();
+		assert_eq!(lines.len(), 2);
+		assert!(lines[0].starts_with("{\"time\":0.0"));
+		assert!(lines[0].ends_with(r#","stream":"stdout","text":"say \"hi\"\n"}"#));
+		assert!(lines[1].ends_with(r#","stream":"stderr","text":"\t\u0001"}"#));
+	}
+}
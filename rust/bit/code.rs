@@ -1,3 +1,5 @@
+use super::*;
+
 /// Intermediate representation for executable code and types.
 ///
 /// The goal of this representation is to allow direct execution in a VM,
@@ -8,6 +10,128 @@
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
 pub enum Code<'a> {
 	Int(i64),
+	/// Arbitrary-precision integer literal as little endian `u32` limbs,
+	/// as returned by [`int::parse_int`].
+	BigInt(&'a [u32]),
 	Str(&'a str),
 	Print(&'a [Code<'a>]),
+
+	/// Declare a new variable in the current block. The variable type is
+	/// the type of its initial value.
+	Let(Sym<'a>, &'a Code<'a>),
+	Get(Sym<'a>),
+	Set(Sym<'a>, &'a Code<'a>),
+
+	Binary(BinaryOp, &'a Code<'a>, &'a Code<'a>),
+	Unary(UnaryOp, &'a Code<'a>),
+
+	/// Sequence of statements with their own variable scope.
+	Block(&'a [Code<'a>]),
+	If(&'a Code<'a>, &'a Code<'a>, Option<&'a Code<'a>>),
+	While(&'a Code<'a>, &'a Code<'a>),
+	Break,
+	Continue,
+	Return(Option<&'a Code<'a>>),
+
+	/// Function definition. Functions are visible in their entire block,
+	/// including before the definition, and can't access outer variables.
+	Func(&'a FuncDef<'a>),
+	Call(Sym<'a>, &'a [Code<'a>]),
+
+	/// Source location for the inner code.
+	At(Span<'a>, &'a Code<'a>),
+}
+
+impl<'a> Code<'a> {
+	/// Return the code without any location information.
+	pub fn inner(&self) -> &Code<'a> {
+		match self {
+			Code::At(_, code) => code.inner(),
+			code => code,
+		}
+	}
+
+	/// Return the statement that unconditionally leaves the code, if any.
+	pub fn exit(&self) -> Option<&'static str> {
+		match self.inner() {
+			Code::Return(..) => Some("return"),
+			Code::Break => Some("break"),
+			Code::Continue => Some("continue"),
+			Code::Block(list) => list.iter().find_map(|x| x.exit()),
+			Code::If(_, then, Some(other)) => then.exit().and(other.exit()),
+			_ => None,
+		}
+	}
+
+	/// Return the innermost source location for the code, if any.
+	pub fn span(&self) -> Option<Span<'a>> {
+		match self {
+			Code::At(span, code) => code.span().or(Some(*span)),
+			_ => None,
+		}
+	}
+}
+
+/// Type of a [`Code`] value.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Kind {
+	Void,
+	Bool,
+	Str,
+	I64,
+	BigInt,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct FuncDef<'a> {
+	pub name: Sym<'a>,
+	pub args: &'a [Param<'a>],
+	pub ret: Kind,
+	pub body: &'a Code<'a>,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct Param<'a> {
+	pub name: Sym<'a>,
+	pub kind: Kind,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum BinaryOp {
+	Add,
+	Sub,
+	Mul,
+	Div,
+	Mod,
+	Eq,
+	Ne,
+	Lt,
+	Le,
+	Gt,
+	Ge,
+	And,
+	Or,
+}
+
+impl BinaryOp {
+	pub fn is_compare(&self) -> bool {
+		matches!(
+			self,
+			BinaryOp::Eq | BinaryOp::Ne | BinaryOp::Lt | BinaryOp::Le | BinaryOp::Gt | BinaryOp::Ge
+		)
+	}
+
+	pub fn is_logic(&self) -> bool {
+		matches!(self, BinaryOp::And | BinaryOp::Or)
+	}
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum UnaryOp {
+	Neg,
+	Not,
+	/// String length in bytes.
+	Len,
+	/// String length in code points.
+	Chars,
 }
@@ -1,3 +1,5 @@
+pub mod asm;
+pub mod check;
 pub mod clang;
 pub mod cmd;
 pub mod code;
@@ -5,24 +7,33 @@ pub mod int;
 pub mod lexer;
 pub mod names;
 pub mod nodes;
+pub mod pass;
 pub mod pretty;
 pub mod result;
+pub mod rust;
+pub mod scope;
+pub mod sexpr;
 pub mod sources;
 pub mod span;
 pub mod store;
 pub mod strings;
+mod sys;
 pub mod temp;
 pub mod term;
 pub mod types;
 pub mod unicode;
 pub mod values;
+pub mod vm;
 
+pub use check::*;
 pub use code::*;
 pub use lexer::*;
 pub use names::*;
 pub use nodes::*;
+pub use pass::{Pass, PassManager};
 pub use pretty::*;
 pub use result::*;
+pub use scope::Scopes;
 pub use sources::*;
 pub use span::*;
 pub use store::*;
@@ -31,5 +42,5 @@ pub use types::*;
 pub use values::*;
 
 pub fn error<T: std::fmt::Display>(msg: T) {
-	let _ = term::error(std::io::stderr(), msg);
+	let _ = term::Stream::Stderr.error(msg);
 }
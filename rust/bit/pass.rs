@@ -0,0 +1,328 @@
+use std::fmt::Write;
+
+use super::*;
+
+pub mod copy;
+pub mod dce;
+pub mod fold;
+
+pub use copy::*;
+pub use dce::*;
+pub use fold::*;
+
+/// Rewriting pass over [`Code`].
+pub trait Pass {
+	fn name(&self) -> &'static str;
+
+	/// Rewrite the code, returning the new code and whether anything changed.
+	fn run<'a>(&mut self, store: &'a Store, code: Code<'a>) -> (Code<'a>, bool);
+}
+
+/// Runs a sequence of passes until none of them changes the code.
+pub struct PassManager<'a> {
+	store: &'a Store,
+	passes: Vec<Box<dyn Pass>>,
+	max_rounds: usize,
+	dump: bool,
+	output: String,
+}
+
+impl<'a> PassManager<'a> {
+	pub fn new(store: &'a Store) -> Self {
+		Self {
+			store,
+			passes: Vec::new(),
+			max_rounds: 10,
+			dump: false,
+			output: String::new(),
+		}
+	}
+
+	/// Manager with constant folding, copy propagation and dead code
+	/// elimination.
+	pub fn standard(store: &'a Store) -> Self {
+		let mut out = Self::new(store);
+		out.add(Fold);
+		out.add(CopyProp);
+		out.add(Dce);
+		out
+	}
+
+	pub fn add<T: Pass + 'static>(&mut self, pass: T) {
+		self.passes.push(Box::new(pass));
+	}
+
+	/// Maximum number of times the whole sequence of passes runs.
+	pub fn set_max_rounds(&mut self, rounds: usize) {
+		self.max_rounds = rounds;
+	}
+
+	/// Record the IR before and after every pass.
+	pub fn set_dump(&mut self, dump: bool) {
+		self.dump = dump;
+	}
+
+	/// IR recorded with [`PassManager::set_dump`].
+	pub fn dump_output(&self) -> &str {
+		&self.output
+	}
+
+	pub fn run(&mut self, code: Code<'a>) -> Code<'a> {
+		let mut code = code;
+		for round in 1..=self.max_rounds {
+			let mut changed = false;
+			for pass in self.passes.iter_mut() {
+				if self.dump {
+					let _ = writeln!(self.output, "=== before {} (round {round}) ===", pass.name());
+					let _ = writeln!(self.output, "{code}");
+				}
+
+				let (out, pass_changed) = pass.run(self.store, code);
+				code = out;
+				changed = changed || pass_changed;
+
+				if self.dump {
+					let status = if pass_changed { "changed" } else { "unchanged" };
+					let _ = writeln!(self.output, "=== after {} ({status}) ===", pass.name());
+					let _ = writeln!(self.output, "{code}");
+				}
+			}
+			if !changed {
+				break;
+			}
+		}
+		code
+	}
+}
+
+/// Rebuild the code applying `f` to each direct child.
+pub fn map_children<'a, F: FnMut(Code<'a>) -> Code<'a>>(store: &'a Store, code: Code<'a>, mut f: F) -> Code<'a> {
+	let mut list = |list: &'a [Code<'a>]| -> &'a [Code<'a>] {
+		let items = list.iter().map(|x| f(*x)).collect::<Vec<_>>();
+		store.add_list(items)
+	};
+	match code {
+		Code::Int(..) | Code::BigInt(..) | Code::Str(..) | Code::Get(..) | Code::Break | Code::Continue => code,
+		Code::Print(args) => Code::Print(list(args)),
+		Code::Block(items) => Code::Block(list(items)),
+		Code::Call(name, args) => Code::Call(name, list(args)),
+		Code::Let(name, value) => Code::Let(name, store.add(f(*value))),
+		Code::Set(name, value) => Code::Set(name, store.add(f(*value))),
+		Code::Binary(op, lhs, rhs) => {
+			let lhs = f(*lhs);
+			let rhs = f(*rhs);
+			Code::Binary(op, store.add(lhs), store.add(rhs))
+		}
+		Code::Unary(op, arg) => Code::Unary(op, store.add(f(*arg))),
+		Code::If(cond, then, other) => {
+			let cond = f(*cond);
+			let then = f(*then);
+			let other = other.map(|x| &*store.add(f(*x)));
+			Code::If(store.add(cond), store.add(then), other)
+		}
+		Code::While(cond, body) => {
+			let cond = f(*cond);
+			let body = f(*body);
+			Code::While(store.add(cond), store.add(body))
+		}
+		Code::Return(value) => Code::Return(value.map(|x| &*store.add(f(*x)))),
+		Code::Func(def) => {
+			let body = f(*def.body);
+			Code::Func(store.add(FuncDef {
+				body: store.add(body),
+				..*def
+			}))
+		}
+		Code::At(span, code) => Code::At(span, store.add(f(*code))),
+	}
+}
+
+/// Constant value of an expression.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Const<'a> {
+	Int(i64),
+	Bool(bool),
+	Str(&'a str),
+}
+
+impl<'a> Const<'a> {
+	/// Truth value when used as a condition.
+	pub fn is_true(&self) -> Option<bool> {
+		match self {
+			Const::Int(v) => Some(*v != 0),
+			Const::Bool(v) => Some(*v),
+			Const::Str(..) => None,
+		}
+	}
+}
+
+/// Evaluate an expression made only of literals and operators.
+///
+/// Operations that could fail at runtime, like division by zero, are not
+/// evaluated.
+pub fn eval<'a>(code: &Code<'a>) -> Option<Const<'a>> {
+	let value = match code.inner() {
+		Code::Int(v) => Const::Int(*v),
+		Code::Str(v) => Const::Str(v),
+		Code::Unary(op, arg) => match (op, eval(arg)?) {
+			(UnaryOp::Neg, Const::Int(v)) => Const::Int(v.wrapping_neg()),
+			(UnaryOp::Not, Const::Bool(v)) => Const::Bool(!v),
+			(UnaryOp::Not, Const::Int(v)) => Const::Bool(v == 0),
+			(UnaryOp::Len, Const::Str(v)) => Const::Int(v.len() as i64),
+			(UnaryOp::Chars, Const::Str(v)) => Const::Int(v.chars().count() as i64),
+			_ => return None,
+		},
+		Code::Binary(op, lhs, rhs) if op.is_logic() => {
+			let Const::Bool(lhs) = eval(lhs)? else {
+				return None;
+			};
+			// short-circuit like the backends
+			match (op, lhs) {
+				(BinaryOp::And, false) => Const::Bool(false),
+				(BinaryOp::Or, true) => Const::Bool(true),
+				_ => match eval(rhs)? {
+					Const::Bool(rhs) => Const::Bool(rhs),
+					_ => return None,
+				},
+			}
+		}
+		Code::Binary(op, lhs, rhs) => match (eval(lhs)?, eval(rhs)?) {
+			(Const::Int(a), Const::Int(b)) => match op {
+				BinaryOp::Add => Const::Int(a.wrapping_add(b)),
+				BinaryOp::Sub => Const::Int(a.wrapping_sub(b)),
+				BinaryOp::Mul => Const::Int(a.wrapping_mul(b)),
+				BinaryOp::Div | BinaryOp::Mod if b == 0 || (a == i64::MIN && b == -1) => return None,
+				BinaryOp::Div => Const::Int(a / b),
+				BinaryOp::Mod => Const::Int(a % b),
+				op => Const::Bool(compare(*op, a.cmp(&b))),
+			},
+			(Const::Bool(a), Const::Bool(b)) => match op {
+				BinaryOp::Eq => Const::Bool(a == b),
+				BinaryOp::Ne => Const::Bool(a != b),
+				_ => return None,
+			},
+			(Const::Str(a), Const::Str(b)) if op.is_compare() => Const::Bool(compare(*op, a.cmp(b))),
+			_ => return None,
+		},
+		_ => return None,
+	};
+	Some(value)
+}
+
+fn compare(op: BinaryOp, ord: std::cmp::Ordering) -> bool {
+	match op {
+		BinaryOp::Eq => ord.is_eq(),
+		BinaryOp::Ne => ord.is_ne(),
+		BinaryOp::Lt => ord.is_lt(),
+		BinaryOp::Le => ord.is_le(),
+		BinaryOp::Gt => ord.is_gt(),
+		BinaryOp::Ge => ord.is_ge(),
+		_ => unreachable!(),
+	}
+}
+
+/// Return true if evaluating the expression has no side effects and
+/// cannot fail.
+pub fn is_pure(code: &Code) -> bool {
+	match code.inner() {
+		Code::Int(..) | Code::BigInt(..) | Code::Str(..) | Code::Get(..) => true,
+		Code::Unary(_, arg) => is_pure(arg),
+		Code::Binary(BinaryOp::Div | BinaryOp::Mod, ..) => false,
+		Code::Binary(_, lhs, rhs) => is_pure(lhs) && is_pure(rhs),
+		_ => false,
+	}
+}
+
+/// Return true if the variable name appears anywhere in the code.
+pub fn mentions(code: &Code, name: Sym) -> bool {
+	match code.inner() {
+		Code::Get(var) => *var == name,
+		Code::Let(var, value) | Code::Set(var, value) => *var == name || mentions(value, name),
+		_ => {
+			let mut found = false;
+			for_children(code.inner(), |x| found = found || mentions(x, name));
+			found
+		}
+	}
+}
+
+/// Return true if the variable is assigned anywhere in the code.
+pub fn assigns(code: &Code, name: Sym) -> bool {
+	match code.inner() {
+		Code::Set(var, value) => *var == name || assigns(value, name),
+		_ => {
+			let mut found = false;
+			for_children(code.inner(), |x| found = found || assigns(x, name));
+			found
+		}
+	}
+}
+
+/// Call `f` for each direct child of the code.
+pub fn for_children<'a, F: FnMut(&'a Code<'a>)>(code: &'a Code<'a>, mut f: F) {
+	match code {
+		Code::Int(..) | Code::BigInt(..) | Code::Str(..) | Code::Get(..) | Code::Break | Code::Continue => {}
+		Code::Print(list) | Code::Block(list) | Code::Call(_, list) => list.iter().for_each(f),
+		Code::Let(_, value) | Code::Set(_, value) | Code::Unary(_, value) => f(value),
+		Code::Binary(_, lhs, rhs) => {
+			f(lhs);
+			f(rhs);
+		}
+		Code::If(cond, then, other) => {
+			f(cond);
+			f(then);
+			if let Some(other) = other {
+				f(other);
+			}
+		}
+		Code::While(cond, body) => {
+			f(cond);
+			f(body);
+		}
+		Code::Return(value) => {
+			if let Some(value) = value {
+				f(value);
+			}
+		}
+		Code::Func(def) => f(def.body),
+		Code::At(_, code) => f(code),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn standard_passes() -> Result<()> {
+		let store = Store::new();
+		let int = |v| &*store.add(Code::Int(v));
+		let str = |v| &*store.add(Code::Str(store.str(v)));
+		let get = |name| &*store.add(Code::Get(store.sym(name)));
+		let op = |op, a, b| &*store.add(Code::Binary(op, a, b));
+
+		let code = Code::Block(store.add_list([
+			Code::Let(store.sym("x"), op(BinaryOp::Mul, int(6), int(7))),
+			Code::Let(store.sym("y"), get("x")),
+			Code::If(
+				op(BinaryOp::Gt, get("y"), int(40)),
+				store.add(Code::Print(store.add_list([*str("answer:"), *get("y")]))),
+				Some(store.add(Code::Print(store.add_list([*str("no")])))),
+			),
+		]));
+
+		let mut passes = PassManager::standard(&store);
+		passes.set_dump(true);
+		let code = passes.run(code);
+		assert_eq!(code.to_string(), "(block\n\t(print (str \"answer: 42\")))");
+		assert!(passes
+			.dump_output()
+			.starts_with("=== before fold (round 1) ===\n(block\n\t(let x (mul (int 6) (int 7)))\n"));
+		assert!(passes.dump_output().contains("=== after dce (unchanged) ==="));
+
+		let mut builder = vm::Builder::new(&store);
+		store.add(code).generate_vm(&mut builder)?;
+		assert_eq!(builder.build().capture()?, ("answer: 42\n".to_string(), 0));
+		Ok(())
+	}
+}
@@ -0,0 +1,148 @@
+use super::*;
+
+/// Copy propagation.
+///
+/// Uses of a variable initialized with a literal or another variable are
+/// replaced by the initial value, as long as neither variable is assigned
+/// afterwards. The variable itself is left for [`Dce`] to remove.
+pub struct CopyProp;
+
+impl Pass for CopyProp {
+	fn name(&self) -> &'static str {
+		"copy"
+	}
+
+	fn run<'a>(&mut self, store: &'a Store, code: Code<'a>) -> (Code<'a>, bool) {
+		let mut changed = false;
+		let code = copy(store, code, &mut changed);
+		(code, changed)
+	}
+}
+
+fn copy<'a>(store: &'a Store, code: Code<'a>, changed: &mut bool) -> Code<'a> {
+	match code {
+		Code::Block(list) => Code::Block(copy_list(store, list, changed)),
+		_ => map_children(store, code, |x| copy(store, x, changed)),
+	}
+}
+
+fn copy_list<'a>(store: &'a Store, list: &'a [Code<'a>], changed: &mut bool) -> &'a [Code<'a>] {
+	let mut items = list.to_vec();
+	for n in 0..items.len() {
+		items[n] = copy(store, items[n], changed);
+		let Code::Let(name, value) = items[n].inner() else {
+			continue;
+		};
+
+		let (name, value) = (*name, **value);
+		let source = match value.inner() {
+			Code::Int(..) | Code::BigInt(..) | Code::Str(..) => None,
+			Code::Get(var) if *var != name => Some(*var),
+			_ => continue,
+		};
+
+		let rest = &items[n + 1..];
+		if rest
+			.iter()
+			.any(|x| assigns(x, name) || source.is_some_and(|var| assigns(x, var)))
+		{
+			continue;
+		}
+
+		let names = [Some(name), source];
+		let rest = subst_list(store, &items[n + 1..], name, value, names, changed);
+		items.truncate(n + 1);
+		items.extend(rest);
+	}
+	store.add_list(items)
+}
+
+/// Replace uses of `name` by `value`, until one of `names` is declared
+/// again.
+fn subst_list<'a>(
+	store: &'a Store,
+	list: &[Code<'a>],
+	name: Sym<'a>,
+	value: Code<'a>,
+	names: [Option<Sym<'a>>; 2],
+	changed: &mut bool,
+) -> Vec<Code<'a>> {
+	let mut out = Vec::new();
+	let mut active = true;
+	for it in list.iter() {
+		if !active {
+			out.push(*it);
+			continue;
+		}
+		out.push(subst(store, *it, name, value, names, changed));
+		if let Code::Let(var, ..) = it.inner() {
+			// the value of the declaration still uses the outer variable
+			active = !names.contains(&Some(*var));
+		}
+	}
+	out
+}
+
+fn subst<'a>(
+	store: &'a Store,
+	code: Code<'a>,
+	name: Sym<'a>,
+	value: Code<'a>,
+	names: [Option<Sym<'a>>; 2],
+	changed: &mut bool,
+) -> Code<'a> {
+	match code {
+		Code::Get(var) if var == name => {
+			*changed = true;
+			value
+		}
+		Code::Block(list) => Code::Block(store.add_list(subst_list(store, list, name, value, names, changed))),
+		// functions cannot access outer variables
+		Code::Func(..) => code,
+		_ => map_children(store, code, |x| subst(store, x, name, value, names, changed)),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn propagate_copies() {
+		let store = Store::new();
+		let int = |v| &*store.add(Code::Int(v));
+		let get = |name| &*store.add(Code::Get(store.sym(name)));
+		let print = |name| Code::Print(store.add_list([*get(name)]));
+
+		let code = Code::Block(store.add_list([
+			Code::Let(store.sym("a"), int(1)),
+			Code::Let(store.sym("b"), get("a")),
+			Code::Let(store.sym("c"), int(2)),
+			print("b"),
+			Code::Block(store.add_list([Code::Let(store.sym("a"), int(3)), print("b")])),
+			Code::Set(store.sym("c"), int(4)),
+			print("c"),
+		]));
+
+		let (code, changed) = CopyProp.run(&store, code);
+		assert!(changed);
+		assert_eq!(
+			code.to_string(),
+			text(
+				r#"
+					(block
+						(let a (int 1))
+						(let b (int 1))
+						(let c (int 2))
+						(print (int 1))
+						(block
+							(let a (int 3))
+							(print (int 1)))
+						(set c (int 4))
+						(print (get c)))
+				"#
+			)
+			.replace("    ", "\t")
+		);
+	}
+}
@@ -0,0 +1,146 @@
+use super::*;
+
+/// Dead code elimination.
+///
+/// Removes code after statements that always leave the block, branches
+/// and loops with constant conditions, statements without effects, and
+/// unused variables with pure values. Nested blocks without declarations
+/// are merged into their parent.
+pub struct Dce;
+
+impl Pass for Dce {
+	fn name(&self) -> &'static str {
+		"dce"
+	}
+
+	fn run<'a>(&mut self, store: &'a Store, code: Code<'a>) -> (Code<'a>, bool) {
+		let mut changed = false;
+		let code = dce(store, code, &mut changed);
+		(code, changed)
+	}
+}
+
+fn dce<'a>(store: &'a Store, code: Code<'a>, changed: &mut bool) -> Code<'a> {
+	match code {
+		Code::Block(list) => Code::Block(dce_list(store, list, changed)),
+		Code::If(cond, then, other) => match eval(cond).and_then(|x| x.is_true()) {
+			Some(value) => {
+				*changed = true;
+				let body = if value { Some(then) } else { other };
+				match body {
+					Some(body) => dce(store, scoped(store, *body), changed),
+					None => Code::Block(&[]),
+				}
+			}
+			None => map_children(store, code, |x| dce(store, x, changed)),
+		},
+		Code::While(cond, ..) if eval(cond).and_then(|x| x.is_true()) == Some(false) => {
+			*changed = true;
+			Code::Block(&[])
+		}
+		// expressions are not statements, so only bodies need to be visited
+		Code::While(..) | Code::Func(..) | Code::At(..) => map_children(store, code, |x| dce(store, x, changed)),
+		code => code,
+	}
+}
+
+/// Wrap a branch body in a block, so it keeps its own scope.
+fn scoped<'a>(store: &'a Store, code: Code<'a>) -> Code<'a> {
+	match code.inner() {
+		Code::Block(..) => code,
+		_ => Code::Block(store.add_list([code])),
+	}
+}
+
+fn dce_list<'a>(store: &'a Store, list: &'a [Code<'a>], changed: &mut bool) -> &'a [Code<'a>] {
+	let mut out = Vec::new();
+	for (n, it) in list.iter().enumerate() {
+		let it = dce(store, *it, changed);
+		match it.inner() {
+			_ if is_pure(&it) => {
+				*changed = true;
+				continue;
+			}
+			Code::Block([]) => {
+				*changed = true;
+				continue;
+			}
+			Code::Block(items) if !items.iter().any(declares) => {
+				*changed = true;
+				out.extend(items.iter().copied());
+			}
+			_ => out.push(it),
+		}
+
+		if it.exit().is_some() {
+			if n + 1 < list.len() {
+				*changed = true;
+			}
+			break;
+		}
+	}
+
+	// remove unused variables, from the last so chains of unused
+	// variables go away in a single run
+	for n in (0..out.len()).rev() {
+		if let Code::Let(name, value) = out[n].inner() {
+			if is_pure(value) && !out[n + 1..].iter().any(|x| mentions(x, *name)) {
+				out.remove(n);
+				*changed = true;
+			}
+		}
+	}
+
+	store.add_list(out)
+}
+
+fn declares(code: &Code) -> bool {
+	matches!(code.inner(), Code::Let(..) | Code::Func(..))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn remove_dead_code() {
+		let store = Store::new();
+		let int = |v| &*store.add(Code::Int(v));
+		let get = |name| &*store.add(Code::Get(store.sym(name)));
+		let op = |op, a, b| &*store.add(Code::Binary(op, a, b));
+		let print = |code: &'static str| Code::Print(store.add_list([Code::Str(code)]));
+
+		let code = Code::Block(store.add_list([
+			Code::Let(store.sym("x"), int(1)),
+			Code::Let(store.sym("y"), get("x")),
+			Code::Let(store.sym("z"), op(BinaryOp::Div, int(1), int(0))),
+			*op(BinaryOp::Add, int(1), int(2)),
+			Code::If(
+				op(BinaryOp::Eq, int(1), int(2)),
+				store.add(print("a")),
+				Some(store.add(print("b"))),
+			),
+			Code::While(op(BinaryOp::Lt, int(2), int(1)), store.add(print("c"))),
+			Code::Return(None),
+			print("d"),
+		]));
+
+		let (code, changed) = Dce.run(&store, code);
+		assert!(changed);
+		assert_eq!(
+			code.to_string(),
+			text(
+				r#"
+					(block
+						(let z (div (int 1) (int 0)))
+						(print (str "b"))
+						(return))
+				"#
+			)
+			.replace("    ", "\t")
+		);
+
+		let (_, changed) = Dce.run(&store, code);
+		assert!(!changed);
+	}
+}
@@ -0,0 +1,124 @@
+use super::*;
+
+/// Constant folding.
+///
+/// Integer and string expressions on literals are replaced by their value,
+/// and consecutive literal arguments of `Print` are merged into a single
+/// string, so printing only literals becomes a single literal.
+pub struct Fold;
+
+impl Pass for Fold {
+	fn name(&self) -> &'static str {
+		"fold"
+	}
+
+	fn run<'a>(&mut self, store: &'a Store, code: Code<'a>) -> (Code<'a>, bool) {
+		let mut changed = false;
+		let code = fold(store, code, &mut changed);
+		(code, changed)
+	}
+}
+
+fn fold<'a>(store: &'a Store, code: Code<'a>, changed: &mut bool) -> Code<'a> {
+	let code = map_children(store, code, |x| fold(store, x, changed));
+	match code {
+		Code::Binary(BinaryOp::Add, lhs, rhs) => match (lhs.inner(), rhs.inner()) {
+			(Code::Str(a), Code::Str(b)) => {
+				*changed = true;
+				Code::Str(store.str(format!("{a}{b}")))
+			}
+			_ => fold_value(code, changed),
+		},
+		Code::Binary(..) | Code::Unary(..) => fold_value(code, changed),
+		Code::Print(args) => fold_print(store, args, changed),
+		code => code,
+	}
+}
+
+/// Replace integer and string results with a literal. Booleans have no
+/// literal, so they are only folded where they are used.
+fn fold_value<'a>(code: Code<'a>, changed: &mut bool) -> Code<'a> {
+	match eval(&code) {
+		Some(Const::Int(v)) => {
+			*changed = true;
+			Code::Int(v)
+		}
+		Some(Const::Str(v)) => {
+			*changed = true;
+			Code::Str(v)
+		}
+		_ => code,
+	}
+}
+
+fn fold_print<'a>(store: &'a Store, args: &'a [Code<'a>], changed: &mut bool) -> Code<'a> {
+	let mut out = Vec::new();
+	let mut run = Vec::new();
+	for it in args.iter() {
+		let text = match eval(it) {
+			Some(Const::Int(v)) => v.to_string(),
+			Some(Const::Bool(v)) => v.to_string(),
+			Some(Const::Str(v)) => v.to_string(),
+			None => {
+				merge(store, &mut out, &mut run, changed);
+				out.push(*it);
+				continue;
+			}
+		};
+		if !matches!(it.inner(), Code::Str(..)) {
+			*changed = true;
+		}
+		run.push(text);
+	}
+	merge(store, &mut out, &mut run, changed);
+	Code::Print(store.add_list(out))
+}
+
+/// Merge consecutive literal arguments, which are printed separated by
+/// a space, into a single string.
+fn merge<'a>(store: &'a Store, out: &mut Vec<Code<'a>>, run: &mut Vec<String>, changed: &mut bool) {
+	if run.is_empty() {
+		return;
+	}
+	if run.len() > 1 {
+		*changed = true;
+	}
+	out.push(Code::Str(store.str(run.join(" "))));
+	run.clear();
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn fold_print() {
+		let store = Store::new();
+		let int = |v| &*store.add(Code::Int(v));
+		let str = |v| &*store.add(Code::Str(store.str(v)));
+		let op = |op, a, b| &*store.add(Code::Binary(op, a, b));
+
+		let code = Code::Print(store.add_list([
+			*str("a"),
+			*op(BinaryOp::Add, str("b"), str("c")),
+			*op(BinaryOp::Mul, op(BinaryOp::Add, int(1), int(2)), int(3)),
+			*op(BinaryOp::Lt, int(1), int(2)),
+			Code::Unary(UnaryOp::Chars, str("日本")),
+		]));
+		let (code, changed) = Fold.run(&store, code);
+		assert!(changed);
+		assert_eq!(code, Code::Print(&[Code::Str("a bc 9 true 2")]));
+
+		let (_, changed) = Fold.run(&store, code);
+		assert!(!changed);
+
+		// only literals around other values are merged
+		let x = Code::Get(store.sym("x"));
+		let code = Code::Print(store.add_list([*str("x ="), *int(1), x, *op(BinaryOp::Div, int(1), int(0))]));
+		let (code, _) = Fold.run(&store, code);
+		let Code::Print(args) = code else { panic!() };
+		assert_eq!(args.len(), 3);
+		assert_eq!(args[0], Code::Str("x = 1"));
+		assert_eq!(args[1], x);
+	}
+}
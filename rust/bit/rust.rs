@@ -0,0 +1,685 @@
+use std::{
+	fmt::Write,
+	path::PathBuf,
+	process::{Command, ExitStatus, Output, Stdio},
+	sync::{Arc, Mutex},
+};
+
+use super::*;
+
+impl Kind {
+	/// Rust type for the kind.
+	pub fn rust_type(&self) -> Result<&'static str> {
+		let out = match self {
+			Kind::Void => "()",
+			Kind::Bool => "bool",
+			Kind::Str => "String",
+			Kind::I64 => "i64",
+			Kind::BigInt => Err("big integers are not supported by the Rust backend")?,
+		};
+		Ok(out)
+	}
+}
+
+/// Generated Rust expression.
+///
+/// Rust is expression oriented, so unlike the C backend there is no
+/// separate body of statements.
+pub struct Expr {
+	code: String,
+	kind: Kind,
+	/// The code is an item (e.g. a function) and not an expression.
+	item: bool,
+}
+
+impl Expr {
+	pub fn new(code: String, kind: Kind) -> Self {
+		Self {
+			code,
+			kind,
+			item: false,
+		}
+	}
+
+	pub fn i64(value: i64) -> Self {
+		let code = if value == i64::MIN {
+			"i64::MIN".to_string()
+		} else if value < 0 {
+			format!("({value}i64)")
+		} else {
+			format!("{value}i64")
+		};
+		Self::new(code, Kind::I64)
+	}
+
+	pub fn str(value: &str) -> Self {
+		// the debug format is a valid Rust string literal
+		Self::new(format!("String::from({value:?})"), Kind::Str)
+	}
+
+	pub fn void(code: String) -> Self {
+		Self::new(code, Kind::Void)
+	}
+
+	fn push_stmt(self, out: &mut String) {
+		if self.code.is_empty() {
+			return;
+		}
+		out.push_str(&self.code);
+		if !self.item {
+			out.push(';');
+		}
+		out.push('\n');
+	}
+}
+
+pub struct Builder<'a> {
+	names: NameSet<'a>,
+	/// Rust names of the variables and functions in scope.
+	scopes: Scopes<'a, &'a str, &'a str>,
+	checker: Checker<'a>,
+	ret: Option<Kind>,
+	loops: usize,
+	vars: u64,
+}
+
+impl<'a> Builder<'a> {
+	pub fn new(store: &'a Store) -> Self {
+		Self {
+			names: NameSet::new(store),
+			scopes: Scopes::new(),
+			checker: Checker::new(),
+			ret: None,
+			loops: 0,
+			vars: 0,
+		}
+	}
+
+	/// Return a new temporary variable name.
+	///
+	/// Unique names from the [`NameSet`] always end with `_`, so they never
+	/// conflict with temporaries.
+	pub fn var(&mut self) -> String {
+		self.vars += 1;
+		format!("_{}", self.vars)
+	}
+
+	/// Declare a new variable in the current scope and return its Rust name.
+	pub fn declare(&mut self, name: Sym<'a>, kind: Kind) -> &'a str {
+		let unique = self.unique(name);
+		self.scopes.declare(name, unique, kind);
+		unique
+	}
+
+	/// Declare a function in the current scope and return its Rust name.
+	pub fn declare_func(&mut self, def: &'a FuncDef<'a>) -> Result<&'a str> {
+		let names = &self.names;
+		self.scopes.declare_func(def, || names.resolve(names.unique(def.name)))
+	}
+
+	pub fn lookup(&self, name: Sym<'a>) -> Result<(&'a str, Kind)> {
+		self.scopes.lookup(name)
+	}
+
+	pub fn lookup_func(&self, name: Sym<'a>) -> Result<(&'a str, &'a FuncDef<'a>)> {
+		self.scopes.lookup_func(name)
+	}
+
+	fn unique(&self, name: Sym<'a>) -> &'a str {
+		let unique = self.names.unique(name);
+		self.names.resolve(unique)
+	}
+
+	pub fn build(&self, main: Expr) -> Runner {
+		let mut body = String::new();
+		main.push_stmt(&mut body);
+
+		// generated code can have unused variables and unreachable code
+		let mut code = String::new();
+		code.push_str("#![allow(warnings)]\n\nfn main() ");
+		push_block(&mut code, &body);
+		code.push('\n');
+
+		let mut program = Runner::new();
+		program.append(code);
+		program
+	}
+
+	fn generate_scope(&mut self, code: &'a [Code<'a>]) -> Result<String> {
+		self.scopes.push();
+		let result = self.generate_list(code);
+		self.scopes.pop();
+		result
+	}
+
+	fn generate_list(&mut self, code: &'a [Code<'a>]) -> Result<String> {
+		// functions are visible in the entire scope, so declare them first
+		for def in scope::functions(code) {
+			self.declare_func(def)?;
+		}
+
+		let mut body = String::new();
+		for it in code.iter() {
+			let expr = it.generate_rust(self)?;
+			expr.push_stmt(&mut body);
+		}
+		Ok(body)
+	}
+
+	fn generate_func(&mut self, def: &'a FuncDef<'a>) -> Result<String> {
+		let name = self.declare_func(def)?;
+
+		let ret = self.ret.replace(def.ret);
+		let loops = std::mem::replace(&mut self.loops, 0);
+		self.scopes.push_func();
+
+		let result = self.generate_func_code(name, def);
+
+		self.scopes.pop_func();
+		self.ret = ret;
+		self.loops = loops;
+		result
+	}
+
+	fn generate_func_code(&mut self, name: &str, def: &'a FuncDef<'a>) -> Result<String> {
+		let mut code = format!("fn {name}(");
+		for (n, it) in def.args.iter().enumerate() {
+			if it.kind == Kind::Void {
+				Err(format!("invalid void argument `{}`", it.name.as_str()))?;
+			}
+			if n > 0 {
+				code.push_str(", ");
+			}
+			let arg = self.declare(it.name, it.kind);
+			let _ = write!(code, "mut {arg}: {}", it.kind.rust_type()?);
+		}
+		code.push(')');
+		if def.ret != Kind::Void {
+			let _ = write!(code, " -> {}", def.ret.rust_type()?);
+		}
+		code.push(' ');
+
+		let mut body = self.generate_body(def.body)?;
+		if def.ret != Kind::Void {
+			// the body must diverge, but the compiler can't always tell
+			body.push_str("unreachable!(\"missing return\")\n");
+		}
+		push_block(&mut code, &body);
+		Ok(code)
+	}
+
+	/// Generate the body of a control structure. Blocks are inlined in the
+	/// structure body instead of being nested.
+	fn generate_body(&mut self, code: &'a Code<'a>) -> Result<String> {
+		match code.inner() {
+			Code::Block(list) => self.generate_scope(list),
+			_ => self.generate_scope(std::slice::from_ref(code)),
+		}
+	}
+
+	fn generate_cond(&mut self, code: &'a Code<'a>) -> Result<String> {
+		let cond = code.generate_rust(self)?;
+		match cond.kind {
+			Kind::Bool => Ok(cond.code),
+			Kind::I64 => Ok(format!("({} != 0)", cond.code)),
+			kind => Err(format!("invalid condition of type {kind:?}"))?,
+		}
+	}
+}
+
+impl<'a> Code<'a> {
+	pub fn generate_rust(&self, builder: &mut Builder<'a>) -> Result<Expr> {
+		builder.checker.enter(self)?;
+		let out = self.generate_rust_node(builder);
+		builder.checker.leave();
+		out
+	}
+
+	fn generate_rust_node(&self, builder: &mut Builder<'a>) -> Result<Expr> {
+		let out = match self {
+			Code::Int(v) => Expr::i64(*v),
+			Code::BigInt(..) => Err("big integers are not supported by the Rust backend")?,
+			Code::Str(v) => Expr::str(v),
+			Code::Print(args) => {
+				// evaluate arguments in order before printing
+				let mut body = String::new();
+				let mut fmt = String::new();
+				let mut vals = String::new();
+				for it in args.iter() {
+					let expr = it.generate_rust(builder)?;
+					if expr.kind == Kind::Void {
+						expr.push_stmt(&mut body);
+						continue;
+					}
+
+					let var = builder.var();
+					let _ = writeln!(body, "let {var} = {};", expr.code);
+					if !fmt.is_empty() {
+						fmt.push(' ');
+					}
+					fmt.push_str("{}");
+					let _ = write!(vals, ", {var}");
+				}
+				let _ = writeln!(body, "println!({fmt:?}{vals});");
+
+				let mut code = String::new();
+				push_block(&mut code, &body);
+				Expr::void(code)
+			}
+			Code::Let(name, value) => {
+				let value = value.generate_rust(builder)?;
+				if value.kind == Kind::Void {
+					Err(format!("cannot declare `{}` with a void value", name.as_str()))?;
+				}
+
+				let var = builder.declare(*name, value.kind);
+				Expr::void(format!("let mut {var} = {}", value.code))
+			}
+			Code::Get(name) => {
+				let (var, kind) = builder.lookup(*name)?;
+				let code = if kind == Kind::Str {
+					format!("{var}.clone()")
+				} else {
+					var.to_string()
+				};
+				Expr::new(code, kind)
+			}
+			Code::Set(name, value) => {
+				let (var, kind) = builder.lookup(*name)?;
+				let value = value.generate_rust(builder)?;
+				if value.kind != kind {
+					Err(format!(
+						"cannot assign {:?} to `{}` of type {kind:?}",
+						value.kind,
+						name.as_str()
+					))?;
+				}
+				Expr::void(format!("{var} = {}", value.code))
+			}
+			Code::Binary(op, lhs, rhs) => generate_binary(builder, *op, lhs, rhs)?,
+			Code::Unary(op, arg) => {
+				let arg = arg.generate_rust(builder)?;
+				let (code, kind) = match (op, arg.kind) {
+					(UnaryOp::Neg, Kind::I64) => (format!("({}).wrapping_neg()", arg.code), Kind::I64),
+					(UnaryOp::Not, Kind::Bool) => (format!("(!{})", arg.code), Kind::Bool),
+					(UnaryOp::Not, Kind::I64) => (format!("({} == 0)", arg.code), Kind::Bool),
+					(UnaryOp::Len, Kind::Str) => (format!("(({}).len() as i64)", arg.code), Kind::I64),
+					(UnaryOp::Chars, Kind::Str) => (format!("(({}).chars().count() as i64)", arg.code), Kind::I64),
+					(op, kind) => Err(format!("invalid operand for {op:?}: {kind:?}"))?,
+				};
+				Expr::new(code, kind)
+			}
+			Code::Block(list) => {
+				let mut code = String::new();
+				push_block(&mut code, &builder.generate_scope(list)?);
+				Expr::void(code)
+			}
+			Code::If(cond, then, other) => {
+				let cond = builder.generate_cond(cond)?;
+				let then = builder.generate_body(then)?;
+
+				let mut code = format!("if {cond} ");
+				push_block(&mut code, &then);
+				if let Some(other) = other {
+					let other = builder.generate_body(other)?;
+					code.push_str(" else ");
+					push_block(&mut code, &other);
+				}
+				Expr::void(code)
+			}
+			Code::While(cond, body) => {
+				let cond = builder.generate_cond(cond)?;
+
+				builder.loops += 1;
+				let body = builder.generate_body(body);
+				builder.loops -= 1;
+				let body = body?;
+
+				let mut code = format!("while {cond} ");
+				push_block(&mut code, &body);
+				Expr::void(code)
+			}
+			Code::Break | Code::Continue => {
+				if builder.loops == 0 {
+					Err(format!("{self:?} outside of a loop"))?;
+				}
+				let code = if let Code::Break = self { "break" } else { "continue" };
+				Expr::void(code.to_string())
+			}
+			Code::Return(value) => {
+				let value = match value {
+					Some(value) => Some(value.generate_rust(builder)?),
+					None => None,
+				};
+				let kind = value.as_ref().map(|x| x.kind).unwrap_or(Kind::Void);
+
+				let code = match (builder.ret, value) {
+					(None, None) | (Some(Kind::Void), None) => "return".to_string(),
+					(None, Some(value)) if kind == Kind::I64 => {
+						format!("std::process::exit({} as i32)", value.code)
+					}
+					(Some(ret), Some(value)) if ret == kind => format!("return {}", value.code),
+					(None, _) => Err(format!("cannot return {kind:?} from main"))?,
+					(Some(ret), _) => Err(format!("cannot return {kind:?} from function returning {ret:?}"))?,
+				};
+				Expr::void(code)
+			}
+			Code::Func(def) => {
+				let code = builder.generate_func(def)?;
+				Expr {
+					code,
+					kind: Kind::Void,
+					item: true,
+				}
+			}
+			Code::At(_, code) => code.generate_rust(builder)?,
+			Code::Call(name, args) => {
+				let (func, def) = builder.lookup_func(*name)?;
+				if args.len() != def.args.len() {
+					Err(format!(
+						"`{}` expects {} arguments, but got {}",
+						name.as_str(),
+						def.args.len(),
+						args.len()
+					))?;
+				}
+
+				let mut code = format!("{func}(");
+				for (n, (arg, param)) in args.iter().zip(def.args.iter()).enumerate() {
+					let arg = arg.generate_rust(builder)?;
+					if arg.kind != param.kind {
+						Err(format!(
+							"invalid argument `{}` for `{}`: expected {:?}, got {:?}",
+							param.name.as_str(),
+							name.as_str(),
+							param.kind,
+							arg.kind
+						))?;
+					}
+					if n > 0 {
+						code.push_str(", ");
+					}
+					code.push_str(&arg.code);
+				}
+				code.push(')');
+				Expr::new(code, def.ret)
+			}
+		};
+		Ok(out)
+	}
+}
+
+fn generate_binary<'a>(builder: &mut Builder<'a>, op: BinaryOp, lhs: &Code<'a>, rhs: &Code<'a>) -> Result<Expr> {
+	let lhs = lhs.generate_rust(builder)?;
+	let rhs = rhs.generate_rust(builder)?;
+	let (a, b) = (&lhs.code, &rhs.code);
+	let Some(kind) = binary_kind(op, lhs.kind, rhs.kind) else {
+		Err(format!(
+			"invalid operands for {op:?}: {:?} and {:?}",
+			lhs.kind, rhs.kind
+		))?
+	};
+
+	if op.is_logic() {
+		let op = if op == BinaryOp::And { "&&" } else { "||" };
+		return Ok(Expr::new(format!("({a} {op} {b})"), kind));
+	}
+
+	let cmp_op = match op {
+		BinaryOp::Eq => "==",
+		BinaryOp::Ne => "!=",
+		BinaryOp::Lt => "<",
+		BinaryOp::Le => "<=",
+		BinaryOp::Gt => ">",
+		BinaryOp::Ge => ">=",
+		_ => "",
+	};
+
+	// C integer arithmetic wraps in practice, so match that instead of
+	// panicking on overflow
+	let code = match (kind, op) {
+		(Kind::Bool, _) => format!("({a} {cmp_op} {b})"),
+		(Kind::Str, _) => format!("({a} + &{b})"),
+		(_, BinaryOp::Add) => format!("({a}).wrapping_add({b})"),
+		(_, BinaryOp::Sub) => format!("({a}).wrapping_sub({b})"),
+		(_, BinaryOp::Mul) => format!("({a}).wrapping_mul({b})"),
+		(_, BinaryOp::Div) => format!("({a} / {b})"),
+		_ => format!("({a} % {b})"),
+	};
+	Ok(Expr::new(code, kind))
+}
+
+/// Append a braced block with the code indented.
+fn push_block(out: &mut String, code: &str) {
+	out.push_str("{\n");
+	for line in code.lines() {
+		if !line.is_empty() {
+			out.push('\t');
+		}
+		out.push_str(line);
+		out.push('\n');
+	}
+	out.push('}');
+}
+
+/// Builds and runs generated Rust code as a cargo crate.
+#[derive(Default)]
+pub struct Runner {
+	code: String,
+}
+
+const MANIFEST: &str = r#"[package]
+name = "main"
+version = "0.1.0"
+edition = "2021"
+publish = false
+
+[workspace]
+
+[[bin]]
+name = "main"
+path = "main.rs"
+"#;
+
+impl Runner {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	pub fn append<T: AsRef<str>>(&mut self, code: T) {
+		self.code.push_str(code.as_ref())
+	}
+
+	pub fn code(&self) -> &str {
+		&self.code
+	}
+
+	pub fn run(&mut self) -> Result<ExitStatus> {
+		let (dir, path) = match self.compile() {
+			Ok(res) => res,
+			Err(err) => {
+				error(err);
+				Err("compilation failed")?
+			}
+		};
+
+		let mut cmd = cmd::new(path).cwd(dir.path());
+		cmd.lines(|out| {
+			match out {
+				cmd::Output::StdErr(err) => error(err),
+				cmd::Output::StdOut(out) => {
+					let color = term::GREEN;
+					term::Stream::Stdout.output(color, out)?;
+				}
+			}
+			Ok(())
+		})
+	}
+
+	pub fn execute(&mut self) -> Result<Output> {
+		let (dir, path) = self.compile()?;
+		let exe = Command::new(path)
+			.current_dir(dir.path())
+			.stderr(Stdio::piped())
+			.stdout(Stdio::piped())
+			.spawn()?;
+
+		let out = exe.wait_with_output()?;
+		Ok(out)
+	}
+
+	/// Build the crate with `cargo build --offline` and return the build
+	/// directory and executable path.
+	pub fn compile(&mut self) -> Result<(temp::Dir, PathBuf)> {
+		let dir = temp::dir_with("bit_rust")?;
+		dir.file("Cargo.toml")?.write(MANIFEST)?;
+		dir.file("main.rs")?.write(&self.code)?;
+
+		// use the same cargo when running from cargo (e.g. tests)
+		let cargo = std::env::var("CARGO").unwrap_or_else(|_| "cargo".into());
+		// the target dir is explicit, as `CARGO_TARGET_DIR` would move the
+		// executable or share it with concurrent builds
+		let target = dir.path().join("target");
+		let stderr = Arc::new(Mutex::new(String::new()));
+		let output = stderr.clone();
+		let status = cmd::new(&cargo)
+			.arg("build")
+			.arg("--offline")
+			.arg("--quiet")
+			.arg("--target-dir")
+			.arg(&target)
+			.cwd(dir.path())
+			.output(move |out| {
+				if let cmd::Output::StdErr(err) = out {
+					output.lock().unwrap().push_str(&err);
+				}
+				Ok(())
+			})
+			.map_err(|err| format!("cargo: could not run `{cargo}`: {err}"))?;
+
+		let result = if status.success() {
+			Ok(())
+		} else {
+			let stderr = stderr.lock().unwrap();
+			Err(format!(
+				"cargo: build exited with status {status}\n\n  | {}\n",
+				indent_with(stderr.trim(), "  | ")
+			)
+			.into())
+		};
+		dir.on_failure(result)?;
+
+		let exe = format!("main{}", std::env::consts::EXE_SUFFIX);
+		let path = target.join("debug").join(exe);
+		Ok((dir, path))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn the_answer() -> Result<()> {
+		let store = Store::new();
+		let code = Code::Print(store.add_list([
+			Code::Str(store.str("the answer to life, the universe, and everything is")),
+			Code::Int(42),
+		]));
+
+		let mut builder = Builder::new(&store);
+		let main = code.generate_rust(&mut builder)?;
+		let mut runner = builder.build(main);
+		let out = runner.execute()?;
+		assert!(out.status.success());
+		assert_eq!(
+			String::from_utf8(out.stdout)?,
+			"the answer to life, the universe, and everything is 42\n"
+		);
+		Ok(())
+	}
+
+	#[test]
+	fn same_output_as_c() -> Result<()> {
+		let store = Store::new();
+		let int = |v| &*store.add(Code::Int(v));
+		let str = |v| &*store.add(Code::Str(store.str(v)));
+		let get = |name| &*store.add(Code::Get(store.sym(name)));
+		let op = |op, a, b| &*store.add(Code::Binary(op, a, b));
+
+		let fib = FuncDef {
+			name: store.sym("fib"),
+			args: store.add_list([Param {
+				name: store.sym("n"),
+				kind: Kind::I64,
+			}]),
+			ret: Kind::I64,
+			body: store.add(Code::Block(store.add_list([
+				Code::If(
+					op(BinaryOp::Lt, get("n"), int(2)),
+					store.add(Code::Return(Some(get("n")))),
+					None,
+				),
+				Code::Return(Some(op(
+					BinaryOp::Add,
+					store.add(Code::Call(
+						store.sym("fib"),
+						store.add_list([*op(BinaryOp::Sub, get("n"), int(1))]),
+					)),
+					store.add(Code::Call(
+						store.sym("fib"),
+						store.add_list([*op(BinaryOp::Sub, get("n"), int(2))]),
+					)),
+				))),
+			]))),
+		};
+
+		let code = Code::Block(store.add_list([
+			Code::Func(store.add(fib)),
+			Code::Let(store.sym("i"), int(0)),
+			Code::Let(store.sym("s"), str("")),
+			Code::While(
+				op(BinaryOp::Lt, get("i"), int(10)),
+				store.add(Code::Block(store.add_list([
+					Code::Set(store.sym("i"), op(BinaryOp::Add, get("i"), int(1))),
+					Code::If(
+						op(BinaryOp::Eq, op(BinaryOp::Mod, get("i"), int(3)), int(0)),
+						store.add(Code::Continue),
+						None,
+					),
+					Code::Set(store.sym("s"), op(BinaryOp::Add, get("s"), str("·"))),
+				]))),
+			),
+			Code::Print(store.add_list([
+				Code::Str(store.str("fib(20) =")),
+				Code::Call(store.sym("fib"), store.add_list([Code::Int(20)])),
+			])),
+			Code::Print(store.add_list([
+				*get("s"),
+				Code::Unary(UnaryOp::Len, get("s")),
+				Code::Unary(UnaryOp::Chars, get("s")),
+				*op(BinaryOp::Lt, str("a\0"), str("a\0b")),
+				*op(
+					BinaryOp::And,
+					op(BinaryOp::Gt, get("i"), int(5)),
+					op(BinaryOp::Eq, str("x"), str("y")),
+				),
+				Code::Unary(UnaryOp::Neg, int(i64::MIN + 1)),
+			])),
+		]));
+
+		let mut builder = clang::Builder::new(&store);
+		let main = code.generate_c(&mut builder)?;
+		let c_out = builder.build(main).execute()?;
+
+		let mut builder = Builder::new(&store);
+		let main = code.generate_rust(&mut builder)?;
+		let rust_out = builder.build(main).execute()?;
+
+		let c_out = String::from_utf8(c_out.stdout)?;
+		let rust_out = String::from_utf8(rust_out.stdout)?;
+		assert_eq!(rust_out, c_out);
+		assert_eq!(c_out, "fib(20) = 6765\n······· 14 7 true false 9223372036854775807\n");
+		Ok(())
+	}
+}
@@ -0,0 +1,160 @@
+use std::collections::HashMap;
+
+use super::*;
+
+/// Variables and functions visible while generating code, with the scoping
+/// rules shared by the [`Checker`] and every backend.
+///
+/// Each user stores its own data for variables (`V`) and functions (`F`),
+/// like the name used in the generated code or a stack slot. Functions are
+/// visible in their entire scope, see [`functions`], and cannot access the
+/// variables of enclosing functions.
+pub struct Scopes<'a, V, F> {
+	scopes: Vec<HashMap<Sym<'a>, Decl<'a, V, F>>>,
+	/// First scope of the current function, with the outer frames before.
+	frames: Vec<usize>,
+}
+
+#[derive(Copy, Clone)]
+enum Decl<'a, V, F> {
+	Var(V, Kind),
+	Func(F, &'a FuncDef<'a>),
+}
+
+impl<'a, V: Copy, F: Copy> Default for Scopes<'a, V, F> {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl<'a, V: Copy, F: Copy> Scopes<'a, V, F> {
+	pub fn new() -> Self {
+		Self {
+			scopes: vec![Default::default()],
+			frames: vec![0],
+		}
+	}
+
+	pub fn push(&mut self) {
+		self.scopes.push(Default::default());
+	}
+
+	pub fn pop(&mut self) {
+		self.scopes.pop();
+	}
+
+	/// Push the scope for the arguments of a function body.
+	pub fn push_func(&mut self) {
+		self.frames.push(self.scopes.len());
+		self.push();
+	}
+
+	pub fn pop_func(&mut self) {
+		self.pop();
+		self.frames.pop();
+	}
+
+	/// Declare a variable in the current scope, shadowing any previous one.
+	pub fn declare(&mut self, name: Sym<'a>, var: V, kind: Kind) {
+		let scope = self.scopes.last_mut().unwrap();
+		scope.insert(name, Decl::Var(var, kind));
+	}
+
+	/// Declare a function in the current scope, calling `new` for its data.
+	///
+	/// Declaring the same function again returns the existing data, so the
+	/// functions of a list can be declared before generating it.
+	pub fn declare_func<T: FnOnce() -> F>(&mut self, def: &'a FuncDef<'a>, new: T) -> Result<F> {
+		let scope = self.scopes.last_mut().unwrap();
+		if let Some(Decl::Func(func, other)) = scope.get(&def.name) {
+			if std::ptr::eq(*other, def) {
+				return Ok(*func);
+			}
+			Err(format!("function `{}` is already defined", def.name.as_str()))?;
+		}
+
+		let func = new();
+		scope.insert(def.name, Decl::Func(func, def));
+		Ok(func)
+	}
+
+	pub fn lookup(&self, name: Sym<'a>) -> Result<(V, Kind)> {
+		let frame = *self.frames.last().unwrap();
+		for (n, scope) in self.scopes.iter().enumerate().rev() {
+			match scope.get(&name) {
+				Some(Decl::Var(var, kind)) => {
+					if n < frame {
+						Err(format!("cannot access `{}` from an inner function", name.as_str()))?;
+					}
+					return Ok((*var, *kind));
+				}
+				Some(Decl::Func(..)) => Err(format!("`{}` is a function", name.as_str()))?,
+				None => {}
+			}
+		}
+		Err(format!("undeclared variable `{}`", name.as_str()))?
+	}
+
+	pub fn lookup_func(&self, name: Sym<'a>) -> Result<(F, &'a FuncDef<'a>)> {
+		for scope in self.scopes.iter().rev() {
+			match scope.get(&name) {
+				Some(Decl::Func(func, def)) => return Ok((*func, def)),
+				Some(Decl::Var(..)) => Err(format!("`{}` is not a function", name.as_str()))?,
+				None => {}
+			}
+		}
+		Err(format!("undeclared function `{}`", name.as_str()))?
+	}
+}
+
+/// Functions defined directly in a list of code, which must be declared
+/// before generating the list.
+pub fn functions<'a>(code: &'a [Code<'a>]) -> impl Iterator<Item = &'a FuncDef<'a>> {
+	code.iter().filter_map(|x| match x.inner() {
+		Code::Func(def) => Some(*def),
+		_ => None,
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn scoping_rules() -> Result<()> {
+		let store = Store::new();
+		let (x, f) = (store.sym("x"), store.sym("f"));
+		let def = |name| {
+			&*store.add(FuncDef {
+				name,
+				args: store.add_list([]),
+				ret: Kind::Void,
+				body: store.add(Code::Block(store.add_list([]))),
+			})
+		};
+		let (f1, f2) = (def(f), def(f));
+
+		let mut scopes = Scopes::<u32, &str>::new();
+		scopes.declare(x, 1, Kind::I64);
+		assert_eq!(scopes.declare_func(f1, || "f_1")?, "f_1");
+		assert_eq!(scopes.declare_func(f1, || "f_2")?, "f_1");
+		let err = scopes.declare_func(f2, || "f_3").unwrap_err();
+		assert_eq!(err.to_string(), "function `f` is already defined");
+
+		scopes.push();
+		scopes.declare(x, 2, Kind::Bool);
+		assert_eq!(scopes.lookup(x)?, (2, Kind::Bool));
+		scopes.pop();
+		assert_eq!(scopes.lookup(x)?, (1, Kind::I64));
+
+		scopes.push_func();
+		let err = scopes.lookup(x).unwrap_err();
+		assert_eq!(err.to_string(), "cannot access `x` from an inner function");
+		assert_eq!(scopes.lookup_func(f)?.0, "f_1");
+		assert_eq!(scopes.lookup(f).unwrap_err().to_string(), "`f` is a function");
+		scopes.pop_func();
+
+		assert_eq!(scopes.lookup_func(x).unwrap_err().to_string(), "`x` is not a function");
+		Ok(())
+	}
+}
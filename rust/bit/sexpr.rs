@@ -0,0 +1,634 @@
+//! Textual S-expression format for [`Code`].
+//!
+//! Every node is a list starting with the node name, for example
+//! `(print (str "x") (int 42))`. Statements inside blocks, branches, loops
+//! and functions are printed one per line, so the output is stable and
+//! diffs well in golden files. Comments start with `;` and go until the
+//! end of the line.
+//!
+//! ```text
+//! (block
+//!     (func double ((n i64)) i64
+//!         (return (mul (get n) (int 2))))
+//!     (print (str "answer") (call double (int 21))))
+//! ```
+
+use std::fmt::{Display, Formatter};
+
+use super::*;
+
+impl<'a> Display for Code<'a> {
+	fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+		let mut out = String::new();
+		write_code(&mut out, self, 0);
+		write!(f, "{out}")
+	}
+}
+
+fn write_code(out: &mut String, code: &Code, indent: usize) {
+	let line = |out: &mut String, indent: usize| {
+		out.push('\n');
+		for _ in 0..indent {
+			out.push('\t');
+		}
+	};
+
+	match code {
+		Code::Int(v) => out.push_str(&format!("(int {v})")),
+		Code::BigInt(v) => out.push_str(&format!("(bigint {})", int::int_to_dec(v))),
+		Code::Str(v) => out.push_str(&format!("(str {v:?})")),
+		Code::Print(args) => write_list(out, "print", None, args, indent),
+		Code::Let(name, value) => write_list(out, "let", Some(name.as_str()), std::slice::from_ref(*value), indent),
+		Code::Get(name) => {
+			out.push_str("(get ");
+			write_name(out, name.as_str());
+			out.push(')');
+		}
+		Code::Set(name, value) => write_list(out, "set", Some(name.as_str()), std::slice::from_ref(*value), indent),
+		Code::Binary(op, lhs, rhs) => {
+			out.push('(');
+			out.push_str(binary_name(*op));
+			out.push(' ');
+			write_code(out, lhs, indent);
+			out.push(' ');
+			write_code(out, rhs, indent);
+			out.push(')');
+		}
+		Code::Unary(op, arg) => write_list(out, unary_name(*op), None, std::slice::from_ref(*arg), indent),
+		Code::Block(list) => {
+			out.push_str("(block");
+			for it in list.iter() {
+				line(out, indent + 1);
+				write_code(out, it, indent + 1);
+			}
+			out.push(')');
+		}
+		Code::If(cond, then, other) => {
+			out.push_str("(if ");
+			write_code(out, cond, indent);
+			line(out, indent + 1);
+			write_code(out, then, indent + 1);
+			if let Some(other) = other {
+				line(out, indent + 1);
+				write_code(out, other, indent + 1);
+			}
+			out.push(')');
+		}
+		Code::While(cond, body) => {
+			out.push_str("(while ");
+			write_code(out, cond, indent);
+			line(out, indent + 1);
+			write_code(out, body, indent + 1);
+			out.push(')');
+		}
+		Code::Break => out.push_str("(break)"),
+		Code::Continue => out.push_str("(continue)"),
+		Code::Return(value) => match value {
+			Some(value) => write_list(out, "return", None, std::slice::from_ref(*value), indent),
+			None => out.push_str("(return)"),
+		},
+		Code::Func(def) => {
+			out.push_str("(func ");
+			write_name(out, def.name.as_str());
+			out.push_str(" (");
+			for (n, it) in def.args.iter().enumerate() {
+				if n > 0 {
+					out.push(' ');
+				}
+				out.push('(');
+				write_name(out, it.name.as_str());
+				out.push(' ');
+				out.push_str(kind_name(it.kind));
+				out.push(')');
+			}
+			out.push_str(") ");
+			out.push_str(kind_name(def.ret));
+			line(out, indent + 1);
+			write_code(out, def.body, indent + 1);
+			out.push(')');
+		}
+		Code::Call(name, args) => write_list(out, "call", Some(name.as_str()), args, indent),
+		Code::At(span, code) => {
+			out.push_str(&format!("(at {:?} {} {} ", span.src.name(), span.sta, span.end));
+			write_code(out, code, indent);
+			out.push(')');
+		}
+	}
+}
+
+fn write_list(out: &mut String, head: &str, name: Option<&str>, args: &[Code], indent: usize) {
+	out.push('(');
+	out.push_str(head);
+	if let Some(name) = name {
+		out.push(' ');
+		write_name(out, name);
+	}
+	for it in args.iter() {
+		out.push(' ');
+		write_code(out, it, indent);
+	}
+	out.push(')');
+}
+
+/// Names are written as bare atoms when possible, and quoted otherwise.
+fn write_name(out: &mut String, name: &str) {
+	if !name.is_empty() && name.chars().all(is_atom_char) {
+		out.push_str(name);
+	} else {
+		out.push_str(&format!("{name:?}"));
+	}
+}
+
+fn is_atom_char(chr: char) -> bool {
+	!(chr.is_whitespace() || chr.is_control() || matches!(chr, '(' | ')' | '"' | ';'))
+}
+
+fn binary_name(op: BinaryOp) -> &'static str {
+	match op {
+		BinaryOp::Add => "add",
+		BinaryOp::Sub => "sub",
+		BinaryOp::Mul => "mul",
+		BinaryOp::Div => "div",
+		BinaryOp::Mod => "mod",
+		BinaryOp::Eq => "eq",
+		BinaryOp::Ne => "ne",
+		BinaryOp::Lt => "lt",
+		BinaryOp::Le => "le",
+		BinaryOp::Gt => "gt",
+		BinaryOp::Ge => "ge",
+		BinaryOp::And => "and",
+		BinaryOp::Or => "or",
+	}
+}
+
+fn unary_name(op: UnaryOp) -> &'static str {
+	match op {
+		UnaryOp::Neg => "neg",
+		UnaryOp::Not => "not",
+		UnaryOp::Len => "len",
+		UnaryOp::Chars => "chars",
+	}
+}
+
+fn kind_name(kind: Kind) -> &'static str {
+	match kind {
+		Kind::Void => "void",
+		Kind::Bool => "bool",
+		Kind::Str => "str",
+		Kind::I64 => "i64",
+		Kind::BigInt => "bigint",
+	}
+}
+
+/// Parse code in the textual format, allocating it in the store.
+pub fn parse<'a>(store: &'a Store, text: &str) -> Result<Code<'a>> {
+	Parser::new(store).parse(text)
+}
+
+/// Parser for the textual format.
+///
+/// Locations in `at` nodes refer to sources by name. Sources added with
+/// [`Parser::source`] are used first, then sources loaded by the store.
+pub struct Parser<'a> {
+	store: &'a Store,
+	sources: Vec<Source<'a>>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Token {
+	Open,
+	Close,
+	Atom(String),
+	Str(String),
+	End,
+}
+
+struct Input<'s> {
+	text: &'s str,
+	pos: usize,
+	line: usize,
+	column: usize,
+}
+
+impl<'a> Parser<'a> {
+	pub fn new(store: &'a Store) -> Self {
+		Self {
+			store,
+			sources: Vec::new(),
+		}
+	}
+
+	pub fn source(mut self, source: Source<'a>) -> Self {
+		self.sources.push(source);
+		self
+	}
+
+	pub fn parse(&self, text: &str) -> Result<Code<'a>> {
+		let mut input = Input {
+			text,
+			pos: 0,
+			line: 1,
+			column: 1,
+		};
+		let code = self.parse_code(&mut input)?;
+		match input.next()? {
+			Token::End => Ok(code),
+			token => input.error(format!("expected end of input, got {token:?}")),
+		}
+	}
+
+	fn parse_code(&self, input: &mut Input) -> Result<Code<'a>> {
+		let store = self.store;
+		input.expect(Token::Open)?;
+		let head = input.atom()?;
+		let code = match head.as_str() {
+			"int" => {
+				let atom = input.atom()?;
+				match atom.parse::<i64>() {
+					Ok(v) => Code::Int(v),
+					Err(_) => input.error(format!("invalid integer `{atom}`"))?,
+				}
+			}
+			"bigint" => {
+				let atom = input.atom()?;
+				match int::parse_int(&atom, 10) {
+					Ok(v) => Code::BigInt(store.add_slice(&v)),
+					Err(_) => input.error(format!("invalid integer `{atom}`"))?,
+				}
+			}
+			"str" => Code::Str(store.str(input.string()?)),
+			"print" => Code::Print(self.parse_list(input)?),
+			"let" => {
+				let name = store.sym(input.name()?);
+				Code::Let(name, self.parse_ref(input)?)
+			}
+			"get" => Code::Get(store.sym(input.name()?)),
+			"set" => {
+				let name = store.sym(input.name()?);
+				Code::Set(name, self.parse_ref(input)?)
+			}
+			"block" => Code::Block(self.parse_list(input)?),
+			"if" => {
+				let cond = self.parse_ref(input)?;
+				let then = self.parse_ref(input)?;
+				let other = if input.peek()? == Token::Open {
+					Some(self.parse_ref(input)?)
+				} else {
+					None
+				};
+				Code::If(cond, then, other)
+			}
+			"while" => {
+				let cond = self.parse_ref(input)?;
+				Code::While(cond, self.parse_ref(input)?)
+			}
+			"break" => Code::Break,
+			"continue" => Code::Continue,
+			"return" => {
+				if input.peek()? == Token::Open {
+					Code::Return(Some(self.parse_ref(input)?))
+				} else {
+					Code::Return(None)
+				}
+			}
+			"func" => {
+				let name = store.sym(input.name()?);
+				input.expect(Token::Open)?;
+				let mut args = Vec::new();
+				while input.peek()? == Token::Open {
+					input.expect(Token::Open)?;
+					let name = store.sym(input.name()?);
+					let kind = input.kind()?;
+					input.expect(Token::Close)?;
+					args.push(Param { name, kind });
+				}
+				input.expect(Token::Close)?;
+				let ret = input.kind()?;
+				let body = self.parse_ref(input)?;
+				Code::Func(store.add(FuncDef {
+					name,
+					args: store.add_list(args),
+					ret,
+					body,
+				}))
+			}
+			"call" => {
+				let name = store.sym(input.name()?);
+				Code::Call(name, self.parse_list(input)?)
+			}
+			"at" => {
+				let name = input.string()?;
+				let sta = input.number()?;
+				let end = input.number()?;
+				let src = self
+					.find_source(&name)
+					.or_else(|_| input.error(format!("unknown source `{name}`")))?;
+				if sta > end || end > src.len() {
+					input.error(format!("invalid location {sta}..{end} for `{name}`"))?;
+				}
+				let span = src.span().slice(sta..end);
+				Code::At(span, self.parse_ref(input)?)
+			}
+			head => {
+				if let Some(op) = parse_binary(head) {
+					let lhs = self.parse_ref(input)?;
+					Code::Binary(op, lhs, self.parse_ref(input)?)
+				} else if let Some(op) = parse_unary(head) {
+					Code::Unary(op, self.parse_ref(input)?)
+				} else {
+					input.error(format!("unknown node `{head}`"))?
+				}
+			}
+		};
+		input.expect(Token::Close)?;
+		Ok(code)
+	}
+
+	fn parse_ref(&self, input: &mut Input) -> Result<&'a Code<'a>> {
+		let code = self.parse_code(input)?;
+		Ok(self.store.add(code))
+	}
+
+	fn parse_list(&self, input: &mut Input) -> Result<&'a [Code<'a>]> {
+		let mut list = Vec::new();
+		while input.peek()? == Token::Open {
+			list.push(self.parse_code(input)?);
+		}
+		Ok(self.store.add_list(list))
+	}
+
+	fn find_source(&self, name: &str) -> Result<Source<'a>> {
+		if let Some(src) = self.sources.iter().find(|x| x.name() == name) {
+			return Ok(*src);
+		}
+		self.store.load_source(name)
+	}
+}
+
+fn parse_binary(name: &str) -> Option<BinaryOp> {
+	let op = match name {
+		"add" => BinaryOp::Add,
+		"sub" => BinaryOp::Sub,
+		"mul" => BinaryOp::Mul,
+		"div" => BinaryOp::Div,
+		"mod" => BinaryOp::Mod,
+		"eq" => BinaryOp::Eq,
+		"ne" => BinaryOp::Ne,
+		"lt" => BinaryOp::Lt,
+		"le" => BinaryOp::Le,
+		"gt" => BinaryOp::Gt,
+		"ge" => BinaryOp::Ge,
+		"and" => BinaryOp::And,
+		"or" => BinaryOp::Or,
+		_ => return None,
+	};
+	Some(op)
+}
+
+fn parse_unary(name: &str) -> Option<UnaryOp> {
+	let op = match name {
+		"neg" => UnaryOp::Neg,
+		"not" => UnaryOp::Not,
+		"len" => UnaryOp::Len,
+		"chars" => UnaryOp::Chars,
+		_ => return None,
+	};
+	Some(op)
+}
+
+impl<'s> Input<'s> {
+	fn error<T, U: AsRef<str>>(&self, msg: U) -> Result<T> {
+		Err(format!("{} (at {}:{})", msg.as_ref(), self.line, self.column))?
+	}
+
+	fn peek_char(&self) -> Option<char> {
+		self.text[self.pos..].chars().next()
+	}
+
+	fn read_char(&mut self) -> Option<char> {
+		let chr = self.peek_char()?;
+		self.pos += chr.len_utf8();
+		if chr == '\n' {
+			self.line += 1;
+			self.column = 1;
+		} else {
+			self.column += 1;
+		}
+		Some(chr)
+	}
+
+	fn skip_space(&mut self) {
+		while let Some(chr) = self.peek_char() {
+			if chr == ';' {
+				while self.peek_char().is_some_and(|x| x != '\n') {
+					self.read_char();
+				}
+			} else if chr.is_whitespace() {
+				self.read_char();
+			} else {
+				break;
+			}
+		}
+	}
+
+	fn peek(&mut self) -> Result<Token> {
+		let (pos, line, column) = (self.pos, self.line, self.column);
+		let token = self.next();
+		(self.pos, self.line, self.column) = (pos, line, column);
+		token
+	}
+
+	fn next(&mut self) -> Result<Token> {
+		self.skip_space();
+		let Some(chr) = self.peek_char() else {
+			return Ok(Token::End);
+		};
+		let token = match chr {
+			'(' => {
+				self.read_char();
+				Token::Open
+			}
+			')' => {
+				self.read_char();
+				Token::Close
+			}
+			'"' => Token::Str(self.read_string()?),
+			_ => {
+				let sta = self.pos;
+				while self.peek_char().is_some_and(is_atom_char) {
+					self.read_char();
+				}
+				Token::Atom(self.text[sta..self.pos].to_string())
+			}
+		};
+		Ok(token)
+	}
+
+	fn read_string(&mut self) -> Result<String> {
+		self.read_char();
+		let mut out = String::new();
+		loop {
+			let Some(chr) = self.read_char() else {
+				return self.error("unterminated string");
+			};
+			match chr {
+				'"' => break,
+				'\\' => {
+					let chr = match self.read_char() {
+						Some('n') => '\n',
+						Some('t') => '\t',
+						Some('r') => '\r',
+						Some('0') => '\0',
+						Some(chr @ ('\\' | '"' | '\'')) => chr,
+						Some('u') => self.read_unicode()?,
+						_ => return self.error("invalid escape sequence"),
+					};
+					out.push(chr);
+				}
+				chr => out.push(chr),
+			}
+		}
+		Ok(out)
+	}
+
+	/// Read a `\u{XXXX}` escape after the `u`.
+	fn read_unicode(&mut self) -> Result<char> {
+		if self.read_char() != Some('{') {
+			return self.error("expected `{` in unicode escape");
+		}
+		let mut code = String::new();
+		loop {
+			match self.read_char() {
+				Some('}') => break,
+				Some(chr) if chr.is_ascii_hexdigit() => code.push(chr),
+				_ => return self.error("invalid unicode escape"),
+			}
+		}
+		let code = u32::from_str_radix(&code, 16).ok().and_then(char::from_u32);
+		match code {
+			Some(chr) => Ok(chr),
+			None => self.error("invalid unicode escape"),
+		}
+	}
+
+	fn expect(&mut self, expected: Token) -> Result<()> {
+		let token = self.next()?;
+		if token != expected {
+			let expected = match expected {
+				Token::Open => "`(`",
+				Token::Close => "`)`",
+				_ => "token",
+			};
+			return self.error(format!("expected {expected}, got {token:?}"));
+		}
+		Ok(())
+	}
+
+	fn atom(&mut self) -> Result<String> {
+		match self.next()? {
+			Token::Atom(atom) => Ok(atom),
+			token => self.error(format!("expected atom, got {token:?}")),
+		}
+	}
+
+	fn string(&mut self) -> Result<String> {
+		match self.next()? {
+			Token::Str(str) => Ok(str),
+			token => self.error(format!("expected string, got {token:?}")),
+		}
+	}
+
+	/// Names can be bare atoms or quoted strings.
+	fn name(&mut self) -> Result<String> {
+		match self.next()? {
+			Token::Atom(name) | Token::Str(name) => Ok(name),
+			token => self.error(format!("expected name, got {token:?}")),
+		}
+	}
+
+	fn number(&mut self) -> Result<usize> {
+		let atom = self.atom()?;
+		match atom.parse() {
+			Ok(v) => Ok(v),
+			Err(_) => self.error(format!("invalid number `{atom}`")),
+		}
+	}
+
+	fn kind(&mut self) -> Result<Kind> {
+		let kind = match self.atom()?.as_str() {
+			"void" => Kind::Void,
+			"bool" => Kind::Bool,
+			"str" => Kind::Str,
+			"i64" => Kind::I64,
+			"bigint" => Kind::BigInt,
+			kind => return self.error(format!("unknown kind `{kind}`")),
+		};
+		Ok(kind)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn round_trip() -> Result<()> {
+		let store = Store::new();
+		let src = store.load_string("test.bit", "print 'x'\n");
+		let text = text(
+			r#"
+				; comments are ignored
+				(block
+					(func "my func" ((n i64) (s str)) void
+						(block
+							(while (gt (get n) (int 0))
+								(set n (sub (get n) (int 1))))
+							(if (eq (len (get s)) (int 0))
+								(return)
+								(print (get s) (neg (int -5)) (bigint 123456789012345678901234567890)))))
+					(call "my func" (int 3) (str "tab\t\"quoted\" \u{1b}"))
+					(at "test.bit" 0 9 (print (str "x"))))
+			"#,
+		);
+
+		let code = Parser::new(&store).source(src).parse(&text)?;
+		let Code::Block(list) = code else { panic!() };
+		assert_eq!(list[2].span().map(|x| x.text()), Some("print 'x'"));
+		let Code::Call(_, args) = list[1] else { panic!() };
+		assert_eq!(args[1], Code::Str("tab\t\"quoted\" \x1b"));
+
+		// the printer output is the same as the input without comments
+		let expected = text.replace("; comments are ignored\n", "").replace("    ", "\t");
+		assert_eq!(code.to_string(), expected);
+		let again = Parser::new(&store).source(src).parse(&code.to_string())?;
+		assert_eq!(again, code);
+		Ok(())
+	}
+
+	#[test]
+	fn parse_errors() {
+		let store = Store::new();
+		let error = |text| parse(&store, text).unwrap_err().to_string();
+		assert_eq!(error("(print (int 1)"), "expected `)`, got End (at 1:15)");
+		assert_eq!(error("(foo)"), "unknown node `foo` (at 1:5)");
+		assert_eq!(error("(int x)"), "invalid integer `x` (at 1:7)");
+		assert_eq!(error("(func f ((n u8)) void (block))"), "unknown kind `u8` (at 1:15)");
+		assert_eq!(error("(str \"a)"), "unterminated string (at 1:9)");
+		assert_eq!(error("(at \"none\" 0 1 (break))"), "unknown source `none` (at 1:15)");
+		assert_eq!(error("(break) (break)"), "expected end of input, got Open (at 1:10)");
+	}
+
+	#[test]
+	fn run_parsed_code() -> Result<()> {
+		let store = Store::new();
+		let code = parse(
+			&store,
+			r#"(block (let x (int 41)) (print (str "answer:") (add (get x) (int 1))))"#,
+		)?;
+		let mut builder = clang::Builder::new(&store);
+		let main = code.generate_c(&mut builder)?;
+		let out = builder.build(main).execute()?;
+		assert_eq!(String::from_utf8(out.stdout)?, "answer: 42\n");
+		Ok(())
+	}
+}
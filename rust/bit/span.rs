@@ -30,6 +30,19 @@ impl<'a> Span<'a> {
 		&text[self.sta..self.end]
 	}
 
+	/// Line number for the start of the span, starting at 1.
+	pub fn line(&self) -> usize {
+		let text = &self.src.text()[..self.sta];
+		text.bytes().filter(|&x| x == b'\n').count() + 1
+	}
+
+	/// Column for the start of the span in characters, starting at 1.
+	pub fn column(&self) -> usize {
+		let text = &self.src.text()[..self.sta];
+		let line = text.rfind('\n').map(|x| &text[x + 1..]).unwrap_or(text);
+		line.chars().count() + 1
+	}
+
 	pub fn text_at<T: RangeBounds<usize>>(&self, range: T) -> &'a str {
 		self.slice(range).text()
 	}
@@ -0,0 +1,24 @@
+//! Operating system calls not covered by the standard library.
+
+#[cfg(unix)]
+pub const SIGKILL: i32 = 9;
+
+#[cfg(unix)]
+pub const ESRCH: i32 = 3;
+
+/// Send `sig` to the process `pid`, or to the process group `-pid`.
+///
+/// Signal `0` only checks that the process exists.
+#[cfg(unix)]
+pub fn kill(pid: i32, sig: i32) -> std::io::Result<()> {
+	extern "C" {
+		fn kill(pid: i32, sig: i32) -> i32;
+	}
+
+	// SAFETY: `kill` has no memory safety requirements
+	if unsafe { kill(pid, sig) } == 0 {
+		Ok(())
+	} else {
+		Err(std::io::Error::last_os_error())
+	}
+}
@@ -1,21 +1,50 @@
 use std::{
+	cell::Cell,
 	ffi::OsStr,
 	fmt::{Display, Formatter},
 	fs::OpenOptions,
 	io::{ErrorKind, Write},
 	path::{Path, PathBuf},
+	sync::atomic::{AtomicBool, Ordering},
 };
 
 use super::*;
 
+pub mod lock;
+
+pub use lock::*;
+
+/// Setting this environment variable to anything other than `0` keeps
+/// temporary directories of failed runs, see [`Dir::on_failure`].
+pub const KEEP_TEMP_VAR: &str = "BIT_KEEP_TEMP";
+
+static KEEP_ON_FAILURE: AtomicBool = AtomicBool::new(false);
+
+/// Keep temporary directories of failed runs, as with [`KEEP_TEMP_VAR`].
+pub fn set_keep_on_failure(keep: bool) {
+	KEEP_ON_FAILURE.store(keep, Ordering::SeqCst);
+}
+
+pub fn keep_on_failure() -> bool {
+	KEEP_ON_FAILURE.load(Ordering::SeqCst) || std::env::var_os(KEEP_TEMP_VAR).is_some_and(|x| !x.is_empty() && x != "0")
+}
+
 pub fn dir() -> Result<Dir> {
+	dir_with("bit")
+}
+
+/// Create a temporary directory with a name starting with `prefix`.
+pub fn dir_with<T: AsRef<str>>(prefix: T) -> Result<Dir> {
 	let temp = std::env::temp_dir().canonicalize()?;
 	for _ in 0..100 {
 		let uniq = rand::random::<u32>();
-		let name = format!("bit_{uniq}.tmp");
+		let name = format!("{}_{uniq}.tmp", prefix.as_ref());
 		let path = temp.join(name);
 		match std::fs::create_dir(&path) {
-			Ok(_) => return Ok(Dir { path }),
+			Ok(_) => {
+				let keep = Cell::new(false);
+				return Ok(Dir { path, keep });
+			}
 			Err(err) => {
 				if err.kind() != ErrorKind::AlreadyExists {
 					Err(err)?
@@ -26,8 +55,58 @@ pub fn dir() -> Result<Dir> {
 	Err("could not generate a unique directory")?
 }
 
+/// Replace the contents of `path` without readers ever seeing a partial
+/// file.
+///
+/// The contents are written to a temporary file in the same directory,
+/// flushed to the disk, then renamed over `path`.
+pub fn atomic_write<T: AsRef<Path>, U: AsRef<[u8]>>(path: T, contents: U) -> Result<()> {
+	let path = path.as_ref();
+	let name = path
+		.file_name()
+		.ok_or_else(|| format!("invalid file path: {}", path.display()))?;
+	let parent = match path.parent() {
+		Some(parent) if parent != Path::new("") => parent,
+		_ => Path::new("."),
+	};
+
+	let mut file = None;
+	for _ in 0..100 {
+		let uniq = rand::random::<u32>();
+		let temp = parent.join(format!(".{}_{uniq}.tmp", name.to_string_lossy()));
+		match OpenOptions::new().write(true).create_new(true).open(&temp) {
+			Ok(new) => {
+				file = Some(File { file: new, path: temp });
+				break;
+			}
+			Err(err) => {
+				if err.kind() != ErrorKind::AlreadyExists {
+					Err(err)?
+				}
+			}
+		}
+	}
+	let Some(mut file) = file else {
+		Err("could not generate a unique file name")?
+	};
+
+	let result = file.write(contents).and_then(|_| file.sync());
+	let result = result.and_then(|_| Ok(std::fs::rename(file.path(), path)?));
+	if result.is_err() {
+		let _ = file.delete();
+		return result;
+	}
+
+	// make the rename itself durable, which is not supported everywhere
+	if let Ok(dir) = std::fs::File::open(parent) {
+		let _ = dir.sync_all();
+	}
+	Ok(())
+}
+
 pub struct Dir {
 	path: PathBuf,
+	keep: Cell<bool>,
 }
 
 impl Dir {
@@ -35,6 +114,34 @@ impl Dir {
 		&self.path
 	}
 
+	/// Do not delete the directory when dropped, and print its path so it
+	/// can be inspected.
+	pub fn keep(&self) -> &Path {
+		if !self.keep.replace(true) {
+			eprintln!("keeping temp dir: {}", self.path.to_string_lossy());
+		}
+		&self.path
+	}
+
+	/// Keep the directory if `result` is an error and [`keep_on_failure`]
+	/// is set.
+	pub fn on_failure<T>(&self, result: Result<T>) -> Result<T> {
+		self.keep_if_failed(result.is_err());
+		result
+	}
+
+	/// Keep the directory if `failed` and [`keep_on_failure`] is set, for
+	/// failures which are not errors like a non-zero exit status.
+	pub fn keep_if_failed(&self, failed: bool) {
+		self.keep_failed(failed, keep_on_failure());
+	}
+
+	fn keep_failed(&self, failed: bool, keep_on_failure: bool) {
+		if failed && keep_on_failure {
+			self.keep();
+		}
+	}
+
 	pub fn file<T: AsRef<Path>>(&self, name: T) -> Result<File> {
 		let name = name.as_ref();
 		assert!(!name.is_absolute());
@@ -47,8 +154,11 @@ impl Dir {
 				std::path::Component::RootDir => unreachable!(),
 				std::path::Component::CurDir => continue,
 				std::path::Component::ParentDir => {
-					assert!(levels > 0);
+					if levels == 0 {
+						Err(format!("file is outside the temp dir: {}", name.display()))?;
+					}
 					path.pop();
+					levels -= 1;
 				}
 				std::path::Component::Normal(name) => {
 					path.push(name);
@@ -57,10 +167,13 @@ impl Dir {
 			}
 		}
 
-		let file = OpenOptions::new().write(true).create_new(true).open(&path)?;
-		let path = path.canonicalize()?;
-		assert!(path.starts_with(&self.path));
-		Ok(File { file, path })
+		if levels == 0 || !path.starts_with(&self.path) {
+			Err(format!("file is outside the temp dir: {}", name.display()))?;
+		}
+		if let Some(parent) = path.parent() {
+			std::fs::create_dir_all(parent)?;
+		}
+		File::create_new(path)
 	}
 }
 
@@ -72,6 +185,9 @@ impl Display for Dir {
 
 impl Drop for Dir {
 	fn drop(&mut self) {
+		if self.keep.get() {
+			return;
+		}
 		if let Err(err) = std::fs::remove_dir_all(&self.path) {
 			eprintln!("could not delete temp dir: {err} -- {:?}", self.path);
 		}
@@ -84,6 +200,14 @@ pub struct File {
 }
 
 impl File {
+	/// Create a new file, failing if it already exists.
+	pub fn create_new<T: AsRef<Path>>(path: T) -> Result<File> {
+		let path = path.as_ref();
+		let file = OpenOptions::new().write(true).create_new(true).open(path)?;
+		let path = path.canonicalize()?;
+		Ok(File { file, path })
+	}
+
 	pub fn path(&self) -> &Path {
 		&self.path
 	}
@@ -98,6 +222,12 @@ impl File {
 		Ok(())
 	}
 
+	/// Flush the file contents to the disk.
+	pub fn sync(&self) -> Result<()> {
+		self.file.sync_all()?;
+		Ok(())
+	}
+
 	pub fn delete(mut self) -> Result<()> {
 		let path = std::mem::take(&mut self.path);
 		drop(self);
@@ -180,4 +310,56 @@ mod tests {
 
 		Ok(())
 	}
+
+	#[test]
+	fn keep_dir() -> Result<()> {
+		let dir = dir_with("bit_keep")?;
+		let name = dir.path().file_name().unwrap().to_string_lossy().to_string();
+		assert!(name.starts_with("bit_keep_") && name.ends_with(".tmp"));
+
+		// parent directories are created for nested files
+		let file = dir.file("a/b/c.txt")?;
+		assert!(file.path().starts_with(dir.path().join("a/b")));
+
+		// paths cannot leave the directory
+		let err = dir.file("a/../../escape.txt").err().unwrap();
+		assert_eq!(err.to_string(), "file is outside the temp dir: a/../../escape.txt");
+		assert!(dir.file("a/..").is_err());
+		assert!(!dir.path().parent().unwrap().join("escape.txt").exists());
+		let file = dir.file("a/b/../d.txt")?;
+		assert_eq!(file.path(), dir.path().join("a/d.txt"));
+
+		// success or disabled keeping does not keep the directory
+		let path = dir.path().to_owned();
+		dir.keep_failed(false, true);
+		dir.keep_failed(true, false);
+		drop(dir);
+		assert!(!path.exists());
+
+		let dir = dir_with("bit_keep")?;
+		let kept = dir.path().to_owned();
+		dir.keep_failed(true, true);
+		drop(dir);
+		assert!(kept.is_dir());
+		std::fs::remove_dir_all(&kept)?;
+		Ok(())
+	}
+
+	#[test]
+	fn atomic_write_file() -> Result<()> {
+		let dir = dir()?;
+		let path = dir.path().join("out.txt");
+		atomic_write(&path, "first")?;
+		atomic_write(&path, "second")?;
+		assert_eq!(std::fs::read_to_string(&path)?, "second");
+
+		// no temporary files are left behind
+		let names = std::fs::read_dir(dir.path())?
+			.map(|x| x.map(|x| x.file_name()))
+			.collect::<std::io::Result<Vec<_>>>()?;
+		assert_eq!(names, ["out.txt"]);
+
+		assert!(atomic_write(dir.path().join("missing/out.txt"), "x").is_err());
+		Ok(())
+	}
 }
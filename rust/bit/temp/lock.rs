@@ -0,0 +1,203 @@
+use std::{
+	thread,
+	time::{Duration, Instant},
+};
+
+use super::*;
+
+/// Exclusive lock shared between processes, held while the lock file exists.
+///
+/// The lock file contains the PID of its owner. A lock is stale, and taken
+/// over, when its owner is no longer running. When the owner cannot be
+/// checked, a lock is stale once it is older than [`LockFile::MAX_AGE`].
+/// The lock is released when dropped.
+///
+/// Taking over a stale lock is not atomic. When one process takes the lock
+/// right after the stale lock is removed, another process removing the
+/// same stale lock may rename the new lock away. The new lock is then put
+/// back, unless a third process took the free lock in the meantime, in
+/// which case two processes both hold the lock. This needs three processes
+/// racing for a stale lock within a few file operations.
+pub struct LockFile {
+	file: Option<File>,
+}
+
+impl LockFile {
+	pub const MAX_AGE: Duration = Duration::from_secs(60 * 60);
+
+	/// Take the lock if it is free, or return `None` if it is held.
+	pub fn try_acquire<T: AsRef<Path>>(path: T) -> Result<Option<LockFile>> {
+		let path = path.as_ref();
+
+		// try again once after removing a stale lock
+		for _ in 0..2 {
+			match OpenOptions::new().write(true).create_new(true).open(path) {
+				Ok(file) => {
+					let mut file = File {
+						file,
+						path: path.to_owned(),
+					};
+					file.write(format!("{}\n", std::process::id()))?;
+					file.sync()?;
+					return Ok(Some(LockFile { file: Some(file) }));
+				}
+				Err(err) => {
+					if err.kind() != ErrorKind::AlreadyExists {
+						Err(err)?
+					}
+				}
+			}
+
+			if !is_stale(path)? {
+				return Ok(None);
+			}
+			remove_stale(path)?;
+		}
+		Ok(None)
+	}
+
+	/// Wait until the lock is free and take it, failing after `timeout`.
+	pub fn acquire<T: AsRef<Path>>(path: T, timeout: Duration) -> Result<LockFile> {
+		let path = path.as_ref();
+		let start = Instant::now();
+		loop {
+			if let Some(lock) = Self::try_acquire(path)? {
+				return Ok(lock);
+			}
+			if start.elapsed() >= timeout {
+				Err(format!("timed out waiting for lock `{}`", path.to_string_lossy()))?;
+			}
+			thread::sleep(Duration::from_millis(10));
+		}
+	}
+
+	pub fn path(&self) -> &Path {
+		self.file.as_ref().unwrap().path()
+	}
+
+	/// Release the lock, reporting any error removing the lock file.
+	pub fn release(mut self) -> Result<()> {
+		self.file.take().unwrap().delete()
+	}
+}
+
+impl Drop for LockFile {
+	fn drop(&mut self) {
+		if let Some(file) = self.file.take() {
+			let _ = file.delete();
+		}
+	}
+}
+
+/// Remove a stale lock, without removing a new lock taken after it.
+///
+/// Only one of the processes renaming the stale lock succeeds. The renamed
+/// lock is checked again, in case another process replaced the stale lock
+/// in between, and put back if it is not stale. See [`LockFile`] for when
+/// putting it back fails.
+fn remove_stale(path: &Path) -> Result<()> {
+	let mut name = path.as_os_str().to_owned();
+	name.push(format!(".stale.{}.{}", std::process::id(), rand::random::<u32>()));
+	let stale = PathBuf::from(name);
+	match std::fs::rename(path, &stale) {
+		Ok(_) => {}
+		// taken over by another process
+		Err(err) if err.kind() == ErrorKind::NotFound => return Ok(()),
+		Err(err) => Err(err)?,
+	}
+
+	if !is_stale(&stale)? {
+		// fails if yet another lock was taken, which is left to its owner
+		let _ = std::fs::hard_link(&stale, path);
+	}
+	std::fs::remove_file(&stale)?;
+	Ok(())
+}
+
+fn is_stale(path: &Path) -> Result<bool> {
+	let read = std::fs::metadata(path).and_then(|meta| {
+		let text = std::fs::read_to_string(path)?;
+		Ok((meta, text))
+	});
+	let (meta, text) = match read {
+		Ok(read) => read,
+		// released while checking
+		Err(err) if err.kind() == ErrorKind::NotFound => return Ok(true),
+		Err(err) => Err(err)?,
+	};
+
+	// the owner may not have written its PID yet
+	if let Some(running) = text.trim().parse::<u32>().ok().and_then(is_running) {
+		return Ok(!running);
+	}
+
+	let age = meta.modified()?.elapsed().unwrap_or_default();
+	Ok(age > LockFile::MAX_AGE)
+}
+
+/// Check if the process `pid` is running, or `None` if it cannot be
+/// checked.
+#[cfg(unix)]
+fn is_running(pid: u32) -> Option<bool> {
+	let Ok(pid) = i32::try_from(pid) else {
+		return Some(false);
+	};
+	if pid <= 0 {
+		return Some(false);
+	}
+
+	match sys::kill(pid, 0) {
+		Ok(_) => Some(true),
+		Err(err) => Some(err.raw_os_error() != Some(sys::ESRCH)),
+	}
+}
+
+#[cfg(not(unix))]
+fn is_running(_pid: u32) -> Option<bool> {
+	None
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn lock_file() -> Result<()> {
+		let dir = dir()?;
+		let path = dir.path().join("test.lock");
+
+		let lock = LockFile::try_acquire(&path)?.unwrap();
+		assert_eq!(std::fs::read_to_string(&path)?, format!("{}\n", std::process::id()));
+		assert!(LockFile::try_acquire(&path)?.is_none());
+
+		let err = LockFile::acquire(&path, Duration::from_millis(50)).err().unwrap();
+		assert!(err.to_string().starts_with("timed out waiting for lock"));
+
+		// waiting for a lock released by another thread
+		let t = thread::spawn(move || {
+			thread::sleep(Duration::from_millis(50));
+			drop(lock);
+		});
+		let lock = LockFile::acquire(&path, Duration::from_secs(5))?;
+		t.join().unwrap();
+		lock.release()?;
+		assert!(!path.exists());
+
+		// locks from processes that are gone are stale
+		std::fs::write(&path, format!("{}\n", i32::MAX))?;
+		let lock = LockFile::try_acquire(&path)?.unwrap();
+		assert_eq!(lock.path(), path);
+
+		// a lock renamed by another process is put back if it is not stale
+		let other = dir.path().join("other.lock");
+		std::fs::write(&other, format!("{}\n", std::process::id()))?;
+		remove_stale(&other)?;
+		assert!(other.exists());
+		drop(lock);
+
+		// no renamed locks are left behind
+		std::fs::remove_file(&other)?;
+		assert_eq!(std::fs::read_dir(dir.path())?.count(), 0);
+		Ok(())
+	}
+}
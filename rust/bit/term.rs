@@ -1,4 +1,12 @@
-use std::{fmt::Display, io::Write};
+use std::{
+	fmt::Display,
+	io::{IsTerminal, Write},
+	str::FromStr,
+	sync::{
+		atomic::{AtomicU8, Ordering},
+		OnceLock,
+	},
+};
 
 /*
 	Escape sequences
@@ -84,22 +92,140 @@ use std::{fmt::Display, io::Write};
 
 use super::*;
 
+pub mod ansi;
+pub mod styled;
+
+pub use ansi::*;
+pub use styled::*;
+
+/// When to use colours and other escape sequences.
+///
+/// With [`Mode::Auto`], escape sequences are written to a [`Stream`] only
+/// when it is a terminal, unless `CLICOLOR_FORCE` is set. Setting `NO_COLOR`
+/// or `TERM=dumb` disables them.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Mode {
+	Auto,
+	Always,
+	Never,
+}
+
+static MODE: AtomicU8 = AtomicU8::new(Mode::Auto as u8);
+
+pub fn mode() -> Mode {
+	match MODE.load(Ordering::Relaxed) {
+		1 => Mode::Always,
+		2 => Mode::Never,
+		_ => Mode::Auto,
+	}
+}
+
+pub fn set_mode(mode: Mode) {
+	MODE.store(mode as u8, Ordering::Relaxed);
+}
+
+/// Whether escape sequences are written to stdout with the current
+/// [`Mode`]. When disabled, all functions in this module taking a writer
+/// only write plain text.
+///
+/// Use [`Stream`] to write to stderr, which may be redirected separately.
+pub fn color_enabled() -> bool {
+	Stream::Stdout.color_enabled()
+}
+
+/// Standard output stream, deciding on colours on its own.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Stream {
+	Stdout,
+	Stderr,
+}
+
+impl Stream {
+	/// Whether escape sequences are written to the stream with the current
+	/// [`Mode`].
+	pub fn color_enabled(self) -> bool {
+		static STDOUT: OnceLock<bool> = OnceLock::new();
+		static STDERR: OnceLock<bool> = OnceLock::new();
+		let (detected, is_terminal) = match self {
+			Stream::Stdout => (&STDOUT, std::io::stdout().is_terminal()),
+			Stream::Stderr => (&STDERR, std::io::stderr().is_terminal()),
+		};
+		match mode() {
+			Mode::Always => true,
+			Mode::Never => false,
+			Mode::Auto => *detected.get_or_init(|| Mode::Auto.use_color(|name| std::env::var(name).ok(), is_terminal)),
+		}
+	}
+
+	/// Same as [`output`], with colours enabled for this stream.
+	pub fn output<T: Display>(self, color: Color, msg: T) -> Result<()> {
+		let enabled = self.color_enabled();
+		match self {
+			Stream::Stdout => output_with(std::io::stdout().lock(), enabled, color, msg),
+			Stream::Stderr => output_with(std::io::stderr().lock(), enabled, color, msg),
+		}
+	}
+
+	/// Same as [`error`], with colours enabled for this stream.
+	pub fn error<T: Display>(self, msg: T) -> Result<()> {
+		self.output(RED, msg)
+	}
+}
+
+impl Mode {
+	/// Decide whether to use colours, given a lookup for environment
+	/// variables and whether the output is a terminal.
+	pub fn use_color<T: Fn(&str) -> Option<String>>(self, var: T, is_terminal: bool) -> bool {
+		match self {
+			Mode::Always => return true,
+			Mode::Never => return false,
+			Mode::Auto => {}
+		}
+
+		let is_set = |name| var(name).is_some_and(|x| !x.is_empty() && x != "0");
+		if is_set("CLICOLOR_FORCE") {
+			true
+		} else if var("NO_COLOR").is_some_and(|x| !x.is_empty()) || var("TERM").as_deref() == Some("dumb") {
+			false
+		} else {
+			is_terminal
+		}
+	}
+}
+
+impl FromStr for Mode {
+	type Err = Error;
+
+	fn from_str(s: &str) -> Result<Self> {
+		match s {
+			"auto" => Ok(Mode::Auto),
+			"always" => Ok(Mode::Always),
+			"never" => Ok(Mode::Never),
+			_ => Err(format!("invalid color mode `{s}` (expected auto, always or never)"))?,
+		}
+	}
+}
+
 pub fn error<T: Write, U: Display>(out: T, msg: U) -> Result<()> {
 	output(out, RED, msg)
 }
 
-pub fn output<T: Write, U: Display>(mut out: T, color: Color, msg: U) -> Result<()> {
-	reset(&mut out)?;
-	color.fg(&mut out)?;
+pub fn output<T: Write, U: Display>(out: T, color: Color, msg: U) -> Result<()> {
+	output_with(out, color_enabled(), color, msg)
+}
+
+fn output_with<T: Write, U: Display>(mut out: T, enabled: bool, color: Color, msg: U) -> Result<()> {
+	esc_with(&mut out, enabled, "0m")?;
+	esc_with(&mut out, enabled, color.fg_code())?;
 	write!(&mut out, "{msg}")?;
-	reset(&mut out)?;
+	esc_with(&mut out, enabled, "0m")?;
 	out.flush()?;
 	Ok(())
 }
 
 pub const ESC: char = '\x1B';
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum Color {
 	Std { fg: u8, bg: u8 },
 	Pal(u8),
@@ -124,10 +250,14 @@ pub const COLOR_NAMES: [&'static str; 9] = [
 
 impl Color {
 	pub fn fg<T: Write>(&self, out: T) -> Result<()> {
+		esc(out, self.fg_code())
+	}
+
+	fn fg_code(&self) -> String {
 		match self {
-			Color::Std { fg, .. } => esc(out, format!("{fg}m")),
-			Color::Pal(v) => esc(out, format!("38;5;{v}m")),
-			Color::Rgb { r, g, b } => esc(out, format!("38;2;{r};{g};{b}m")),
+			Color::Std { fg, .. } => format!("{fg}m"),
+			Color::Pal(v) => format!("38;5;{v}m"),
+			Color::Rgb { r, g, b } => format!("38;2;{r};{g};{b}m"),
 		}
 	}
 
@@ -161,7 +291,14 @@ pub fn dim<T: Write>(out: T) -> Result<()> {
 }
 
 #[inline]
-pub fn esc<T: Write, U: AsRef<str>>(mut out: T, seq: U) -> Result<()> {
+pub fn esc<T: Write, U: AsRef<str>>(out: T, seq: U) -> Result<()> {
+	esc_with(out, color_enabled(), seq)
+}
+
+fn esc_with<T: Write, U: AsRef<str>>(mut out: T, enabled: bool, seq: U) -> Result<()> {
+	if !enabled {
+		return Ok(());
+	}
 	let seq = seq.as_ref();
 	write!(out, "{ESC}[{seq}")?;
 	Ok(())
@@ -254,11 +391,36 @@ fn output_colors<T: Write>(mut out: T) -> Result<()> {
 }
 
 #[cfg(test)]
-#[cfg(off)]
 mod tests {
 	use super::*;
 
 	#[test]
+	fn color_mode() -> Result<()> {
+		let env = |vars: &'static [(&'static str, &'static str)]| {
+			move |name: &str| vars.iter().find(|x| x.0 == name).map(|x| x.1.to_string())
+		};
+		assert!(Mode::Auto.use_color(env(&[]), true));
+		assert!(!Mode::Auto.use_color(env(&[]), false));
+		assert!(!Mode::Auto.use_color(env(&[("NO_COLOR", "1")]), true));
+		assert!(Mode::Auto.use_color(env(&[("NO_COLOR", "")]), true));
+		assert!(!Mode::Auto.use_color(env(&[("TERM", "dumb")]), true));
+		assert!(Mode::Auto.use_color(env(&[("CLICOLOR_FORCE", "1"), ("NO_COLOR", "1")]), false));
+		assert!(!Mode::Auto.use_color(env(&[("CLICOLOR_FORCE", "0")]), false));
+		assert!(Mode::Always.use_color(env(&[("NO_COLOR", "1")]), false));
+		assert!(!Mode::Never.use_color(env(&[]), true));
+
+		assert_eq!("always".parse::<Mode>()?, Mode::Always);
+		assert!("yes".parse::<Mode>().is_err());
+
+		let mut out = Vec::new();
+		output_with(&mut out, false, RED, "plain")?;
+		output_with(&mut out, true, RED, "red")?;
+		assert_eq!(String::from_utf8(out)?, "plain\x1B[0m\x1B[31mred\x1B[0m");
+		Ok(())
+	}
+
+	#[test]
+	#[cfg(off)]
 	pub fn term_output() -> Result<()> {
 		let mut out = std::io::stdout();
 		println!("hello");
@@ -0,0 +1,508 @@
+use std::fmt::Write as _;
+
+use super::*;
+
+/// Terminal screen that replays text containing escape sequences.
+///
+/// Understands the cursor, erase and graphics sequences documented at the
+/// top of this module, and ignores any other sequence. The screen grows as
+/// needed. Characters take their [`unicode::char_width`] in columns, and
+/// zero width characters are dropped.
+#[derive(Clone, Debug, Default)]
+pub struct Screen {
+	lines: Vec<Vec<Option<Cell>>>,
+	row: usize,
+	col: usize,
+	saved: (usize, usize),
+	style: Style,
+	pending: String,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+struct Cell {
+	chr: char,
+	style: Style,
+}
+
+/// Character of the cells covered by the previous wide character.
+const WIDE: char = '\0';
+
+/// Largest parameter of escape sequences, see also [`MAX_SIZE`].
+const MAX_PARAM: usize = 4096;
+
+/// Largest number of rows and columns reached by moving the cursor, so
+/// repeated moves cannot grow the screen without bounds. Text and line
+/// breaks still grow it past this size.
+const MAX_SIZE: usize = 4096;
+
+impl Screen {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	pub fn parse<T: AsRef<str>>(text: T) -> Self {
+		let mut screen = Self::new();
+		screen.feed(text);
+		screen
+	}
+
+	/// Process more output. Escape sequences may be split between calls.
+	pub fn feed<T: AsRef<str>>(&mut self, text: T) {
+		let mut input = std::mem::take(&mut self.pending);
+		input.push_str(text.as_ref());
+
+		let mut chars = input.char_indices();
+		while let Some((pos, chr)) = chars.next() {
+			match chr {
+				ESC => match chars.next() {
+					Some((_, '[')) => {
+						let mut params = String::new();
+						let end = loop {
+							match chars.next() {
+								Some((_, chr)) if ('@'..='~').contains(&chr) => break Some(chr),
+								Some((_, chr)) => params.push(chr),
+								None => break None,
+							}
+						};
+						match end {
+							Some(end) => self.csi(&params, end),
+							None => {
+								self.pending = input[pos..].to_string();
+								return;
+							}
+						}
+					}
+					Some((_, '7')) => self.saved = (self.row, self.col),
+					Some((_, '8')) => (self.row, self.col) = self.saved,
+					Some((_, 'M')) => self.row = self.row.saturating_sub(1),
+					Some(_) => {}
+					None => {
+						self.pending = input[pos..].to_string();
+						return;
+					}
+				},
+				'\n' => {
+					self.row += 1;
+					self.col = 0;
+					self.line();
+				}
+				'\r' => self.col = 0,
+				'\x08' => self.col = self.col.saturating_sub(1),
+				'\t' => self.col = (self.col / 8 + 1) * 8,
+				chr if chr.is_control() => {}
+				chr => {
+					let width = unicode::char_width(chr);
+					if width == 0 {
+						continue;
+					}
+					let (col, style) = (self.col, self.style);
+					let line = self.line();
+					if line.len() < col + width {
+						line.resize(col + width, None);
+					}
+					erase_wide(line, col);
+					erase_wide(line, col + width - 1);
+					line[col] = Some(Cell { chr, style });
+					line[col + 1..col + width].fill(Some(Cell { chr: WIDE, style }));
+					self.col += width;
+				}
+			}
+		}
+	}
+
+	/// Lines of the screen, without trailing blanks.
+	pub fn lines(&self) -> Vec<StyledText> {
+		let mut out = Vec::new();
+		for line in self.lines.iter() {
+			let mut text = StyledText::new();
+			let len = line.iter().rposition(|x| x.is_some()).map(|x| x + 1).unwrap_or(0);
+			let mut covered = 0;
+			for cell in line[..len].iter() {
+				match cell {
+					Some(cell) if cell.chr == WIDE && covered > 0 => covered -= 1,
+					Some(cell) if cell.chr == WIDE => text.push(' ', Style::default()),
+					Some(cell) => {
+						text.push(cell.chr, cell.style);
+						covered = unicode::char_width(cell.chr) - 1;
+					}
+					None => {
+						text.push(' ', Style::default());
+						covered = 0;
+					}
+				}
+			}
+			out.push(text);
+		}
+		out
+	}
+
+	pub fn to_styled(&self) -> StyledText {
+		let mut out = StyledText::new();
+		for (n, line) in self.lines().into_iter().enumerate() {
+			if n > 0 {
+				out.push('\n', Style::default());
+			}
+			for span in line.spans() {
+				out.push(span.text.as_str(), span.style);
+			}
+		}
+		out
+	}
+
+	pub fn to_plain(&self) -> String {
+		self.to_styled().to_plain()
+	}
+
+	/// Render as a `pre` element with inline styles.
+	pub fn to_html(&self) -> String {
+		let mut out = String::from("<pre class=\"ansi\">");
+		for span in self.to_styled().spans() {
+			let css = css(&span.style);
+			if css.is_empty() {
+				escape_html(&mut out, &span.text);
+			} else {
+				let _ = write!(out, "<span style=\"{css}\">");
+				escape_html(&mut out, &span.text);
+				out.push_str("</span>");
+			}
+		}
+		out.push_str("</pre>");
+		out
+	}
+
+	fn line(&mut self) -> &mut Vec<Option<Cell>> {
+		if self.lines.len() <= self.row {
+			self.lines.resize_with(self.row + 1, Vec::new);
+		}
+		&mut self.lines[self.row]
+	}
+
+	fn csi(&mut self, params: &str, end: char) {
+		let args = params
+			.split(';')
+			.map(|x| x.parse().map_or(0, |x: usize| x.min(MAX_PARAM)))
+			.collect::<Vec<usize>>();
+		let arg = |n: usize| match args.get(n) {
+			Some(0) | None => 1,
+			Some(v) => *v,
+		};
+		let (row, col) = (self.row, self.col);
+		match end {
+			'H' | 'f' => {
+				self.row = arg(0) - 1;
+				self.col = arg(1) - 1;
+			}
+			'A' => self.row = self.row.saturating_sub(arg(0)),
+			'B' => self.row = self.row.saturating_add(arg(0)),
+			'C' => self.col = self.col.saturating_add(arg(0)),
+			'D' => self.col = self.col.saturating_sub(arg(0)),
+			'E' => (self.row, self.col) = (self.row.saturating_add(arg(0)), 0),
+			'F' => (self.row, self.col) = (self.row.saturating_sub(arg(0)), 0),
+			'G' => self.col = arg(0) - 1,
+			'J' => match args[0] {
+				0 => {
+					self.lines.truncate(self.row + 1);
+					let col = self.col;
+					self.line().truncate(col);
+				}
+				1 => {
+					for line in self.lines.iter_mut().take(self.row) {
+						line.clear();
+					}
+					self.erase_start();
+				}
+				2 => self.lines.clear(),
+				// only erases the saved lines, which are not kept
+				_ => {}
+			},
+			'K' => match args[0] {
+				0 => {
+					let col = self.col;
+					self.line().truncate(col);
+				}
+				1 => self.erase_start(),
+				_ => self.line().clear(),
+			},
+			's' => self.saved = (self.row, self.col),
+			'u' => (self.row, self.col) = self.saved,
+			'm' => self.sgr(&args),
+			_ => {}
+		}
+		self.row = self.row.min(row.max(MAX_SIZE - 1));
+		self.col = self.col.min(col.max(MAX_SIZE - 1));
+	}
+
+	/// Erase the current line up to and including the cursor.
+	fn erase_start(&mut self) {
+		let col = self.col;
+		let line = self.line();
+		let end = (col + 1).min(line.len());
+		line[..end].fill(None);
+	}
+
+	fn sgr(&mut self, args: &[usize]) {
+		let style = &mut self.style;
+		let mut n = 0;
+		while n < args.len() {
+			let code = args[n];
+			match code {
+				0 => *style = Style::default(),
+				1 => style.bold = true,
+				2 => style.dim = true,
+				3 => style.italic = true,
+				4 => style.underline = true,
+				5 => style.blink = true,
+				7 => style.inverse = true,
+				8 => style.hidden = true,
+				9 => style.strikethrough = true,
+				22 => (style.bold, style.dim) = (false, false),
+				23 => style.italic = false,
+				24 => style.underline = false,
+				25 => style.blink = false,
+				27 => style.inverse = false,
+				28 => style.hidden = false,
+				29 => style.strikethrough = false,
+				30..=37 | 90..=97 => {
+					let fg = code as u8;
+					style.fg = Some(Color::Std { fg, bg: fg + 10 });
+				}
+				39 => style.fg = None,
+				40..=47 | 100..=107 => {
+					let bg = code as u8;
+					style.bg = Some(Color::Std { fg: bg - 10, bg });
+				}
+				49 => style.bg = None,
+				38 | 48 => {
+					let byte = |v: usize| v.min(255) as u8;
+					let color = match args.get(n + 1) {
+						Some(5) => {
+							n += 2;
+							args.get(n).map(|v| Color::Pal(byte(*v)))
+						}
+						Some(2) => {
+							n += 4;
+							let rgb = args.get(n - 2..=n);
+							rgb.map(|x| Color::Rgb {
+								r: byte(x[0]),
+								g: byte(x[1]),
+								b: byte(x[2]),
+							})
+						}
+						_ => None,
+					};
+					if code == 38 {
+						style.fg = color;
+					} else {
+						style.bg = color;
+					}
+				}
+				_ => {}
+			}
+			n += 1;
+		}
+	}
+}
+
+/// Erase the whole wide character at `col`, if any, before overwriting part
+/// of it.
+fn erase_wide(line: &mut [Option<Cell>], col: usize) {
+	let is_wide = |cell: &Option<Cell>| cell.is_some_and(|x| x.chr == WIDE);
+	let mut start = col;
+	while start > 0 && is_wide(&line[start]) {
+		start -= 1;
+	}
+	let mut end = start + 1;
+	while end < line.len() && is_wide(&line[end]) {
+		end += 1;
+	}
+	if end - start > 1 {
+		line[start..end].fill(None);
+	}
+}
+
+/// Inline CSS for a style, using the xterm colour palette.
+fn css(style: &Style) -> String {
+	let mut out = Vec::new();
+	if let Some(color) = style.fg.and_then(color_hex) {
+		out.push(format!("color:{color}"));
+	}
+	if let Some(color) = style.bg.and_then(color_hex) {
+		out.push(format!("background-color:{color}"));
+	}
+	if style.bold {
+		out.push("font-weight:bold".to_string());
+	}
+	if style.dim {
+		out.push("opacity:0.5".to_string());
+	}
+	if style.italic {
+		out.push("font-style:italic".to_string());
+	}
+
+	let lines = [
+		(style.underline, "underline"),
+		(style.strikethrough, "line-through"),
+		(style.blink, "blink"),
+	];
+	let lines = lines.iter().filter(|x| x.0).map(|x| x.1).collect::<Vec<_>>();
+	if !lines.is_empty() {
+		out.push(format!("text-decoration:{}", lines.join(" ")));
+	}
+
+	if style.inverse {
+		out.push("filter:invert(100%)".to_string());
+	}
+	if style.hidden {
+		out.push("visibility:hidden".to_string());
+	}
+	out.join(";")
+}
+
+fn color_hex(color: Color) -> Option<String> {
+	const BASE: [(u8, u8, u8); 16] = [
+		(0x00, 0x00, 0x00),
+		(0xCD, 0x00, 0x00),
+		(0x00, 0xCD, 0x00),
+		(0xCD, 0xCD, 0x00),
+		(0x00, 0x00, 0xEE),
+		(0xCD, 0x00, 0xCD),
+		(0x00, 0xCD, 0xCD),
+		(0xE5, 0xE5, 0xE5),
+		(0x7F, 0x7F, 0x7F),
+		(0xFF, 0x00, 0x00),
+		(0x00, 0xFF, 0x00),
+		(0xFF, 0xFF, 0x00),
+		(0x5C, 0x5C, 0xFF),
+		(0xFF, 0x00, 0xFF),
+		(0x00, 0xFF, 0xFF),
+		(0xFF, 0xFF, 0xFF),
+	];
+
+	let index = match color {
+		Color::Std { fg, .. } => match fg {
+			30..=37 => fg - 30,
+			90..=97 => fg - 90 + 8,
+			_ => return None,
+		},
+		Color::Pal(index) => index,
+		Color::Rgb { r, g, b } => return Some(format!("#{r:02x}{g:02x}{b:02x}")),
+	};
+
+	let (r, g, b) = match index {
+		0..=15 => BASE[index as usize],
+		16..=231 => {
+			// 6x6x6 colour cube
+			let level = |x: u8| if x == 0 { 0 } else { 55 + x * 40 };
+			let index = index - 16;
+			(level(index / 36), level(index / 6 % 6), level(index % 6))
+		}
+		_ => {
+			let gray = 8 + (index - 232) * 10;
+			(gray, gray, gray)
+		}
+	};
+	Some(format!("#{r:02x}{g:02x}{b:02x}"))
+}
+
+fn escape_html(out: &mut String, text: &str) {
+	for chr in text.chars() {
+		match chr {
+			'&' => out.push_str("&amp;"),
+			'<' => out.push_str("&lt;"),
+			'>' => out.push_str("&gt;"),
+			'"' => out.push_str("&quot;"),
+			chr => out.push(chr),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn graphics_mode() {
+		let text = "\x1B[1;31mred\x1B[0m plain \x1B[38;5;208mo\x1B[48;2;1;2;3mx\x1B[22;39;3mz";
+		let screen = Screen::parse(text);
+		assert_eq!(screen.to_plain(), "red plain oxz");
+
+		let orange = Color::Pal(208);
+		let rgb = Color::Rgb { r: 1, g: 2, b: 3 };
+		let expected = StyledText::new()
+			.add("red", Style::new().fg(RED).bold())
+			.plain(" plain ")
+			.add("o", Style::new().fg(orange))
+			.add("x", Style::new().fg(orange).bg(rgb))
+			.add("z", Style::new().bg(rgb).italic());
+		assert_eq!(screen.to_styled(), expected);
+
+		// styled text goes through unchanged
+		let text = expected.plain("\n").add(
+			"all",
+			Style::new()
+				.dim()
+				.underline()
+				.blink()
+				.inverse()
+				.hidden()
+				.strikethrough(),
+		);
+		assert_eq!(Screen::parse(text.to_ansi()).to_styled(), text);
+	}
+
+	#[test]
+	fn cursor_moves() {
+		let plain = |text: &str| Screen::parse(text).to_plain();
+		assert_eq!(plain("hello\rj"), "jello");
+		assert_eq!(plain("abc\x1B[2Dx"), "axc");
+		assert_eq!(plain("line1\nline2\x1B[1A\x1B[3G!"), "li!e1\nline2");
+		assert_eq!(plain("abcdef\x1B[3D\x1B[K\n"), "abc\n");
+		assert_eq!(plain("abcdef\x1B[3D\x1B[1K"), "    ef");
+		assert_eq!(plain("x\ny\x1B[H\x1B[2Jz"), "z");
+		assert_eq!(plain("x\ny\x1B[3Jz"), "x\nyz");
+		assert_eq!(plain("\x1B[3;2Hz\x1B[Hy"), "y\n\n z");
+		assert_eq!(plain("ab\x1B7cd\x1B8X\x1B[sY\x1B[5GZ\x1B[uW"), "abXWZ");
+		assert_eq!(plain("a\tb\x08c"), "a       c");
+		assert_eq!(plain("\x1B[?25lhidden cursor\x1B[?25h"), "hidden cursor");
+
+		// huge parameters are clamped instead of overflowing
+		let huge = plain("\x1B[18446744073709551615Bx\x1B[999999999;1Hx\x1B[999999999Cx");
+		let lines = huge.lines().collect::<Vec<_>>();
+		assert_eq!(lines.len(), MAX_SIZE);
+		assert_eq!(lines[MAX_SIZE - 1], format!("x{}x", " ".repeat(MAX_SIZE - 2)));
+
+		// and repeated moves stop at the edge of the screen
+		let moves = "\x1B[4096C\x1B[4096B".repeat(100);
+		assert_eq!(plain(&format!("{moves}x")).len(), MAX_SIZE * 2 - 1);
+		assert_eq!(plain(&format!("{}\x1B[99Cx", "a".repeat(5000))).len(), 5001);
+	}
+
+	#[test]
+	fn wide_characters() {
+		let plain = |text: &str| Screen::parse(text).to_plain();
+		assert_eq!(plain("日本\x1B[2Dx"), "日x");
+		assert_eq!(plain("日本語\rx"), "x 本語");
+		assert_eq!(plain("ab\x1B[1G日c"), "日c");
+		assert_eq!(plain("e\u{0301}x"), "ex");
+		assert_eq!(plain("日本\x1B[2G\x1B[1K|"), " |本");
+		assert_eq!(display_width(plain("a日\tb")), 9);
+	}
+
+	#[test]
+	fn html_and_streaming() {
+		let mut screen = Screen::new();
+		screen.feed("<a> & \x1B[3");
+		screen.feed("1;1");
+		screen.feed("mred\x1B");
+		screen.feed("[0m\n\x1B[4;9;48;5;231mline\x1B[m");
+		assert_eq!(
+			screen.to_html(),
+			concat!(
+				"<pre class=\"ansi\">&lt;a&gt; &amp; ",
+				"<span style=\"color:#cd0000;font-weight:bold\">red</span>\n",
+				"<span style=\"background-color:#ffffff;text-decoration:underline line-through\">line</span>",
+				"</pre>",
+			)
+		);
+	}
+}
@@ -0,0 +1,331 @@
+use std::fmt::Write as _;
+
+use super::*;
+
+const ELLIPSIS: char = '…';
+
+/// Colours and modes for a [`StyledSpan`].
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct Style {
+	pub fg: Option<Color>,
+	pub bg: Option<Color>,
+	pub bold: bool,
+	pub dim: bool,
+	pub italic: bool,
+	pub underline: bool,
+	pub blink: bool,
+	pub inverse: bool,
+	pub hidden: bool,
+	pub strikethrough: bool,
+}
+
+impl Style {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	pub fn fg(mut self, color: Color) -> Self {
+		self.fg = Some(color);
+		self
+	}
+
+	pub fn bg(mut self, color: Color) -> Self {
+		self.bg = Some(color);
+		self
+	}
+
+	pub fn bold(mut self) -> Self {
+		self.bold = true;
+		self
+	}
+
+	pub fn dim(mut self) -> Self {
+		self.dim = true;
+		self
+	}
+
+	pub fn italic(mut self) -> Self {
+		self.italic = true;
+		self
+	}
+
+	pub fn underline(mut self) -> Self {
+		self.underline = true;
+		self
+	}
+
+	pub fn blink(mut self) -> Self {
+		self.blink = true;
+		self
+	}
+
+	pub fn inverse(mut self) -> Self {
+		self.inverse = true;
+		self
+	}
+
+	pub fn hidden(mut self) -> Self {
+		self.hidden = true;
+		self
+	}
+
+	pub fn strikethrough(mut self) -> Self {
+		self.strikethrough = true;
+		self
+	}
+
+	pub fn is_plain(&self) -> bool {
+		*self == Self::default()
+	}
+
+	/// Parameters for the SGR sequence setting this style, e.g. `1;31`.
+	pub fn sgr(&self) -> String {
+		let mut codes = Vec::new();
+		let modes = [
+			(self.bold, 1),
+			(self.dim, 2),
+			(self.italic, 3),
+			(self.underline, 4),
+			(self.blink, 5),
+			(self.inverse, 7),
+			(self.hidden, 8),
+			(self.strikethrough, 9),
+		];
+		for (set, code) in modes {
+			if set {
+				codes.push(code.to_string());
+			}
+		}
+		if let Some(color) = self.fg {
+			codes.push(match color {
+				Color::Std { fg, .. } => format!("{fg}"),
+				Color::Pal(v) => format!("38;5;{v}"),
+				Color::Rgb { r, g, b } => format!("38;2;{r};{g};{b}"),
+			});
+		}
+		if let Some(color) = self.bg {
+			codes.push(match color {
+				Color::Std { bg, .. } => format!("{bg}"),
+				Color::Pal(v) => format!("48;5;{v}"),
+				Color::Rgb { r, g, b } => format!("48;2;{r};{g};{b}"),
+			});
+		}
+		codes.join(";")
+	}
+}
+
+/// Text with a single style.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct StyledSpan {
+	pub text: String,
+	pub style: Style,
+}
+
+/// Text made of styled spans, for output that must be aligned in columns.
+///
+/// The spans hold plain text, with styles written only when rendered.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct StyledText {
+	spans: Vec<StyledSpan>,
+}
+
+impl StyledText {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Append text with the given style.
+	pub fn add<T: Into<String>>(mut self, text: T, style: Style) -> Self {
+		self.push(text, style);
+		self
+	}
+
+	/// Append unstyled text.
+	pub fn plain<T: Into<String>>(self, text: T) -> Self {
+		self.add(text, Style::default())
+	}
+
+	pub fn push<T: Into<String>>(&mut self, text: T, style: Style) {
+		let text = text.into();
+		if text.is_empty() {
+			return;
+		}
+		match self.spans.last_mut() {
+			Some(last) if last.style == style => last.text.push_str(&text),
+			_ => self.spans.push(StyledSpan { text, style }),
+		}
+	}
+
+	pub fn spans(&self) -> &[StyledSpan] {
+		&self.spans
+	}
+
+	/// Width in terminal columns.
+	pub fn width(&self) -> usize {
+		self.spans.iter().map(|x| display_width(&x.text)).sum()
+	}
+
+	/// Cut the text to at most `width` columns, ending it with an ellipsis
+	/// when anything is removed.
+	pub fn truncate(&self, width: usize) -> StyledText {
+		if self.width() <= width {
+			return self.clone();
+		}
+
+		let mut out = StyledText::new();
+		let mut left = width.saturating_sub(1);
+		for span in self.spans.iter() {
+			let mut text = String::new();
+			for chr in span.text.chars() {
+				let size = unicode::char_width(chr);
+				if size > left {
+					if width > 0 {
+						text.push(ELLIPSIS);
+					}
+					out.push(text, span.style);
+					return out;
+				}
+				left -= size;
+				text.push(chr);
+			}
+			out.push(text, span.style);
+		}
+		out
+	}
+
+	/// Add unstyled spaces at the end, up to `width` columns.
+	pub fn pad(&self, width: usize) -> StyledText {
+		let mut out = self.clone();
+		out.push(" ".repeat(width.saturating_sub(self.width())), Style::default());
+		out
+	}
+
+	/// Add unstyled spaces at the start, up to `width` columns.
+	pub fn pad_left(&self, width: usize) -> StyledText {
+		let mut out = StyledText::new();
+		out.push(" ".repeat(width.saturating_sub(self.width())), Style::default());
+		for span in self.spans.iter() {
+			out.push(span.text.as_str(), span.style);
+		}
+		out
+	}
+
+	/// Truncate or pad to exactly `width` columns.
+	pub fn fit(&self, width: usize) -> StyledText {
+		self.truncate(width).pad(width)
+	}
+
+	pub fn to_plain(&self) -> String {
+		self.spans.iter().map(|x| x.text.as_str()).collect()
+	}
+
+	pub fn to_ansi(&self) -> String {
+		let mut out = String::new();
+		for span in self.spans.iter() {
+			if span.style.is_plain() {
+				out.push_str(&span.text);
+			} else {
+				let _ = write!(out, "{ESC}[{}m{}{ESC}[0m", span.style.sgr(), span.text);
+			}
+		}
+		out
+	}
+
+	/// Write the text, with styles only if colours are enabled.
+	pub fn write<T: Write>(&self, out: T) -> Result<()> {
+		self.write_with(out, color_enabled())
+	}
+
+	/// Write the text to `stream`, with styles only if colours are enabled
+	/// for it.
+	pub fn write_to(&self, stream: Stream) -> Result<()> {
+		let enabled = stream.color_enabled();
+		match stream {
+			Stream::Stdout => self.write_with(std::io::stdout().lock(), enabled)?,
+			Stream::Stderr => self.write_with(std::io::stderr().lock(), enabled)?,
+		}
+		Ok(())
+	}
+
+	fn write_with<T: Write>(&self, mut out: T, enabled: bool) -> Result<()> {
+		if enabled {
+			write!(out, "{}", self.to_ansi())?;
+		} else {
+			write!(out, "{}", self.to_plain())?;
+		}
+		Ok(())
+	}
+}
+
+impl Display for StyledText {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		write!(f, "{}", self.to_plain())
+	}
+}
+
+/// Width of the text in terminal columns, ignoring escape sequences.
+pub fn display_width<T: AsRef<str>>(text: T) -> usize {
+	let mut width = 0;
+	let mut chars = text.as_ref().chars().peekable();
+	while let Some(chr) = chars.next() {
+		if chr != ESC {
+			width += unicode::char_width(chr);
+			continue;
+		}
+
+		// control sequences end with a byte in the `@` to `~` range, other
+		// escapes are a single character
+		if chars.next_if_eq(&'[').is_some() {
+			for chr in chars.by_ref() {
+				if ('@'..='~').contains(&chr) {
+					break;
+				}
+			}
+		} else {
+			chars.next();
+		}
+	}
+	width
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn styled_text() {
+		let text = StyledText::new()
+			.add("error", Style::new().fg(RED).bold())
+			.plain(": ")
+			.add("日本語", Style::new().underline().bg(Color::Pal(236)));
+		assert_eq!(text.width(), 13);
+		assert_eq!(text.to_plain(), "error: 日本語");
+		assert_eq!(text.to_ansi(), "\x1B[1;31merror\x1B[0m: \x1B[4;48;5;236m日本語\x1B[0m");
+		assert_eq!(display_width(text.to_ansi()), 13);
+
+		// truncating never splits a wide character
+		assert_eq!(text.truncate(13), text);
+		assert_eq!(text.truncate(12).to_plain(), "error: 日本…");
+		assert_eq!(text.truncate(11).to_plain(), "error: 日…");
+		assert_eq!(text.truncate(11).width(), 10);
+		assert_eq!(text.truncate(3).spans()[0].text, "er…");
+		assert_eq!(text.truncate(0).to_plain(), "");
+
+		assert_eq!(text.fit(11).width(), 11);
+		assert_eq!(text.pad(15).to_plain(), "error: 日本語  ");
+		assert_eq!(text.pad_left(15).to_plain(), "  error: 日本語");
+		assert_eq!(text.pad(5), text);
+
+		let mut out = Vec::new();
+		text.write_with(&mut out, false).unwrap();
+		text.write_with(&mut out, true).unwrap();
+		assert_eq!(String::from_utf8(out).unwrap(), text.to_plain() + &text.to_ansi());
+	}
+
+	#[test]
+	fn width_with_escapes() {
+		assert_eq!(display_width("\x1B[1;38;2;1;2;3mab\x1B[0m"), 2);
+		assert_eq!(display_width("\x1B[2Jx\x1B7y"), 2);
+		assert_eq!(display_width("e\u{0301}\x1B"), 1);
+	}
+}
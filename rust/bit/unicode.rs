@@ -87,6 +87,55 @@ pub fn utf8_len(input: &[u8]) -> usize {
 	}
 }
 
+/// Number of terminal columns used to display a character.
+///
+/// Control characters and combining marks take no space. East Asian wide
+/// and fullwidth characters, and most emoji, take two columns.
+pub fn char_width(chr: char) -> usize {
+	let code = chr as u32;
+	if code < 0x20 || (0x7F..0xA0).contains(&code) {
+		return 0;
+	}
+	if ZERO_WIDTH.iter().any(|(sta, end)| (*sta..=*end).contains(&code)) {
+		return 0;
+	}
+	if WIDE.iter().any(|(sta, end)| (*sta..=*end).contains(&code)) {
+		return 2;
+	}
+	1
+}
+
+const ZERO_WIDTH: [(u32, u32); 9] = [
+	(0x0300, 0x036F), // combining diacritical marks
+	(0x0483, 0x0489), // combining cyrillic
+	(0x0591, 0x05BD), // hebrew points
+	(0x1AB0, 0x1AFF), // combining diacritical marks extended
+	(0x1DC0, 0x1DFF), // combining diacritical marks supplement
+	(0x200B, 0x200F), // zero width space, joiners and marks
+	(0x20D0, 0x20FF), // combining marks for symbols
+	(0xFE00, 0xFE0F), // variation selectors
+	(0xFE20, 0xFE2F), // combining half marks
+];
+
+const WIDE: [(u32, u32); 16] = [
+	(0x1100, 0x115F),   // hangul jamo
+	(0x2E80, 0x303E),   // CJK radicals and punctuation
+	(0x3041, 0x33FF),   // kana and CJK symbols
+	(0x3400, 0x4DBF),   // CJK extension A
+	(0x4E00, 0x9FFF),   // CJK unified ideographs
+	(0xA000, 0xA4CF),   // yi
+	(0xAC00, 0xD7A3),   // hangul syllables
+	(0xF900, 0xFAFF),   // CJK compatibility ideographs
+	(0xFE30, 0xFE4F),   // CJK compatibility forms
+	(0xFF00, 0xFF60),   // fullwidth forms
+	(0xFFE0, 0xFFE6),   // fullwidth signs
+	(0x1F300, 0x1F64F), // pictographs and emoticons
+	(0x1F680, 0x1F6FF), // transport and map symbols
+	(0x1F900, 0x1F9FF), // supplemental pictographs
+	(0x20000, 0x2FFFD), // CJK extension B and later
+	(0x30000, 0x3FFFD), // CJK extension G and later
+];
+
 #[cfg(test)]
 mod tests {
 	use super::*;
@@ -118,4 +167,15 @@ mod tests {
 			assert_eq!(actual, expected);
 		}
 	}
+
+	#[test]
+	fn display_width() {
+		let width = |text: &str| text.chars().map(char_width).sum::<usize>();
+		assert_eq!(width("abc"), 3);
+		assert_eq!(width("日本語"), 6);
+		assert_eq!(width("e\u{0301}"), 1);
+		assert_eq!(width("ｗｉｄｅ"), 8);
+		assert_eq!(width("🎉!"), 3);
+		assert_eq!(width("\t\x1B"), 0);
+	}
 }
@@ -0,0 +1,826 @@
+use std::{
+	collections::HashMap,
+	fmt::{Display, Formatter},
+	io::Write,
+	rc::Rc,
+};
+
+use super::*;
+
+/// Bytecode instruction for the stack based [`Program`] interpreter.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Op {
+	Int(i64),
+	Bool(bool),
+	/// Push a string from the constant pool.
+	Const(u32),
+	/// Push a local variable from the current frame.
+	Load(u32),
+	/// Pop a value into a local variable of the current frame.
+	Store(u32),
+	Pop,
+	/// Apply an operator to the two top values. Logic operators are
+	/// compiled to jumps instead.
+	Binary(BinaryOp),
+	Unary(UnaryOp),
+	Jump(u32),
+	/// Pop a value and jump if it is false or zero.
+	JumpIfFalse(u32),
+	/// Pop the given number of values and print them.
+	Print(u32),
+	Call(u32),
+	/// Return from the current function, with the top value for functions
+	/// with a result.
+	Return,
+	/// Pop the exit code and stop the program.
+	Exit,
+	/// Reached the end of a function with a result without returning.
+	MissingReturn,
+}
+
+/// Runtime value for the [`Program`] interpreter.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Value<'a> {
+	Int(i64),
+	Bool(bool),
+	/// String from the constant pool.
+	Str(&'a str),
+	/// String created at runtime.
+	String(Rc<str>),
+}
+
+impl<'a> Value<'a> {
+	pub fn as_str(&self) -> Option<&str> {
+		match self {
+			Value::Str(str) => Some(str),
+			Value::String(str) => Some(str),
+			_ => None,
+		}
+	}
+}
+
+impl<'a> Display for Value<'a> {
+	fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+		match self {
+			Value::Int(v) => write!(f, "{v}"),
+			Value::Bool(v) => write!(f, "{v}"),
+			Value::Str(v) => write!(f, "{v}"),
+			Value::String(v) => write!(f, "{v}"),
+		}
+	}
+}
+
+/// Function in a compiled [`Program`].
+#[derive(Clone, Debug)]
+pub struct FuncInfo<'a> {
+	pub name: &'a str,
+	pub entry: u32,
+	pub args: u32,
+	pub locals: u32,
+	pub ret: Kind,
+}
+
+/// Compiled bytecode program. The first function is `main`.
+#[derive(Clone, Debug)]
+pub struct Program<'a> {
+	code: Vec<Op>,
+	consts: Vec<&'a str>,
+	funcs: Vec<FuncInfo<'a>>,
+}
+
+/// Compiles [`Code`] to a bytecode [`Program`].
+pub struct Builder<'a> {
+	store: &'a Store,
+	/// Local slots of the variables and indexes of the functions in scope.
+	scopes: Scopes<'a, u32, u32>,
+	checker: Checker<'a>,
+	code: Vec<Op>,
+	consts: Vec<&'a str>,
+	const_map: HashMap<&'a str, u32>,
+	funcs: Vec<FuncInfo<'a>>,
+	func: usize,
+	loops: Vec<Loop>,
+}
+
+#[derive(Default)]
+struct Loop {
+	start: u32,
+	breaks: Vec<usize>,
+}
+
+impl<'a> Builder<'a> {
+	pub fn new(store: &'a Store) -> Self {
+		let main = FuncInfo {
+			name: store.str("main"),
+			entry: 0,
+			args: 0,
+			locals: 0,
+			ret: Kind::I64,
+		};
+		Self {
+			store,
+			scopes: Scopes::new(),
+			checker: Checker::new(),
+			code: Vec::new(),
+			consts: Vec::new(),
+			const_map: Default::default(),
+			funcs: vec![main],
+			func: 0,
+			loops: Vec::new(),
+		}
+	}
+
+	/// Finish the `main` function and return the program.
+	pub fn build(mut self) -> Program<'a> {
+		self.emit(Op::Int(0));
+		self.emit(Op::Exit);
+		Program {
+			code: self.code,
+			consts: self.consts,
+			funcs: self.funcs,
+		}
+	}
+
+	fn emit(&mut self, op: Op) -> usize {
+		self.code.push(op);
+		self.code.len() - 1
+	}
+
+	fn pos(&self) -> u32 {
+		self.code.len() as u32
+	}
+
+	/// Point the jump at `index` to the current position.
+	fn patch(&mut self, index: usize) {
+		let pos = self.pos();
+		match &mut self.code[index] {
+			Op::Jump(target) | Op::JumpIfFalse(target) => *target = pos,
+			op => panic!("cannot patch {op:?}"),
+		}
+	}
+
+	fn constant(&mut self, str: &'a str) -> u32 {
+		if let Some(index) = self.const_map.get(str) {
+			return *index;
+		}
+		let index = self.consts.len() as u32;
+		self.consts.push(str);
+		self.const_map.insert(str, index);
+		index
+	}
+
+	pub fn declare(&mut self, name: Sym<'a>, kind: Kind) -> u32 {
+		let func = &mut self.funcs[self.func];
+		let slot = func.locals;
+		func.locals += 1;
+		self.scopes.declare(name, slot, kind);
+		slot
+	}
+
+	pub fn declare_func(&mut self, def: &'a FuncDef<'a>) -> Result<u32> {
+		let (store, funcs) = (self.store, &mut self.funcs);
+		self.scopes.declare_func(def, || {
+			funcs.push(FuncInfo {
+				name: store.str(def.name.as_str()),
+				entry: 0,
+				args: def.args.len() as u32,
+				locals: 0,
+				ret: def.ret,
+			});
+			funcs.len() as u32 - 1
+		})
+	}
+
+	pub fn lookup(&self, name: Sym<'a>) -> Result<(u32, Kind)> {
+		self.scopes.lookup(name)
+	}
+
+	pub fn lookup_func(&self, name: Sym<'a>) -> Result<(u32, &'a FuncDef<'a>)> {
+		self.scopes.lookup_func(name)
+	}
+
+	fn generate_scope(&mut self, code: &'a [Code<'a>]) -> Result<()> {
+		self.scopes.push();
+		let result = self.generate_list(code);
+		self.scopes.pop();
+		result
+	}
+
+	fn generate_list(&mut self, code: &'a [Code<'a>]) -> Result<()> {
+		// functions are visible in the entire scope, so declare them first
+		for def in scope::functions(code) {
+			self.declare_func(def)?;
+		}
+		for it in code.iter() {
+			self.generate_stmt(it)?;
+		}
+		Ok(())
+	}
+
+	fn generate_stmt(&mut self, code: &'a Code<'a>) -> Result<()> {
+		if code.generate_vm(self)? != Kind::Void {
+			self.emit(Op::Pop);
+		}
+		Ok(())
+	}
+
+	fn generate_body(&mut self, code: &'a Code<'a>) -> Result<()> {
+		match code.inner() {
+			Code::Block(list) => self.generate_scope(list),
+			_ => self.generate_scope(std::slice::from_ref(code)),
+		}
+	}
+
+	fn generate_func(&mut self, def: &'a FuncDef<'a>) -> Result<()> {
+		let index = self.declare_func(def)?;
+
+		// functions are compiled inline, so jump over the body
+		let skip = self.emit(Op::Jump(0));
+		self.funcs[index as usize].entry = self.pos();
+
+		let func = std::mem::replace(&mut self.func, index as usize);
+		let loops = std::mem::take(&mut self.loops);
+		self.scopes.push_func();
+
+		let result = (|| -> Result<()> {
+			for arg in def.args.iter() {
+				if arg.kind == Kind::Void || arg.kind == Kind::BigInt {
+					Err(format!("unsupported argument type {:?}", arg.kind))?;
+				}
+				self.declare(arg.name, arg.kind);
+			}
+			self.generate_body(def.body)?;
+			if def.ret == Kind::Void {
+				self.emit(Op::Return);
+			} else {
+				self.emit(Op::MissingReturn);
+			}
+			Ok(())
+		})();
+
+		self.scopes.pop_func();
+		self.loops = loops;
+		self.func = func;
+		result?;
+
+		self.patch(skip);
+		Ok(())
+	}
+
+	fn generate_cond(&mut self, code: &'a Code<'a>) -> Result<()> {
+		match code.generate_vm(self)? {
+			Kind::Bool | Kind::I64 => Ok(()),
+			kind => Err(format!("invalid condition of type {kind:?}"))?,
+		}
+	}
+
+	fn generate_binary(&mut self, op: BinaryOp, lhs: &'a Code<'a>, rhs: &'a Code<'a>) -> Result<Kind> {
+		let lhs_kind = lhs.generate_vm(self)?;
+		if op.is_logic() {
+			// `a && b` is `a ? b : false` and `a || b` is `a ? true : b`
+			let jump = self.emit(Op::JumpIfFalse(0));
+			let rhs_kind = if op == BinaryOp::And {
+				let kind = rhs.generate_vm(self)?;
+				let end = self.emit(Op::Jump(0));
+				self.patch(jump);
+				self.emit(Op::Bool(false));
+				self.patch(end);
+				kind
+			} else {
+				self.emit(Op::Bool(true));
+				let end = self.emit(Op::Jump(0));
+				self.patch(jump);
+				let kind = rhs.generate_vm(self)?;
+				self.patch(end);
+				kind
+			};
+			let Some(kind) = binary_kind(op, lhs_kind, rhs_kind) else {
+				Err(format!("invalid operands for {op:?}: {lhs_kind:?} and {rhs_kind:?}"))?
+			};
+			return Ok(kind);
+		}
+
+		let rhs_kind = rhs.generate_vm(self)?;
+		let Some(kind) = binary_kind(op, lhs_kind, rhs_kind) else {
+			Err(format!("invalid operands for {op:?}: {lhs_kind:?} and {rhs_kind:?}"))?
+		};
+		self.emit(Op::Binary(op));
+		Ok(kind)
+	}
+}
+
+impl<'a> Code<'a> {
+	/// Compile the code, leaving any value on the stack.
+	pub fn generate_vm(&'a self, builder: &mut Builder<'a>) -> Result<Kind> {
+		builder.checker.enter(self)?;
+		let out = self.generate_vm_node(builder);
+		builder.checker.leave();
+		out
+	}
+
+	fn generate_vm_node(&'a self, builder: &mut Builder<'a>) -> Result<Kind> {
+		let kind = match self {
+			Code::Int(v) => {
+				builder.emit(Op::Int(*v));
+				Kind::I64
+			}
+			Code::BigInt(..) => Err("big integers are not supported by the VM")?,
+			Code::Str(v) => {
+				let index = builder.constant(v);
+				builder.emit(Op::Const(index));
+				Kind::Str
+			}
+			Code::Print(args) => {
+				let mut count = 0;
+				for it in args.iter() {
+					if it.generate_vm(builder)? != Kind::Void {
+						count += 1;
+					}
+				}
+				builder.emit(Op::Print(count));
+				Kind::Void
+			}
+			Code::Let(name, value) => {
+				let kind = value.generate_vm(builder)?;
+				if kind == Kind::Void {
+					Err(format!("cannot declare `{}` with a void value", name.as_str()))?;
+				}
+				let slot = builder.declare(*name, kind);
+				builder.emit(Op::Store(slot));
+				Kind::Void
+			}
+			Code::Get(name) => {
+				let (slot, kind) = builder.lookup(*name)?;
+				builder.emit(Op::Load(slot));
+				kind
+			}
+			Code::Set(name, value) => {
+				let (slot, kind) = builder.lookup(*name)?;
+				let value = value.generate_vm(builder)?;
+				if value != kind {
+					Err(format!(
+						"cannot assign {value:?} to `{}` of type {kind:?}",
+						name.as_str()
+					))?;
+				}
+				builder.emit(Op::Store(slot));
+				Kind::Void
+			}
+			Code::Binary(op, lhs, rhs) => builder.generate_binary(*op, lhs, rhs)?,
+			Code::Unary(op, arg) => {
+				let kind = arg.generate_vm(builder)?;
+				let kind = match (op, kind) {
+					(UnaryOp::Neg, Kind::I64) => Kind::I64,
+					(UnaryOp::Not, Kind::Bool | Kind::I64) => Kind::Bool,
+					(UnaryOp::Len | UnaryOp::Chars, Kind::Str) => Kind::I64,
+					(op, kind) => Err(format!("invalid operand for {op:?}: {kind:?}"))?,
+				};
+				builder.emit(Op::Unary(*op));
+				kind
+			}
+			Code::Block(list) => {
+				builder.generate_scope(list)?;
+				Kind::Void
+			}
+			Code::If(cond, then, other) => {
+				builder.generate_cond(cond)?;
+				let jump = builder.emit(Op::JumpIfFalse(0));
+				builder.generate_body(then)?;
+				if let Some(other) = other {
+					let end = builder.emit(Op::Jump(0));
+					builder.patch(jump);
+					builder.generate_body(other)?;
+					builder.patch(end);
+				} else {
+					builder.patch(jump);
+				}
+				Kind::Void
+			}
+			Code::While(cond, body) => {
+				let start = builder.pos();
+				builder.generate_cond(cond)?;
+				let jump = builder.emit(Op::JumpIfFalse(0));
+				builder.loops.push(Loop {
+					start,
+					breaks: Vec::new(),
+				});
+				let result = builder.generate_body(body);
+				let state = builder.loops.pop().unwrap();
+				result?;
+				builder.emit(Op::Jump(start));
+				builder.patch(jump);
+				for it in state.breaks {
+					builder.patch(it);
+				}
+				Kind::Void
+			}
+			Code::Break => {
+				if builder.loops.is_empty() {
+					Err("break outside of a loop")?;
+				}
+				let jump = builder.emit(Op::Jump(0));
+				builder.loops.last_mut().unwrap().breaks.push(jump);
+				Kind::Void
+			}
+			Code::Continue => {
+				let Some(state) = builder.loops.last() else {
+					Err("continue outside of a loop")?
+				};
+				let start = state.start;
+				builder.emit(Op::Jump(start));
+				Kind::Void
+			}
+			Code::Return(value) => {
+				let kind = match value {
+					Some(value) => value.generate_vm(builder)?,
+					None => Kind::Void,
+				};
+				if builder.func == 0 {
+					match kind {
+						Kind::Void => {
+							builder.emit(Op::Int(0));
+						}
+						Kind::I64 => {}
+						kind => Err(format!("cannot return {kind:?} from main"))?,
+					}
+					builder.emit(Op::Exit);
+				} else {
+					let ret = builder.funcs[builder.func].ret;
+					if ret != kind {
+						Err(format!("cannot return {kind:?} from function returning {ret:?}"))?;
+					}
+					builder.emit(Op::Return);
+				}
+				Kind::Void
+			}
+			Code::Func(def) => {
+				builder.generate_func(def)?;
+				Kind::Void
+			}
+			Code::At(_, code) => code.generate_vm(builder)?,
+			Code::Call(name, args) => {
+				let (index, def) = builder.lookup_func(*name)?;
+				if args.len() != def.args.len() {
+					Err(format!(
+						"`{}` expects {} arguments, but got {}",
+						name.as_str(),
+						def.args.len(),
+						args.len()
+					))?;
+				}
+				for (arg, param) in args.iter().zip(def.args.iter()) {
+					let kind = arg.generate_vm(builder)?;
+					if kind != param.kind {
+						Err(format!(
+							"invalid argument `{}` for `{}`: expected {:?}, got {:?}",
+							param.name.as_str(),
+							name.as_str(),
+							param.kind,
+							kind
+						))?;
+					}
+				}
+				builder.emit(Op::Call(index));
+				def.ret
+			}
+		};
+		Ok(kind)
+	}
+}
+
+struct Frame {
+	func: usize,
+	ret: usize,
+	base: usize,
+}
+
+impl<'a> Program<'a> {
+	pub fn code(&self) -> &[Op] {
+		&self.code
+	}
+
+	pub fn consts(&self) -> &[&'a str] {
+		&self.consts
+	}
+
+	pub fn funcs(&self) -> &[FuncInfo<'a>] {
+		&self.funcs
+	}
+
+	/// Run the program and return the captured output with the exit code.
+	pub fn capture(&self) -> Result<(String, i64)> {
+		let mut out = Vec::new();
+		let code = self.run(&mut out)?;
+		Ok((String::from_utf8(out)?, code))
+	}
+
+	/// Run the program writing its output to `out`, and return the exit code.
+	pub fn run<W: Write>(&self, out: &mut W) -> Result<i64> {
+		let mut stack: Vec<Value<'a>> = Vec::new();
+		let mut locals: Vec<Value<'a>> = Vec::new();
+		let mut frames = vec![Frame {
+			func: 0,
+			ret: 0,
+			base: 0,
+		}];
+		locals.resize(self.funcs[0].locals as usize, Value::Int(0));
+
+		let mut pc = 0;
+		loop {
+			let op = self.code[pc];
+			pc += 1;
+
+			let frame = frames.last().unwrap();
+			match op {
+				Op::Int(v) => stack.push(Value::Int(v)),
+				Op::Bool(v) => stack.push(Value::Bool(v)),
+				Op::Const(index) => stack.push(Value::Str(self.consts[index as usize])),
+				Op::Load(slot) => stack.push(locals[frame.base + slot as usize].clone()),
+				Op::Store(slot) => locals[frame.base + slot as usize] = pop(&mut stack)?,
+				Op::Pop => {
+					pop(&mut stack)?;
+				}
+				Op::Binary(op) => {
+					let rhs = pop(&mut stack)?;
+					let lhs = pop(&mut stack)?;
+					stack.push(binary(op, lhs, rhs)?);
+				}
+				Op::Unary(op) => {
+					let arg = pop(&mut stack)?;
+					let value = match (op, &arg) {
+						(UnaryOp::Neg, Value::Int(v)) => Value::Int(v.wrapping_neg()),
+						(UnaryOp::Not, Value::Bool(v)) => Value::Bool(!v),
+						(UnaryOp::Not, Value::Int(v)) => Value::Bool(*v == 0),
+						(UnaryOp::Len, arg) if arg.as_str().is_some() => Value::Int(arg.as_str().unwrap().len() as i64),
+						(UnaryOp::Chars, arg) if arg.as_str().is_some() => {
+							Value::Int(arg.as_str().unwrap().chars().count() as i64)
+						}
+						_ => Err(format!("invalid operand for {op:?}: {arg:?}"))?,
+					};
+					stack.push(value);
+				}
+				Op::Jump(target) => pc = target as usize,
+				Op::JumpIfFalse(target) => match pop(&mut stack)? {
+					Value::Bool(false) | Value::Int(0) => pc = target as usize,
+					Value::Bool(true) | Value::Int(_) => {}
+					value => Err(format!("invalid condition: {value:?}"))?,
+				},
+				Op::Print(count) => {
+					let args = stack.split_off(stack.len() - count as usize);
+					let mut line = String::new();
+					for (n, it) in args.iter().enumerate() {
+						if n > 0 {
+							line.push(' ');
+						}
+						line.push_str(&it.to_string());
+					}
+					line.push('\n');
+					out.write_all(line.as_bytes())?;
+				}
+				Op::Call(index) => {
+					let func = &self.funcs[index as usize];
+					let base = locals.len();
+					locals.resize(base + func.locals as usize, Value::Int(0));
+					let args = stack.split_off(stack.len() - func.args as usize);
+					for (slot, arg) in locals[base..].iter_mut().zip(args) {
+						*slot = arg;
+					}
+					frames.push(Frame {
+						func: index as usize,
+						ret: pc,
+						base,
+					});
+					pc = func.entry as usize;
+				}
+				Op::Return => {
+					let frame = frames.pop().unwrap();
+					locals.truncate(frame.base);
+					pc = frame.ret;
+				}
+				Op::Exit => {
+					out.flush()?;
+					return match pop(&mut stack)? {
+						Value::Int(code) => Ok(code),
+						value => Err(format!("invalid exit code: {value:?}"))?,
+					};
+				}
+				Op::MissingReturn => {
+					let name = self.funcs[frame.func].name;
+					Err(format!("missing return in `{name}`"))?;
+				}
+			}
+		}
+	}
+}
+
+fn pop<'a>(stack: &mut Vec<Value<'a>>) -> Result<Value<'a>> {
+	match stack.pop() {
+		Some(value) => Ok(value),
+		None => Err("stack underflow")?,
+	}
+}
+
+fn binary<'a>(op: BinaryOp, lhs: Value<'a>, rhs: Value<'a>) -> Result<Value<'a>> {
+	let value = match (&lhs, &rhs) {
+		(Value::Int(a), Value::Int(b)) => {
+			let (a, b) = (*a, *b);
+			match op {
+				BinaryOp::Add => Value::Int(a.wrapping_add(b)),
+				BinaryOp::Sub => Value::Int(a.wrapping_sub(b)),
+				BinaryOp::Mul => Value::Int(a.wrapping_mul(b)),
+				BinaryOp::Div | BinaryOp::Mod if b == 0 => Err("division by zero")?,
+				BinaryOp::Div => Value::Int(a.wrapping_div(b)),
+				BinaryOp::Mod => Value::Int(a.wrapping_rem(b)),
+				BinaryOp::Eq => Value::Bool(a == b),
+				BinaryOp::Ne => Value::Bool(a != b),
+				BinaryOp::Lt => Value::Bool(a < b),
+				BinaryOp::Le => Value::Bool(a <= b),
+				BinaryOp::Gt => Value::Bool(a > b),
+				BinaryOp::Ge => Value::Bool(a >= b),
+				BinaryOp::And | BinaryOp::Or => Err(format!("invalid operands for {op:?}: {lhs:?} and {rhs:?}"))?,
+			}
+		}
+		(Value::Bool(a), Value::Bool(b)) if op == BinaryOp::Eq => Value::Bool(a == b),
+		(Value::Bool(a), Value::Bool(b)) if op == BinaryOp::Ne => Value::Bool(a != b),
+		_ => match (lhs.as_str(), rhs.as_str()) {
+			(Some(a), Some(b)) => match op {
+				BinaryOp::Add => {
+					let mut str = String::with_capacity(a.len() + b.len());
+					str.push_str(a);
+					str.push_str(b);
+					Value::String(str.into())
+				}
+				BinaryOp::Eq => Value::Bool(a == b),
+				BinaryOp::Ne => Value::Bool(a != b),
+				BinaryOp::Lt => Value::Bool(a < b),
+				BinaryOp::Le => Value::Bool(a <= b),
+				BinaryOp::Gt => Value::Bool(a > b),
+				BinaryOp::Ge => Value::Bool(a >= b),
+				_ => Err(format!("invalid operands for {op:?}: {lhs:?} and {rhs:?}"))?,
+			},
+			_ => Err(format!("invalid operands for {op:?}: {lhs:?} and {rhs:?}"))?,
+		},
+	};
+	Ok(value)
+}
+
+impl<'a> Display for Program<'a> {
+	fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+		for (n, it) in self.consts.iter().enumerate() {
+			writeln!(f, "const {n}: {it:?}")?;
+		}
+		for (n, func) in self.funcs.iter().enumerate() {
+			writeln!(
+				f,
+				"func {n}: {} @{} (args: {}, locals: {})",
+				func.name, func.entry, func.args, func.locals
+			)?;
+		}
+		for (n, op) in self.code.iter().enumerate() {
+			writeln!(f, "{n:04}  {op:?}")?;
+		}
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn the_answer() -> Result<()> {
+		let store = Store::new();
+		let int = |v| &*store.add(Code::Int(v));
+		let str = |v| &*store.add(Code::Str(store.str(v)));
+		let get = |name| &*store.add(Code::Get(store.sym(name)));
+		let op = |op, a, b| &*store.add(Code::Binary(op, a, b));
+		let code = Code::Block(store.add_list([
+			Code::Let(store.sym("x"), int(10)),
+			Code::Let(
+				store.sym("ans"),
+				op(BinaryOp::Add, op(BinaryOp::Mul, get("x"), int(4)), int(2)),
+			),
+			Code::Print(store.add_list([
+				*str("the answer is"),
+				*get("ans"),
+				*op(BinaryOp::Eq, get("ans"), int(42)),
+				*op(BinaryOp::Add, str("ab"), str("c")),
+				Code::Unary(UnaryOp::Chars, str("日本")),
+				*str("the answer is"),
+			])),
+			Code::Return(Some(int(3))),
+		]));
+
+		let mut builder = Builder::new(&store);
+		code.generate_vm(&mut builder)?;
+		let program = builder.build();
+		assert_eq!(program.consts(), ["the answer is", "ab", "c", "日本"]);
+
+		let (out, status) = program.capture()?;
+		assert_eq!(out, "the answer is 42 true abc 2 the answer is\n");
+		assert_eq!(status, 3);
+		Ok(())
+	}
+
+	#[test]
+	fn same_output_as_c() -> Result<()> {
+		let store = Store::new();
+		let int = |v| &*store.add(Code::Int(v));
+		let get = |name| &*store.add(Code::Get(store.sym(name)));
+		let op = |op, a, b| &*store.add(Code::Binary(op, a, b));
+		let set = |name, value| Code::Set(store.sym(name), value);
+
+		let fib = FuncDef {
+			name: store.sym("fib"),
+			args: store.add_list([Param {
+				name: store.sym("n"),
+				kind: Kind::I64,
+			}]),
+			ret: Kind::I64,
+			body: store.add(Code::Block(store.add_list([
+				Code::If(
+					op(BinaryOp::Lt, get("n"), int(2)),
+					store.add(Code::Return(Some(get("n")))),
+					None,
+				),
+				Code::Return(Some(op(
+					BinaryOp::Add,
+					store.add(Code::Call(
+						store.sym("fib"),
+						store.add_list([*op(BinaryOp::Sub, get("n"), int(1))]),
+					)),
+					store.add(Code::Call(
+						store.sym("fib"),
+						store.add_list([*op(BinaryOp::Sub, get("n"), int(2))]),
+					)),
+				))),
+			]))),
+		};
+
+		let code = store.add(Code::Block(store.add_list([
+			Code::Print(store.add_list([
+				Code::Str(store.str("fib(20) =")),
+				Code::Call(store.sym("fib"), store.add_list([*int(20)])),
+			])),
+			Code::Func(store.add(fib)),
+			Code::Let(store.sym("i"), int(0)),
+			Code::Let(store.sym("s"), store.add(Code::Str(store.str("")))),
+			Code::While(
+				op(BinaryOp::Eq, int(1), int(1)),
+				store.add(Code::Block(store.add_list([
+					set("i", op(BinaryOp::Add, get("i"), int(1))),
+					Code::If(
+						op(BinaryOp::Eq, op(BinaryOp::Mod, get("i"), int(2)), int(0)),
+						store.add(Code::Continue),
+						None,
+					),
+					Code::If(op(BinaryOp::Gt, get("i"), int(9)), store.add(Code::Break), None),
+					set("s", op(BinaryOp::Add, get("s"), store.add(Code::Str(store.str("·"))))),
+				]))),
+			),
+			Code::Print(store.add_list([
+				*get("s"),
+				*get("i"),
+				Code::Unary(UnaryOp::Len, get("s")),
+				*op(
+					BinaryOp::Or,
+					op(BinaryOp::Gt, get("s"), store.add(Code::Str(store.str("a")))),
+					op(BinaryOp::Eq, op(BinaryOp::Div, int(1), int(0)), int(0)),
+				),
+				*op(
+					BinaryOp::And,
+					op(BinaryOp::Eq, get("i"), int(0)),
+					op(BinaryOp::Eq, int(1), int(1)),
+				),
+			])),
+		])));
+
+		let mut builder = Builder::new(&store);
+		code.generate_vm(&mut builder)?;
+		let (vm, _) = builder.build().capture()?;
+
+		let mut builder = clang::Builder::new(&store);
+		let main = code.generate_c(&mut builder)?;
+		let out = builder.build(main).execute()?;
+		assert_eq!(vm, String::from_utf8(out.stdout)?);
+		assert_eq!(vm, "fib(20) = 6765\n····· 11 10 true false\n");
+		Ok(())
+	}
+
+	#[test]
+	fn runtime_errors() -> Result<()> {
+		let store = Store::new();
+		let int = |v| &*store.add(Code::Int(v));
+		let code = Code::Print(store.add_list([Code::Binary(BinaryOp::Div, int(1), int(0))]));
+
+		let mut builder = Builder::new(&store);
+		code.generate_vm(&mut builder)?;
+		let err = builder.build().capture().unwrap_err();
+		assert_eq!(err.to_string(), "division by zero");
+
+		let code = Code::Binary(BinaryOp::Add, int(1), store.add(Code::Str(store.str("x"))));
+		let err = code.generate_vm(&mut Builder::new(&store)).unwrap_err();
+		assert_eq!(err.to_string(), "invalid operands for Add: I64 and Str");
+		Ok(())
+	}
+}